@@ -0,0 +1,30 @@
+//! Compare memory throughput. Run with `cargo bench --bench memory`.
+
+use luvemix_rust::memory::*;
+use luvemix_rust::types::*;
+use std::time::Instant;
+
+fn bench(name: &str, mem: &mut dyn Memory) {
+    const ROUNDS: usize = 64;
+    let start = Instant::now();
+    let mut sum: usize = 0;
+    for round in 0..ROUNDS {
+        for addr in 0..=0xFFFF {
+            mem.write(addr, (addr as usize + round) as Data);
+            sum += mem.read(&addr).unwrap_or(0) as usize;
+        }
+    }
+    let elapsed = start.elapsed();
+    let accesses = (ROUNDS * 2 * 0x10000) as f64;
+    println!(
+        "{:>12}: {:>8.1} M accesses/s (checksum {})",
+        name,
+        accesses / elapsed.as_secs_f64() / 1e6,
+        sum
+    );
+}
+
+fn main() {
+    bench("CheapoMemory", &mut CheapoMemory::new());
+    bench("Ram", &mut Ram::new());
+}
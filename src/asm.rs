@@ -0,0 +1,859 @@
+//! A two-pass assembler for the syntax the disassembler prints.
+//!
+//! Each line holds an optional `label:` and a statement: an instruction,
+//! `.org ADDR`, `.byte` or `.word` with a list of values, or a constant
+//! definition `NAME = VALUE`. `/` separates statements on one line, and
+//! `;` starts a comment. Labels starting with `@` are local to the last
+//! label without one.
+//!
+//! Numbers are decimal, `$` hex, `%` binary or `'c'` characters, and `*` is
+//! the address of the current statement. Expressions combine them with
+//! `+ - * & | ^ << >>`, unary `-` and `~`, and parentheses. A leading `<`
+//! or `>` takes the low or high byte of the whole expression.
+
+use crate::cpu::*;
+use crate::memory::*;
+use crate::types::*;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+/// Assemble `source` and return its bytes, panicking on errors. Code
+/// starts at $0000 unless the source says otherwise with `.org`.
+///
+/// ```
+/// use luvemix_rust::asm;
+/// assert_eq!(asm!("LDA #$42 / STA $0200"), [0xA9, 0x42, 0x8D, 0x00, 0x02]);
+/// ```
+#[macro_export]
+macro_rules! asm {
+    ($variant:expr, $source:expr) => {
+        $crate::asm::assemble($variant, $source)
+            .unwrap_or_else(|err| panic!("{}", err))
+            .to_bytes()
+    };
+    ($source:expr) => {
+        $crate::asm!($crate::cpu::Variant::NMOS, $source)
+    };
+}
+
+/// An error in the source. Lines count from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl std::fmt::Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Bytes assembled to consecutive addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub addr: Address,
+    pub bytes: Vec<Data>,
+}
+
+/// The result of assembling: code and the values of all symbols.
+/// Local labels are named like `outer@inner`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Assembly {
+    pub chunks: Vec<Chunk>,
+    pub symbols: BTreeMap<String, i64>,
+}
+
+impl Assembly {
+    /// The value of a label or constant, as an address.
+    pub fn symbol(&self, name: &str) -> Option<Address> {
+        self.symbols.get(name).map(|val| *val as Address)
+    }
+
+    /// Lowest address assembled to, if any.
+    pub fn start(&self) -> Option<Address> {
+        self.chunks
+            .iter()
+            .filter(|chunk| !chunk.bytes.is_empty())
+            .map(|chunk| chunk.addr)
+            .min()
+    }
+
+    /// Everything from the lowest to the highest address assembled to,
+    /// with gaps filled with zeros.
+    pub fn to_bytes(&self) -> Vec<Data> {
+        let start = match self.start() {
+            Some(start) => start as usize,
+            None => return Vec::new(),
+        };
+        let mut bytes = Vec::new();
+        for chunk in &self.chunks {
+            let offset = chunk.addr as usize - start;
+            let end = offset + chunk.bytes.len();
+            if bytes.len() < end {
+                bytes.resize(end, 0);
+            }
+            bytes[offset..end].copy_from_slice(&chunk.bytes);
+        }
+        bytes
+    }
+
+    /// Write all chunks to memory.
+    pub fn write_to<M: Memory + ?Sized>(&self, mem: &mut M) -> Result<(), MemoryError> {
+        for chunk in &self.chunks {
+            for (i, val) in chunk.bytes.iter().enumerate() {
+                mem.try_write(chunk.addr.wrapping_add(i as Address), *val)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Assemble `source` for the instruction set of `variant`.
+pub fn assemble(variant: Variant, source: &str) -> Result<Assembly, AsmError> {
+    let statements = parse(variant, source)?;
+    let mut asm = Assembler {
+        variant,
+        symbols: BTreeMap::new(),
+        modes: Vec::new(),
+    };
+    asm.first_pass(&statements)?;
+    asm.second_pass(&statements)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinOp {
+    Or,
+    Xor,
+    And,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnOp {
+    Neg,
+    Not,
+    Lo,
+    Hi,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Num(i64),
+    Symbol(String),
+    Pc,
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+/// Operand syntax, before it is matched to an addressing mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operand {
+    None,
+    Acc,
+    Imm(Expr),
+    Addr(Expr),
+    AddrX(Expr),
+    AddrY(Expr),
+    Ind(Expr),
+    IndX(Expr),
+    IndY(Expr),
+    Pair(Expr, Expr),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    Expr(Expr),
+    Text(Vec<Data>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    Label(String),
+    Constant(String, Expr),
+    Org(Expr),
+    Byte(Vec<Item>),
+    Word(Vec<Expr>),
+    Instruction {
+        op: Operation,
+        bit: Data,
+        operand: Operand,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Statement {
+    line: usize,
+    kind: Kind,
+}
+
+fn is_bit_op(op: Operation) -> bool {
+    matches!(
+        op,
+        Operation::RMB | Operation::SMB | Operation::BBR | Operation::BBS
+    )
+}
+
+/// The opcode `variant` uses for an instruction, preferring documented
+/// opcodes over undocumented duplicates.
+fn encode(variant: Variant, op: Operation, mode: AddressingMode, bit: Data) -> Option<Data> {
+    let wanted = Instruction { op, mode };
+    let documented = (0..=255).filter(|opcode| decode(*opcode) == Some(variant.decode(*opcode)));
+    documented.chain(0..=255).find(|opcode| {
+        variant.decode(*opcode) == wanted && (!is_bit_op(op) || opcode >> 4 & 7 == bit)
+    })
+}
+
+/// Look up a mnemonic, like `LDA` or `BBS3`, among the instructions of
+/// `variant`. Returns the operation and its bit number.
+fn mnemonic(variant: Variant, name: &str) -> Option<(Operation, Data)> {
+    let name = name.to_ascii_uppercase();
+    let (base, bit) = match name.as_bytes() {
+        [.., digit @ b'0'..=b'7'] => (&name[..name.len() - 1], digit - b'0'),
+        _ => (name.as_str(), 0),
+    };
+    (0..=255)
+        .map(|opcode| variant.decode(opcode).op)
+        .find_map(|op| {
+            let op_name = format!("{:?}", op);
+            match is_bit_op(op) {
+                true if op_name == base && base != name => Some((op, bit)),
+                false if op_name == name => Some((op, 0)),
+                _ => None,
+            }
+        })
+}
+
+/// Split at `sep` where it is outside quotes and parentheses.
+fn split_top_level(text: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, c) if c == sep && depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Cut off a comment, minding quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, ';') => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Split off a leading symbol name, local or not.
+fn split_ident(text: &str) -> Option<(&str, &str)> {
+    let body = text.strip_prefix('@').unwrap_or(text);
+    if !body.starts_with(is_ident_start) {
+        return None;
+    }
+    let len = text.len() - body.len() + body.find(|c| !is_ident(c)).unwrap_or(body.len());
+    Some(text.split_at(len))
+}
+
+/// Parser for the statements of one line.
+struct LineParser<'a> {
+    variant: Variant,
+    line: usize,
+    scope: &'a mut String,
+}
+
+impl LineParser<'_> {
+    fn error(&self, msg: String) -> AsmError {
+        AsmError {
+            line: self.line,
+            msg,
+        }
+    }
+
+    /// Full name of a symbol: local ones are qualified by their scope.
+    fn qualify(&self, name: &str) -> String {
+        match name.starts_with('@') {
+            true => format!("{}{}", self.scope, name),
+            false => name.to_string(),
+        }
+    }
+
+    fn statement(&mut self, text: &str, out: &mut Vec<Statement>) -> Result<(), AsmError> {
+        let mut text = text.trim();
+        if let Some((name, rest)) = split_ident(text) {
+            if let Some(rest) = rest.trim_start().strip_prefix(':') {
+                if !name.starts_with('@') {
+                    *self.scope = name.to_string();
+                }
+                self.push(out, Kind::Label(self.qualify(name)));
+                text = rest.trim();
+            } else if let Some(value) = rest.trim_start().strip_prefix('=') {
+                let kind = Kind::Constant(self.qualify(name), self.expr(value)?);
+                self.push(out, kind);
+                return Ok(());
+            }
+        }
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let (word, rest) = text.split_at(text.find(char::is_whitespace).unwrap_or(text.len()));
+        let rest = rest.trim();
+        let kind = match word.to_ascii_lowercase().as_str() {
+            ".org" => Kind::Org(self.expr(rest)?),
+            ".byte" => Kind::Byte(
+                self.list(rest)?
+                    .into_iter()
+                    .map(|item| self.item(item))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ".word" => Kind::Word(
+                self.list(rest)?
+                    .into_iter()
+                    .map(|item| self.expr(item))
+                    .collect::<Result<_, _>>()?,
+            ),
+            directive if directive.starts_with('.') => {
+                return Err(self.error(format!("unknown directive {}", word)))
+            }
+            _ => {
+                let (op, bit) = mnemonic(self.variant, word)
+                    .ok_or_else(|| self.error(format!("unknown instruction {}", word)))?;
+                Kind::Instruction {
+                    op,
+                    bit,
+                    operand: self.operand(rest)?,
+                }
+            }
+        };
+        self.push(out, kind);
+        Ok(())
+    }
+
+    fn push(&self, out: &mut Vec<Statement>, kind: Kind) {
+        out.push(Statement {
+            line: self.line,
+            kind,
+        });
+    }
+
+    fn list<'t>(&self, text: &'t str) -> Result<Vec<&'t str>, AsmError> {
+        if text.is_empty() {
+            return Err(self.error("no values given".to_string()));
+        }
+        Ok(split_top_level(text, ','))
+    }
+
+    fn item(&self, text: &str) -> Result<Item, AsmError> {
+        let text = text.trim();
+        match text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+            Some(chars) => Ok(Item::Text(chars.bytes().collect())),
+            None => Ok(Item::Expr(self.expr(text)?)),
+        }
+    }
+
+    fn operand(&self, text: &str) -> Result<Operand, AsmError> {
+        let upper = text.to_ascii_uppercase();
+        if text.is_empty() {
+            return Ok(Operand::None);
+        }
+        if upper == "A" {
+            return Ok(Operand::Acc);
+        }
+        if let Some(value) = text.strip_prefix('#') {
+            return Ok(Operand::Imm(self.expr(value)?));
+        }
+        if let Some(text) = text.strip_prefix('(') {
+            let inner = split_top_level(text, ')');
+            if let [inner, rest] = inner.as_slice() {
+                let upper_inner = inner.trim_end().to_ascii_uppercase();
+                match rest.trim().to_ascii_uppercase().as_str() {
+                    "" if upper_inner.ends_with(",X") => {
+                        let inner = inner.trim_end();
+                        return Ok(Operand::IndX(self.expr(&inner[..inner.len() - 2])?));
+                    }
+                    "" => return Ok(Operand::Ind(self.expr(inner)?)),
+                    ",Y" => return Ok(Operand::IndY(self.expr(inner)?)),
+                    _ => {}
+                }
+            }
+        }
+        match split_top_level(text, ',').as_slice() {
+            [addr] => Ok(Operand::Addr(self.expr(addr)?)),
+            [addr, index] => match index.trim().to_ascii_uppercase().as_str() {
+                "X" => Ok(Operand::AddrX(self.expr(addr)?)),
+                "Y" => Ok(Operand::AddrY(self.expr(addr)?)),
+                _ => Ok(Operand::Pair(self.expr(addr)?, self.expr(index)?)),
+            },
+            _ => Err(self.error(format!("invalid operand {}", text))),
+        }
+    }
+
+    fn expr(&self, text: &str) -> Result<Expr, AsmError> {
+        let mut parser = ExprParser {
+            text: text.trim(),
+            pos: 0,
+            line: self,
+        };
+        let expr = parser.full()?;
+        match parser.rest().is_empty() {
+            true => Ok(expr),
+            false => Err(self.error(format!("unexpected {:?} in expression", parser.rest()))),
+        }
+    }
+}
+
+/// Recursive descent over one expression.
+struct ExprParser<'a, 'l> {
+    text: &'a str,
+    pos: usize,
+    line: &'a LineParser<'l>,
+}
+
+impl ExprParser<'_, '_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_space(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consume `token` if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_space();
+        match self.rest().starts_with(token) {
+            true => {
+                self.pos += token.len();
+                true
+            }
+            false => false,
+        }
+    }
+
+    fn full(&mut self) -> Result<Expr, AsmError> {
+        if self.eat("<") {
+            return Ok(Expr::Unary(UnOp::Lo, Box::new(self.binary(0)?)));
+        }
+        if self.eat(">") {
+            return Ok(Expr::Unary(UnOp::Hi, Box::new(self.binary(0)?)));
+        }
+        self.binary(0)
+    }
+
+    /// Binary operators by precedence, loosest binding first.
+    fn binary(&mut self, level: usize) -> Result<Expr, AsmError> {
+        const LEVELS: &[&[(&str, BinOp)]] = &[
+            &[("|", BinOp::Or)],
+            &[("^", BinOp::Xor)],
+            &[("&", BinOp::And)],
+            &[("<<", BinOp::Shl), (">>", BinOp::Shr)],
+            &[("+", BinOp::Add), ("-", BinOp::Sub)],
+            &[("*", BinOp::Mul)],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        'outer: loop {
+            for (token, op) in LEVELS[level] {
+                if self.eat(token) {
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, AsmError> {
+        if self.eat("-") {
+            return Ok(Expr::Unary(UnOp::Neg, Box::new(self.unary()?)));
+        }
+        if self.eat("~") {
+            return Ok(Expr::Unary(UnOp::Not, Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.full()?;
+            if !self.eat(")") {
+                return Err(self.line.error("missing )".to_string()));
+            }
+            return Ok(expr);
+        }
+        if self.eat("*") {
+            return Ok(Expr::Pc);
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, AsmError> {
+        self.skip_space();
+        let rest = self.rest();
+        if let Some((name, _)) = split_ident(rest) {
+            let symbol = Expr::Symbol(self.line.qualify(name));
+            self.pos += name.len();
+            return Ok(symbol);
+        }
+        if let [b'\'', c, b'\'', ..] = *rest.as_bytes() {
+            self.pos += 3;
+            return Ok(Expr::Num(c as i64));
+        }
+        let (radix, digits) = match rest.as_bytes().first() {
+            Some(b'$') => (16, &rest[1..]),
+            Some(b'%') => (2, &rest[1..]),
+            _ => (10, rest),
+        };
+        let len = digits
+            .find(|c: char| !c.is_digit(radix))
+            .unwrap_or(digits.len());
+        let num = i64::from_str_radix(&digits[..len], radix).map_err(|_| {
+            self.line.error(match rest.is_empty() {
+                true => "missing value".to_string(),
+                false => format!("expected a value at {:?}", rest),
+            })
+        })?;
+        self.pos += rest.len() - digits.len() + len;
+        Ok(Expr::Num(num))
+    }
+}
+
+/// Split the source into statements, naming local labels after their
+/// scope on the way.
+fn parse(variant: Variant, source: &str) -> Result<Vec<Statement>, AsmError> {
+    let mut statements = Vec::new();
+    let mut scope = String::new();
+    for (i, line) in source.lines().enumerate() {
+        let mut parser = LineParser {
+            variant,
+            line: i + 1,
+            scope: &mut scope,
+        };
+        for text in split_top_level(strip_comment(line), '/') {
+            parser.statement(text, &mut statements)?;
+        }
+    }
+    Ok(statements)
+}
+
+struct Assembler {
+    variant: Variant,
+    symbols: BTreeMap<String, i64>,
+    /// Addressing modes chosen in the first pass, one per instruction.
+    /// The second pass sticks to them, so that no addresses move.
+    modes: Vec<AddressingMode>,
+}
+
+impl Assembler {
+    fn eval(&self, expr: &Expr, pc: u32) -> Result<i64, String> {
+        Ok(match expr {
+            Expr::Num(num) => *num,
+            Expr::Pc => pc as i64,
+            Expr::Symbol(name) => *self
+                .symbols
+                .get(name)
+                .ok_or_else(|| format!("undefined symbol {}", name))?,
+            Expr::Unary(op, expr) => {
+                let val = self.eval(expr, pc)?;
+                match op {
+                    UnOp::Neg => val.wrapping_neg(),
+                    UnOp::Not => !val,
+                    UnOp::Lo => val & 0xFF,
+                    UnOp::Hi => val >> 8 & 0xFF,
+                }
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.eval(lhs, pc)?, self.eval(rhs, pc)?);
+                let shift = u32::try_from(rhs).unwrap_or(u32::MAX);
+                match op {
+                    BinOp::Or => lhs | rhs,
+                    BinOp::Xor => lhs ^ rhs,
+                    BinOp::And => lhs & rhs,
+                    BinOp::Shl => lhs.checked_shl(shift).unwrap_or(0),
+                    BinOp::Shr => lhs.checked_shr(shift).unwrap_or(0),
+                    BinOp::Add => lhs.wrapping_add(rhs),
+                    BinOp::Sub => lhs.wrapping_sub(rhs),
+                    BinOp::Mul => lhs.wrapping_mul(rhs),
+                }
+            }
+        })
+    }
+
+    fn has(&self, op: Operation, mode: AddressingMode, bit: Data) -> bool {
+        encode(self.variant, op, mode, bit).is_some()
+    }
+
+    /// Pick the addressing mode for an operand. Zero page modes are only
+    /// used for values known to fit, as forward references might not.
+    fn choose_mode(
+        &self,
+        op: Operation,
+        bit: Data,
+        operand: &Operand,
+        pc: u32,
+    ) -> Result<AddressingMode, String> {
+        use AddressingMode::*;
+        let has = |mode| self.has(op, mode, bit);
+        let zero_page = |expr: &Expr| {
+            self.eval(expr, pc)
+                .is_ok_and(|val| (0..=0xFF).contains(&val))
+        };
+        let pick = |modes: &[(AddressingMode, bool)]| {
+            modes
+                .iter()
+                .find(|(mode, ok)| *ok && has(*mode))
+                .map(|(mode, _)| *mode)
+        };
+        let mode = match operand {
+            Operand::None => pick(&[(IMP, true), (ACC, true)]),
+            Operand::Acc => pick(&[(ACC, true)]),
+            Operand::Imm(_) => pick(&[(IMM, true)]),
+            Operand::Addr(expr) => pick(&[
+                (REL, true),
+                (ZPG, zero_page(expr)),
+                (ABS, true),
+                (ZPG, true),
+            ]),
+            Operand::AddrX(expr) => pick(&[(ZPX, zero_page(expr)), (ABX, true), (ZPX, true)]),
+            Operand::AddrY(expr) => pick(&[(ZPY, zero_page(expr)), (ABY, true), (ZPY, true)]),
+            Operand::Ind(_) => pick(&[(IND, true), (IZP, true)]),
+            Operand::IndX(_) => pick(&[(IZX, true), (IAX, true)]),
+            Operand::IndY(_) => pick(&[(IZY, true)]),
+            Operand::Pair(_, _) => pick(&[(ZPR, true)]),
+        };
+        mode.ok_or_else(|| format!("{:?} does not take this operand", op))
+    }
+
+    fn define(&mut self, name: &str, val: i64) -> Result<(), String> {
+        match self.symbols.insert(name.to_string(), val) {
+            Some(_) => Err(format!("{} defined twice", name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Find the address of every label, and the value of every constant.
+    fn first_pass(&mut self, statements: &[Statement]) -> Result<(), AsmError> {
+        let mut pc: u32 = 0;
+        let mut pending = Vec::new();
+        for statement in statements {
+            let error = |msg| AsmError {
+                line: statement.line,
+                msg,
+            };
+            match &statement.kind {
+                Kind::Label(name) => self.define(name, pc as i64).map_err(error)?,
+                Kind::Constant(name, expr) => match self.eval(expr, pc) {
+                    Ok(val) => self.define(name, val).map_err(error)?,
+                    Err(_) => pending.push((statement.line, name, expr, pc)),
+                },
+                Kind::Org(expr) => pc = org(self.eval(expr, pc).map_err(error)?).map_err(error)?,
+                Kind::Byte(items) => {
+                    pc += items
+                        .iter()
+                        .map(|item| match item {
+                            Item::Expr(_) => 1,
+                            Item::Text(text) => text.len() as u32,
+                        })
+                        .sum::<u32>()
+                }
+                Kind::Word(exprs) => pc += 2 * exprs.len() as u32,
+                Kind::Instruction { op, bit, operand } => {
+                    let mode = self.choose_mode(*op, *bit, operand, pc).map_err(error)?;
+                    self.modes.push(mode);
+                    pc += 1 + mode.operand_len() as u32;
+                }
+            }
+            if pc > 0x10000 {
+                return Err(error("code beyond $FFFF".to_string()));
+            }
+        }
+
+        // Constants referring to later ones, until no more can be resolved.
+        while !pending.is_empty() {
+            let count = pending.len();
+            let mut unresolved = Vec::new();
+            for (line, name, expr, pc) in pending {
+                match self.eval(expr, pc) {
+                    Ok(val) => self
+                        .define(name, val)
+                        .map_err(|msg| AsmError { line, msg })?,
+                    Err(_) => unresolved.push((line, name, expr, pc)),
+                }
+            }
+            if let Some((line, _, expr, pc)) = unresolved.first() {
+                if unresolved.len() == count {
+                    let msg = self.eval(expr, *pc).unwrap_err();
+                    return Err(AsmError { line: *line, msg });
+                }
+            }
+            pending = unresolved;
+        }
+        Ok(())
+    }
+
+    /// Emit the code, now that all labels are known.
+    fn second_pass(mut self, statements: &[Statement]) -> Result<Assembly, AsmError> {
+        let mut chunks = vec![Chunk {
+            addr: 0,
+            bytes: Vec::new(),
+        }];
+        let mut modes = std::mem::take(&mut self.modes).into_iter();
+        let mut pc: u32 = 0;
+        for statement in statements {
+            let error = |msg| AsmError {
+                line: statement.line,
+                msg,
+            };
+            let mut bytes = Vec::new();
+            match &statement.kind {
+                Kind::Label(_) => {}
+                Kind::Constant(_, _) => {}
+                Kind::Org(expr) => {
+                    pc = org(self.eval(expr, pc).map_err(error)?).map_err(error)?;
+                    match chunks.last_mut() {
+                        Some(chunk) if chunk.bytes.is_empty() => chunk.addr = pc as Address,
+                        _ => chunks.push(Chunk {
+                            addr: pc as Address,
+                            bytes: Vec::new(),
+                        }),
+                    }
+                }
+                Kind::Byte(items) => {
+                    for item in items {
+                        match item {
+                            Item::Expr(expr) => bytes
+                                .push(byte(self.eval(expr, pc).map_err(error)?).map_err(error)?),
+                            Item::Text(text) => bytes.extend(text),
+                        }
+                    }
+                }
+                Kind::Word(exprs) => {
+                    for expr in exprs {
+                        let val = word(self.eval(expr, pc).map_err(error)?).map_err(error)?;
+                        bytes.extend(val.to_le_bytes());
+                    }
+                }
+                Kind::Instruction { op, bit, operand } => {
+                    let mode = modes.next().expect("a mode for every instruction");
+                    bytes = self
+                        .instruction(*op, *bit, mode, operand, pc)
+                        .map_err(error)?;
+                }
+            }
+            pc += bytes.len() as u32;
+            chunks
+                .last_mut()
+                .expect("at least one chunk")
+                .bytes
+                .extend(bytes);
+        }
+        chunks.retain(|chunk| !chunk.bytes.is_empty());
+        Ok(Assembly {
+            chunks,
+            symbols: self.symbols,
+        })
+    }
+
+    fn instruction(
+        &self,
+        op: Operation,
+        bit: Data,
+        mode: AddressingMode,
+        operand: &Operand,
+        pc: u32,
+    ) -> Result<Vec<Data>, String> {
+        use AddressingMode::*;
+        let opcode = encode(self.variant, op, mode, bit).expect("mode chosen from the opcodes");
+        let branch = |expr: &Expr, size: u32| -> Result<Data, String> {
+            let offset = match self.eval(expr, pc)?.checked_sub((pc + size) as i64) {
+                Some(offset) => offset,
+                None => return Err("branch target out of range".to_string()),
+            };
+            match i8::try_from(offset) {
+                Ok(offset) => Ok(offset as Data),
+                Err(_) => Err(format!("branch target {} bytes away", offset)),
+            }
+        };
+        let zero_page = |expr: &Expr| -> Result<Data, String> {
+            match self.eval(expr, pc)? {
+                val @ 0..=0xFF => Ok(val as Data),
+                val => Err(format!("${:X} is not in zero page", val)),
+            }
+        };
+        let operands = match (mode, operand) {
+            (IMP, _) | (ACC, _) => vec![],
+            (IMM, Operand::Imm(expr)) => vec![byte(self.eval(expr, pc)?)?],
+            (REL, Operand::Addr(target)) => vec![branch(target, 2)?],
+            (ZPR, Operand::Pair(addr, target)) => vec![zero_page(addr)?, branch(target, 3)?],
+            (ZPG, Operand::Addr(expr))
+            | (ZPX, Operand::AddrX(expr))
+            | (ZPY, Operand::AddrY(expr))
+            | (IZX, Operand::IndX(expr))
+            | (IZY, Operand::IndY(expr))
+            | (IZP, Operand::Ind(expr)) => vec![zero_page(expr)?],
+            (_, Operand::Addr(expr))
+            | (_, Operand::AddrX(expr))
+            | (_, Operand::AddrY(expr))
+            | (_, Operand::Ind(expr))
+            | (_, Operand::IndX(expr)) => word(self.eval(expr, pc)?)?.to_le_bytes().to_vec(),
+            _ => unreachable!("{:?} chosen for {:?}", mode, operand),
+        };
+        Ok(std::iter::once(opcode).chain(operands).collect())
+    }
+}
+
+fn org(val: i64) -> Result<u32, String> {
+    match val {
+        0..=0xFFFF => Ok(val as u32),
+        _ => Err(format!("${:X} is not an address", val)),
+    }
+}
+
+/// A byte value, signed or not.
+fn byte(val: i64) -> Result<Data, String> {
+    match val {
+        -0x80..=0xFF => Ok(val as Data),
+        _ => Err(format!("{} does not fit in a byte", val)),
+    }
+}
+
+/// A word value, signed or not.
+fn word(val: i64) -> Result<Word, String> {
+    match val {
+        -0x8000..=0xFFFF => Ok(val as Word),
+        _ => Err(format!("{} does not fit in a word", val)),
+    }
+}
@@ -0,0 +1,444 @@
+//! Breakpoints and watchpoints, checked by `Cpu` as it steps.
+
+use crate::cpu::*;
+use crate::memory::*;
+use crate::types::*;
+use std::collections::BTreeMap;
+
+/// What stopped the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hit {
+    /// An instruction ended with PC at a breakpoint whose condition, if
+    /// any, holds.
+    Breakpoint(Address),
+    /// A bus cycle accessed a watched address. The instruction doing so
+    /// was completed.
+    Watchpoint {
+        addr: Address,
+        mode: BusMode,
+        data: Data,
+    },
+}
+
+impl std::fmt::Display for Hit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Hit::Breakpoint(addr) => write!(f, "breakpoint at ${:04X}", addr),
+            Hit::Watchpoint {
+                addr,
+                mode: BusMode::READ,
+                data,
+            } => write!(f, "read of ${:02X} from ${:04X}", data, addr),
+            Hit::Watchpoint {
+                addr,
+                mode: BusMode::WRITE,
+                data,
+            } => write!(f, "write of ${:02X} to ${:04X}", data, addr),
+        }
+    }
+}
+
+/// Bus cycles a watchpoint reacts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    READ,
+    WRITE,
+    ANY,
+}
+
+/// Watches the addresses from `start` to `end`, inclusive. All bus cycles
+/// count, including opcode fetches and dummy accesses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: Address,
+    pub end: Address,
+    pub access: Access,
+}
+
+impl Watchpoint {
+    pub fn new(start: Address, end: Address, access: Access) -> Self {
+        Watchpoint { start, end, access }
+    }
+
+    fn matches(&self, addr: Address, mode: BusMode) -> bool {
+        let access = matches!(
+            (self.access, mode),
+            (Access::ANY, _) | (Access::READ, BusMode::READ) | (Access::WRITE, BusMode::WRITE)
+        );
+        access && (self.start..=self.end).contains(&addr)
+    }
+}
+
+/// All breakpoints and watchpoints of a `Cpu`. Checking them costs next
+/// to nothing while there are none.
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints {
+    breakpoints: BTreeMap<Address, Option<Condition>>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.breakpoints.is_empty() && self.watchpoints.is_empty()
+    }
+
+    /// Break at `addr`, replacing any condition it had.
+    pub fn add(&mut self, addr: Address) {
+        self.breakpoints.insert(addr, None);
+    }
+
+    /// Break at `addr` only when `condition` holds.
+    pub fn add_if(&mut self, addr: Address, condition: Condition) {
+        self.breakpoints.insert(addr, Some(condition));
+    }
+
+    /// Remove the breakpoint at `addr`. Returns whether there was one.
+    pub fn remove(&mut self, addr: Address) -> bool {
+        self.breakpoints.remove(&addr).is_some()
+    }
+
+    /// Breakpoints by address, with their conditions.
+    pub fn breakpoints(&self) -> impl Iterator<Item = (Address, Option<&Condition>)> {
+        self.breakpoints
+            .iter()
+            .map(|(addr, condition)| (*addr, condition.as_ref()))
+    }
+
+    pub fn watch(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Remove watchpoints on exactly this range. Returns whether there
+    /// were any.
+    pub fn unwatch(&mut self, start: Address, end: Address) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| (watchpoint.start, watchpoint.end) != (start, end));
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    pub(crate) fn is_watching(&self) -> bool {
+        !self.watchpoints.is_empty()
+    }
+
+    /// Check a bus cycle against the watchpoints.
+    pub(crate) fn check_bus(&self, addr: Address, mode: BusMode, data: Data) -> Option<Hit> {
+        self.watchpoints
+            .iter()
+            .any(|watchpoint| watchpoint.matches(addr, mode))
+            .then_some(Hit::Watchpoint { addr, mode, data })
+    }
+
+    /// Check the breakpoint at PC, if there is one.
+    pub(crate) fn check_pc<M: Memory + ?Sized>(&self, cpu: &Cpu, mem: &M) -> Option<Hit> {
+        let pc = cpu.state().pc;
+        match self.breakpoints.get(&pc)? {
+            Some(condition) if !condition.eval(cpu, mem) => None,
+            _ => Some(Hit::Breakpoint(pc)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Register {
+    A,
+    X,
+    Y,
+    SP,
+    PC,
+    P,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Num(i64),
+    Register(Register),
+    /// A status flag, by its mask.
+    Flag(Flags),
+    /// The byte at an address.
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+/// A condition on registers, flags and memory, like
+/// `A == $42 && !Z || [$0200] >= 10`.
+///
+/// Registers are `A`, `X`, `Y`, `SP`, `PC` and `P`, flags `C`, `Z`, `I`,
+/// `D`, `V` and `N`, and `[ADDR]` is the byte at an address. Numbers are
+/// decimal, `$` hex or `%` binary. Values combine with `+ - & | ^`,
+/// compare with `== != < <= > >=`, and join with `! && ||`. Anything
+/// nonzero is true.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    text: String,
+    expr: Expr,
+}
+
+impl Condition {
+    /// Whether the condition holds. Memory is peeked at, so reading it
+    /// has no side effects; addresses nothing responds to read as zero.
+    pub fn eval<M: Memory + ?Sized>(&self, cpu: &Cpu, mem: &M) -> bool {
+        eval(&self.expr, cpu, mem) != 0
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl std::str::FromStr for Condition {
+    type Err = EmuError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let error =
+            |msg: String| EmuError::Config(format!("invalid condition {:?}: {}", text, msg));
+        let mut parser = Parser {
+            tokens: tokenize(text).map_err(error)?,
+            pos: 0,
+        };
+        let expr = parser.expr().map_err(error)?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(error(format!("unexpected {}", token)));
+        }
+        Ok(Condition {
+            text: text.trim().to_string(),
+            expr,
+        })
+    }
+}
+
+fn eval<M: Memory + ?Sized>(expr: &Expr, cpu: &Cpu, mem: &M) -> i64 {
+    let state = cpu.state();
+    match expr {
+        Expr::Num(num) => *num,
+        Expr::Register(reg) => match reg {
+            Register::A => state.a as i64,
+            Register::X => state.x as i64,
+            Register::Y => state.y as i64,
+            Register::SP => state.sp as i64,
+            Register::PC => state.pc as i64,
+            Register::P => state.sr() as i64,
+        },
+        Expr::Flag(mask) => (state.sr() & mask != 0) as i64,
+        Expr::Memory(addr) => mem.read(&(eval(addr, cpu, mem) as Address)).unwrap_or(0) as i64,
+        Expr::Not(expr) => (eval(expr, cpu, mem) == 0) as i64,
+        Expr::Neg(expr) => eval(expr, cpu, mem).wrapping_neg(),
+        Expr::Binary(BinOp::Or, lhs, rhs) => {
+            (eval(lhs, cpu, mem) != 0 || eval(rhs, cpu, mem) != 0) as i64
+        }
+        Expr::Binary(BinOp::And, lhs, rhs) => {
+            (eval(lhs, cpu, mem) != 0 && eval(rhs, cpu, mem) != 0) as i64
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (eval(lhs, cpu, mem), eval(rhs, cpu, mem));
+            match op {
+                BinOp::Eq => (lhs == rhs) as i64,
+                BinOp::Ne => (lhs != rhs) as i64,
+                BinOp::Lt => (lhs < rhs) as i64,
+                BinOp::Le => (lhs <= rhs) as i64,
+                BinOp::Gt => (lhs > rhs) as i64,
+                BinOp::Ge => (lhs >= rhs) as i64,
+                BinOp::BitOr => lhs | rhs,
+                BinOp::BitXor => lhs ^ rhs,
+                BinOp::BitAnd => lhs & rhs,
+                BinOp::Add => lhs.wrapping_add(rhs),
+                BinOp::Sub => lhs.wrapping_sub(rhs),
+                BinOp::Or | BinOp::And => unreachable!("short-circuited above"),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Num(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Num(num) => write!(f, "{}", num),
+            Token::Name(name) => f.write_str(name),
+            Token::Symbol(symbol) => f.write_str(symbol),
+        }
+    }
+}
+
+/// Symbols, longest first so that `<=` is not taken for `<`.
+const SYMBOLS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "&", "|", "^", "+", "-", "(", ")", "[", "]",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let (radix, digits) = match rest.as_bytes()[0] {
+            b'$' => (16, &rest[1..]),
+            b'%' => (2, &rest[1..]),
+            _ => (10, rest),
+        };
+        if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else if rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let n = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..n].to_ascii_uppercase()));
+            rest = &rest[n..];
+        } else if digits.starts_with(|c: char| c.is_digit(radix)) {
+            let n = digits.chars().take_while(|c| c.is_digit(radix)).count();
+            let num = i64::from_str_radix(&digits[..n], radix)
+                .map_err(|_| format!("number too large: {}", &digits[..n]))?;
+            tokens.push(Token::Num(num));
+            rest = &digits[n..];
+        } else {
+            return Err(format!("unexpected {:?}", rest.chars().next().unwrap()));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over condition tokens.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    /// Consume one of the symbols if it comes next.
+    fn eat(&mut self, symbols: &[&'static str]) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Symbol(symbol)) if symbols.contains(symbol) => {
+                self.pos += 1;
+                Some(symbol)
+            }
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        self.binary(0)
+    }
+
+    /// Binary operators by precedence, loosest binding first.
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        const LEVELS: &[&[(&str, BinOp)]] = &[
+            &[("||", BinOp::Or)],
+            &[("&&", BinOp::And)],
+            &[
+                ("==", BinOp::Eq),
+                ("!=", BinOp::Ne),
+                ("<=", BinOp::Le),
+                (">=", BinOp::Ge),
+                ("<", BinOp::Lt),
+                (">", BinOp::Gt),
+            ],
+            &[
+                ("|", BinOp::BitOr),
+                ("^", BinOp::BitXor),
+                ("&", BinOp::BitAnd),
+            ],
+            &[("+", BinOp::Add), ("-", BinOp::Sub)],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let symbols: Vec<&'static str> = LEVELS[level].iter().map(|(symbol, _)| *symbol).collect();
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(symbol) = self.eat(&symbols) {
+            let (_, op) = LEVELS[level]
+                .iter()
+                .find(|(s, _)| *s == symbol)
+                .expect("symbol of this level");
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat(&["!"]).is_some() {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(&["-"]).is_some() {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.eat(&["("]).is_some() {
+            let expr = self.expr()?;
+            self.eat(&[")"]).ok_or("missing )")?;
+            return Ok(expr);
+        }
+        if self.eat(&["["]).is_some() {
+            let expr = self.expr()?;
+            self.eat(&["]"]).ok_or("missing ]")?;
+            return Ok(Expr::Memory(Box::new(expr)));
+        }
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Num(num)) => Ok(Expr::Num(num)),
+            Some(Token::Name(name)) => name_expr(&name),
+            Some(token) => Err(format!("unexpected {}", token)),
+            None => Err("missing value".to_string()),
+        }
+    }
+}
+
+fn name_expr(name: &str) -> Result<Expr, String> {
+    let flag = |flag: Flag| Ok(Expr::Flag(flag.to_mask()));
+    let register = |reg| Ok(Expr::Register(reg));
+    match name {
+        "A" => register(Register::A),
+        "X" => register(Register::X),
+        "Y" => register(Register::Y),
+        "SP" => register(Register::SP),
+        "PC" => register(Register::PC),
+        "P" => register(Register::P),
+        "C" => flag(Flag::CRY),
+        "Z" => flag(Flag::ZRO),
+        "I" => flag(Flag::INT),
+        "D" => flag(Flag::DEC),
+        "V" => flag(Flag::OVF),
+        "N" => flag(Flag::NEG),
+        _ => Err(format!("unknown register or flag {}", name)),
+    }
+}
@@ -0,0 +1,187 @@
+use crate::cpu::*;
+use crate::memory::*;
+use crate::types::*;
+
+/// Handle for a device attached to a `Bus`.
+pub type DeviceId = usize;
+
+/// Where a device appears in the address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    /// First address of the range.
+    pub start: Address,
+
+    /// Last address of the range, inclusive.
+    pub end: Address,
+
+    /// Mask applied to the offset into the range before it is passed
+    /// to the device. Masking off high bits mirrors the device
+    /// throughout the range.
+    pub mask: Address,
+
+    /// Mappings with higher priority win where ranges overlap. Among
+    /// equal priorities, the last one mapped wins.
+    pub priority: i32,
+}
+
+impl Mapping {
+    pub fn new(start: Address, end: Address) -> Mapping {
+        Mapping {
+            start,
+            end,
+            mask: 0xFFFF,
+            priority: 0,
+        }
+    }
+
+    fn contains(&self, addr: Address) -> bool {
+        self.start <= addr && addr <= self.end
+    }
+}
+
+/// System bus decoding addresses to memory-mapped devices.
+/// Reads from addresses without a device return `None`. Any attached
+/// device can assert the interrupt lines, mapped or not.
+#[derive(Default)]
+pub struct Bus {
+    devices: Vec<Option<Box<dyn Memory>>>,
+    /// Sorted by descending priority, most recent first.
+    mappings: Vec<(Mapping, DeviceId)>,
+
+    /// Report accesses to unmapped addresses as errors, instead of
+    /// letting the CPU see open bus and dropping writes.
+    pub strict: bool,
+}
+
+impl Bus {
+    pub fn new() -> Bus {
+        Bus {
+            devices: Vec::new(),
+            mappings: Vec::new(),
+            strict: false,
+        }
+    }
+
+    /// Add a device to the bus. It is not visible until it gets mapped.
+    pub fn attach<M: Memory + 'static>(&mut self, device: M) -> DeviceId {
+        self.devices.push(Some(Box::new(device)));
+        self.devices.len() - 1
+    }
+
+    /// Remove a device and all its mappings from the bus.
+    pub fn detach(&mut self, id: DeviceId) -> Option<Box<dyn Memory>> {
+        self.unmap(id);
+        self.devices.get_mut(id).and_then(Option::take)
+    }
+
+    /// Make a device appear in an address range. A device can be
+    /// mapped any number of times, e.g. to mirror it.
+    pub fn map(&mut self, id: DeviceId, mapping: Mapping) {
+        assert!(mapping.start <= mapping.end, "empty mapping {:?}", mapping);
+        assert!(
+            matches!(self.devices.get(id), Some(Some(_))),
+            "no device {}",
+            id
+        );
+        let pos = self
+            .mappings
+            .iter()
+            .position(|(m, _)| m.priority <= mapping.priority)
+            .unwrap_or(self.mappings.len());
+        self.mappings.insert(pos, (mapping, id));
+    }
+
+    /// Remove all mappings of a device, keeping it attached.
+    pub fn unmap(&mut self, id: DeviceId) {
+        self.mappings.retain(|(_, device)| *device != id);
+    }
+
+    /// Attach a device and map it in one go.
+    pub fn attach_at<M: Memory + 'static>(&mut self, device: M, mapping: Mapping) -> DeviceId {
+        let id = self.attach(device);
+        self.map(id, mapping);
+        id
+    }
+
+    /// Find the device responding to an address, and the address it sees.
+    pub fn decode(&self, addr: Address) -> Option<(DeviceId, Address)> {
+        self.mappings
+            .iter()
+            .find(|(m, _)| m.contains(addr))
+            .map(|(m, id)| (*id, (addr - m.start) & m.mask))
+    }
+
+    fn attached(&self) -> impl Iterator<Item = &dyn Memory> {
+        self.devices.iter().flatten().map(|device| device.as_ref())
+    }
+
+    /// Run one CPU cycle, letting the addressed device respond to it.
+    /// If the device refuses the access, the cycle still completes.
+    pub fn cycle(&mut self, cpu: &mut Cpu) -> Result<(), MemoryError> {
+        cpu.cycle(self)
+    }
+}
+
+impl Memory for Bus {
+    fn read(&self, addr: &Address) -> Option<Data> {
+        let (id, addr) = self.decode(*addr)?;
+        self.devices[id].as_ref()?.read(&addr)
+    }
+
+    fn write(&mut self, addr: Address, val: Data) {
+        let _ = self.try_write(addr, val);
+    }
+
+    /// Errors report the address on the bus rather than the one the
+    /// device saw.
+    fn try_write(&mut self, addr: Address, val: Data) -> Result<(), MemoryError> {
+        let unmapped = if self.strict {
+            Err(MemoryError::Unmapped(addr))
+        } else {
+            Ok(())
+        };
+        let (id, device_addr) = match self.decode(addr) {
+            Some(decoded) => decoded,
+            None => return unmapped,
+        };
+        match self.devices[id].as_mut() {
+            Some(device) => device
+                .try_write(device_addr, val)
+                .map_err(|err| err.at(addr)),
+            None => unmapped,
+        }
+    }
+
+    fn try_read(&self, addr: &Address) -> Result<Option<Data>, MemoryError> {
+        let val = match self.decode(*addr) {
+            Some((id, device_addr)) => match self.devices[id].as_ref() {
+                Some(device) => device.try_read(&device_addr).map_err(|err| err.at(*addr))?,
+                None => None,
+            },
+            None => None,
+        };
+        match val {
+            // Nobody drives the data bus, so it keeps its last value.
+            None if self.strict => Err(MemoryError::Unmapped(*addr)),
+            val => Ok(val),
+        }
+    }
+
+    /// The interrupt lines are wired-OR: any device can pull them.
+    fn irq(&self) -> bool {
+        self.attached().any(|device| device.irq())
+    }
+
+    fn nmi(&self) -> bool {
+        self.attached().any(|device| device.nmi())
+    }
+}
+
+impl std::fmt::Debug for Bus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Bus")
+            .field("mappings", &self.mappings)
+            .field("strict", &self.strict)
+            .finish()
+    }
+}
@@ -0,0 +1,2173 @@
+use crate::breakpoints::*;
+use crate::loader::LoadError;
+use crate::memory::*;
+use crate::types::*;
+
+#[derive(Clone, Copy)]
+pub enum Flag {
+    /// Carry
+    CRY = 0b0000_0001,
+
+    /// Zero
+    ZRO = 0b0000_0010,
+
+    /// Interrupt Disable
+    INT = 0b0000_0100,
+
+    /// Decimal Mode
+    DEC = 0b0000_1000,
+
+    /// Break: not stored in the register, only set in copies of it
+    /// pushed by BRK and PHP
+    BRK = 0b0001_0000,
+
+    /// Reserved: always set
+    RSV = 0b0010_0000,
+
+    /// Overflow
+    OVF = 0b0100_0000,
+
+    /// Negative
+    NEG = 0b1000_0000,
+}
+
+impl Flag {
+    pub fn to_mask(self) -> Flags {
+        self as Flags
+    }
+}
+
+/// Sources and destinations when transferring data between registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    ACC,
+    X,
+    Y,
+    SP,
+    MDR,
+}
+
+#[derive(Debug)]
+pub struct CpuState {
+    /// Program Counter
+    pub pc: Address,
+
+    /// Stack Pointer, an offset into page $01
+    pub sp: Data,
+
+    /// Accumulator Register
+    pub a: Data,
+
+    /// X Index Register
+    pub x: Data,
+
+    /// Y Index Register
+    pub y: Data,
+
+    // Status Register, accessed via `sr()` and `set_sr()`
+    sr: Flags,
+
+    // Instruction Register
+    pub ir: Data,
+
+    // Memory Address Register
+    pub mar: Address,
+
+    // Memory Data Register
+    pub mdr: Data,
+}
+
+impl CpuState {
+    pub fn new() -> CpuState {
+        CpuState {
+            sr: Flag::RSV.to_mask(),
+            pc: 0,
+            sp: 0,
+            a: 0,
+            x: 0,
+            y: 0,
+            ir: 0,
+            mar: 0,
+            mdr: 0,
+        }
+    }
+
+    pub fn get_flag(&self, flag: Flag) -> bool {
+        self.sr & flag.to_mask() != 0
+    }
+
+    pub fn set_flag(&mut self, flag: Flag, val: bool) {
+        let mask = flag.to_mask();
+        let flag_val = if val { self.sr | mask } else { self.sr & !mask };
+        self.set_sr(flag_val);
+    }
+
+    /// The status register as it would be pushed by an interrupt.
+    pub fn sr(&self) -> Flags {
+        self.sr
+    }
+
+    /// Restore the status register, like RTI or PLP do.
+    /// The break bit is ignored and the reserved bit stays set.
+    pub fn set_sr(&mut self, val: Flags) {
+        self.sr = val & !Flag::BRK.to_mask() | Flag::RSV.to_mask();
+    }
+
+    /// Address the next push writes to.
+    pub fn stack_addr(&self) -> Address {
+        STACK_PAGE | self.sp as Address
+    }
+
+    /// Address the next pull reads from. Like pushes, it wraps around
+    /// within page $01.
+    pub fn stack_top(&self) -> Address {
+        STACK_PAGE | self.sp.wrapping_add(1) as Address
+    }
+
+    pub fn set_flags_from_val(&mut self, val: Data) {
+        self.set_flag(Flag::ZRO, val == 0);
+        self.set_flag(Flag::NEG, (val >> (DATA_WIDTH - 1)) > 0);
+    }
+
+    /// Transfer values between registers via implied internal bus.
+    /// Setting A, X or Y updates the N and Z flags, while the stack
+    /// pointer and MDR leave them alone.
+    pub fn transfer(&mut self, src: Target, dst: Target) {
+        let val: Byte = match src {
+            Target::ACC => self.a,
+            Target::X => self.x,
+            Target::Y => self.y,
+            Target::SP => self.sp,
+            Target::MDR => self.mdr,
+        };
+        match dst {
+            Target::ACC => self.a = val,
+            Target::X => self.x = val,
+            Target::Y => self.y = val,
+            Target::SP => self.sp = val,
+            Target::MDR => self.mdr = val,
+        }
+        if matches!(dst, Target::ACC | Target::X | Target::Y) {
+            self.set_flags_from_val(val);
+        }
+    }
+}
+
+impl Default for CpuState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Registers on one line, like `PC=0600 A=2A X=00 Y=00 SP=FF P=nv-bdIzc`.
+/// Flags that are set are shown in upper case.
+impl std::fmt::Display for CpuState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let flags: String = "NV-BDIZC"
+            .chars()
+            .enumerate()
+            .map(|(i, name)| match self.sr >> (7 - i) & 1 {
+                1 => name,
+                _ => name.to_ascii_lowercase(),
+            })
+            .collect();
+        write!(
+            f,
+            "PC={:04X} A={:02X} X={:02X} Y={:02X} SP={:02X} P={}",
+            self.pc, self.a, self.x, self.y, self.sp, flags
+        )
+    }
+}
+
+/// Instruction mnemonics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    /// Add Memory to Accumulator with Carry
+    ADC,
+    /// AND Memory with Accumulator
+    AND,
+    /// Shift Left One Bit
+    ASL,
+    /// Branch on Carry Clear
+    BCC,
+    /// Branch on Carry Set
+    BCS,
+    /// Branch on Result Zero
+    BEQ,
+    /// Test Bits in Memory with Accumulator
+    BIT,
+    /// Branch on Result Minus
+    BMI,
+    /// Branch on Result not Zero
+    BNE,
+    /// Branch on Result Plus
+    BPL,
+    /// Force Break
+    BRK,
+    /// Branch on Overflow Clear
+    BVC,
+    /// Branch on Overflow Set
+    BVS,
+    /// Clear Carry Flag
+    CLC,
+    /// Clear Decimal Mode
+    CLD,
+    /// Clear Interrupt Disable
+    CLI,
+    /// Clear Overflow Flag
+    CLV,
+    /// Compare Memory with Accumulator
+    CMP,
+    /// Compare Memory with X
+    CPX,
+    /// Compare Memory with Y
+    CPY,
+    /// Decrement Memory by One
+    DEC,
+    /// Decrement X by One
+    DEX,
+    /// Decrement Y by One
+    DEY,
+    /// Exclusive-OR Memory with Accumulator
+    EOR,
+    /// Increment Memory by One
+    INC,
+    /// Increment X by One
+    INX,
+    /// Increment Y by One
+    INY,
+    /// Jump to New Location
+    JMP,
+    /// Jump to New Location Saving Return Address
+    JSR,
+    /// Load Accumulator with Memory
+    LDA,
+    /// Load X with Memory
+    LDX,
+    /// Load Y with Memory
+    LDY,
+    /// Shift One Bit Right
+    LSR,
+    /// No Operation
+    NOP,
+    /// OR Memory with Accumulator
+    ORA,
+    /// Push Accumulator on Stack
+    PHA,
+    /// Push Processor Status on Stack
+    PHP,
+    /// Pull Accumulator from Stack
+    PLA,
+    /// Pull Processor Status from Stack
+    PLP,
+    /// Rotate One Bit Left
+    ROL,
+    /// Rotate One Bit Right
+    ROR,
+    /// Return from Interrupt
+    RTI,
+    /// Return from Subroutine
+    RTS,
+    /// Subtract Memory from Accumulator with Borrow
+    SBC,
+    /// Set Carry Flag
+    SEC,
+    /// Set Decimal Mode
+    SED,
+    /// Set Interrupt Disable
+    SEI,
+    /// Store Accumulator in Memory
+    STA,
+    /// Store X in Memory
+    STX,
+    /// Store Y in Memory
+    STY,
+    /// Transfer Accumulator to X
+    TAX,
+    /// Transfer Accumulator to Y
+    TAY,
+    /// Transfer Stack Pointer to X
+    TSX,
+    /// Transfer X to Accumulator
+    TXA,
+    /// Transfer X to Stack Pointer
+    TXS,
+    /// Transfer Y to Accumulator
+    TYA,
+
+    // Undocumented operations
+    /// ASL Memory, then ORA with Accumulator
+    SLO,
+    /// ROL Memory, then AND with Accumulator
+    RLA,
+    /// LSR Memory, then EOR with Accumulator
+    SRE,
+    /// ROR Memory, then ADC to Accumulator
+    RRA,
+    /// Store Accumulator AND X
+    SAX,
+    /// Load Accumulator and X with Memory
+    LAX,
+    /// DEC Memory, then CMP with Accumulator
+    DCP,
+    /// INC Memory, then SBC from Accumulator
+    ISC,
+    /// AND Memory with Accumulator, copying N to C
+    ANC,
+    /// AND Memory with Accumulator, then LSR Accumulator
+    ALR,
+    /// AND Memory with Accumulator, then ROR Accumulator
+    ARR,
+    /// Subtract Memory from Accumulator AND X into X
+    SBX,
+    /// AND Memory, X and magic constant into Accumulator (unstable)
+    XAA,
+    /// AND Memory and magic constant into Accumulator and X (unstable)
+    LXA,
+    /// Store Accumulator AND X AND high address byte + 1 (unstable)
+    SHA,
+    /// Store X AND high address byte + 1 (unstable)
+    SHX,
+    /// Store Y AND high address byte + 1 (unstable)
+    SHY,
+    /// Transfer Accumulator AND X to Stack Pointer, then SHA (unstable)
+    TAS,
+    /// AND Memory with Stack Pointer into Accumulator, X and Stack Pointer
+    LAS,
+    /// Halt the CPU until reset
+    JAM,
+
+    // 65C02 operations
+    /// Branch Always
+    BRA,
+    /// Push X on Stack
+    PHX,
+    /// Push Y on Stack
+    PHY,
+    /// Pull X from Stack
+    PLX,
+    /// Pull Y from Stack
+    PLY,
+    /// Store Zero in Memory
+    STZ,
+    /// Test and Reset Memory Bits with Accumulator
+    TRB,
+    /// Test and Set Memory Bits with Accumulator
+    TSB,
+    /// Reset Memory Bit (bit number in the opcode's high nibble)
+    RMB,
+    /// Set Memory Bit (bit number in the opcode's high nibble)
+    SMB,
+    /// Branch on Bit Reset
+    BBR,
+    /// Branch on Bit Set
+    BBS,
+    /// Wait for Interrupt
+    WAI,
+    /// Stop the CPU until reset
+    STP,
+}
+
+/// How an instruction accesses its memory operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    Modify,
+}
+
+impl Operation {
+    fn access(self) -> Access {
+        use Operation::*;
+        match self {
+            STA | STX | STY | STZ | SAX | SHA | SHX | SHY | TAS => Access::Write,
+            ASL | LSR | ROL | ROR | INC | DEC => Access::Modify,
+            SLO | RLA | SRE | RRA | DCP | ISC => Access::Modify,
+            TRB | TSB | RMB | SMB => Access::Modify,
+            _ => Access::Read,
+        }
+    }
+}
+
+/// Ways an instruction can specify its operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressingMode {
+    /// Implied: `OPC`
+    IMP,
+    /// Accumulator: `OPC A`
+    ACC,
+    /// Immediate: `OPC #$BB`
+    IMM,
+    /// Zero Page: `OPC $LL`
+    ZPG,
+    /// Zero Page, X-indexed: `OPC $LL,X`
+    ZPX,
+    /// Zero Page, Y-indexed: `OPC $LL,Y`
+    ZPY,
+    /// Absolute: `OPC $HHLL`
+    ABS,
+    /// Absolute, X-indexed: `OPC $HHLL,X`
+    ABX,
+    /// Absolute, Y-indexed: `OPC $HHLL,Y`
+    ABY,
+    /// Indirect: `OPC ($HHLL)`
+    IND,
+    /// X-indexed, Indirect: `OPC ($LL,X)`
+    IZX,
+    /// Indirect, Y-indexed: `OPC ($LL),Y`
+    IZY,
+    /// Relative: `OPC $BB`
+    REL,
+    /// Zero Page Indirect: `OPC ($LL)` (65C02)
+    IZP,
+    /// Absolute X-indexed, Indirect: `OPC ($HHLL,X)` (65C02)
+    IAX,
+    /// Zero Page, Relative: `OPC $LL,$BB` (65C02)
+    ZPR,
+}
+
+impl AddressingMode {
+    /// Steps resolving the operand into MAR, or into MDR for
+    /// immediate operands.
+    fn steps(self) -> &'static [Step] {
+        use AddressingMode::*;
+        use Step::*;
+        match self {
+            IMP | ACC => &[DummyReadPc],
+            IMM => &[FetchOperand],
+            REL => &[FetchOperand, BranchTaken, BranchFixPc],
+            ZPG => &[FetchZeroPage],
+            ZPX | ZPY => &[FetchZeroPage, IndexZeroPage],
+            ABS => &[FetchAddrLo, FetchAddrHi],
+            ABX | ABY => &[FetchAddrLo, FetchAddrHi, FixAddrHi],
+            IND => &[FetchAddrLo, FetchAddrHi, ReadPointerLo, ReadPointerHi],
+            IZX => &[FetchZeroPage, IndexZeroPage, ReadPointerLo, ReadPointerHi],
+            IZY => &[FetchZeroPage, ReadPointerLo, ReadPointerHi, FixAddrHi],
+            IZP => &[FetchZeroPage, ReadPointerLo, ReadPointerHi],
+            IAX => &[
+                FetchAddrLo,
+                FetchAddrHi,
+                DummyReadPrev,
+                ReadPointerLo,
+                ReadPointerHi,
+            ],
+            ZPR => &[
+                FetchZeroPage,
+                ReadOperand,
+                ReadOperand,
+                FetchOperand,
+                BranchTaken,
+                BranchFixPc,
+            ],
+        }
+    }
+
+    /// Number of operand bytes following the opcode.
+    pub fn operand_len(self) -> usize {
+        use AddressingMode::*;
+        match self {
+            IMP | ACC => 0,
+            IMM | ZPG | ZPX | ZPY | IZX | IZY | IZP | REL => 1,
+            ABS | ABX | ABY | IND | IAX | ZPR => 2,
+        }
+    }
+
+    /// Whether the operand lives in memory at MAR.
+    fn has_memory_operand(self) -> bool {
+        use AddressingMode::*;
+        !matches!(self, IMP | ACC | IMM | REL | ZPR)
+    }
+
+    /// Index added to a zero page address, which wraps around
+    /// within page zero.
+    fn zero_page_index(self, state: &CpuState) -> Data {
+        match self {
+            AddressingMode::ZPX | AddressingMode::IZX => state.x,
+            AddressingMode::ZPY => state.y,
+            _ => 0,
+        }
+    }
+
+    /// Index added to a full address, which may cross a page boundary.
+    fn address_index(self, state: &CpuState) -> Data {
+        match self {
+            AddressingMode::ABX | AddressingMode::IAX => state.x,
+            AddressingMode::ABY | AddressingMode::IZY => state.y,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub op: Operation,
+    pub mode: AddressingMode,
+}
+
+impl Instruction {
+    /// Size in bytes, including the opcode.
+    pub fn size(self) -> usize {
+        1 + self.mode.operand_len()
+    }
+}
+
+/// Look up the instruction encoded by an opcode.
+/// Returns `None` for undocumented opcodes.
+pub fn decode(opcode: Data) -> Option<Instruction> {
+    use AddressingMode::*;
+    use Operation::*;
+    let (op, mode) = match opcode {
+        0x00 => (BRK, IMP),
+        0x01 => (ORA, IZX),
+        0x05 => (ORA, ZPG),
+        0x06 => (ASL, ZPG),
+        0x08 => (PHP, IMP),
+        0x09 => (ORA, IMM),
+        0x0A => (ASL, ACC),
+        0x0D => (ORA, ABS),
+        0x0E => (ASL, ABS),
+        0x10 => (BPL, REL),
+        0x11 => (ORA, IZY),
+        0x15 => (ORA, ZPX),
+        0x16 => (ASL, ZPX),
+        0x18 => (CLC, IMP),
+        0x19 => (ORA, ABY),
+        0x1D => (ORA, ABX),
+        0x1E => (ASL, ABX),
+        0x20 => (JSR, ABS),
+        0x21 => (AND, IZX),
+        0x24 => (BIT, ZPG),
+        0x25 => (AND, ZPG),
+        0x26 => (ROL, ZPG),
+        0x28 => (PLP, IMP),
+        0x29 => (AND, IMM),
+        0x2A => (ROL, ACC),
+        0x2C => (BIT, ABS),
+        0x2D => (AND, ABS),
+        0x2E => (ROL, ABS),
+        0x30 => (BMI, REL),
+        0x31 => (AND, IZY),
+        0x35 => (AND, ZPX),
+        0x36 => (ROL, ZPX),
+        0x38 => (SEC, IMP),
+        0x39 => (AND, ABY),
+        0x3D => (AND, ABX),
+        0x3E => (ROL, ABX),
+        0x40 => (RTI, IMP),
+        0x41 => (EOR, IZX),
+        0x45 => (EOR, ZPG),
+        0x46 => (LSR, ZPG),
+        0x48 => (PHA, IMP),
+        0x49 => (EOR, IMM),
+        0x4A => (LSR, ACC),
+        0x4C => (JMP, ABS),
+        0x4D => (EOR, ABS),
+        0x4E => (LSR, ABS),
+        0x50 => (BVC, REL),
+        0x51 => (EOR, IZY),
+        0x55 => (EOR, ZPX),
+        0x56 => (LSR, ZPX),
+        0x58 => (CLI, IMP),
+        0x59 => (EOR, ABY),
+        0x5D => (EOR, ABX),
+        0x5E => (LSR, ABX),
+        0x60 => (RTS, IMP),
+        0x61 => (ADC, IZX),
+        0x65 => (ADC, ZPG),
+        0x66 => (ROR, ZPG),
+        0x68 => (PLA, IMP),
+        0x69 => (ADC, IMM),
+        0x6A => (ROR, ACC),
+        0x6C => (JMP, IND),
+        0x6D => (ADC, ABS),
+        0x6E => (ROR, ABS),
+        0x70 => (BVS, REL),
+        0x71 => (ADC, IZY),
+        0x75 => (ADC, ZPX),
+        0x76 => (ROR, ZPX),
+        0x78 => (SEI, IMP),
+        0x79 => (ADC, ABY),
+        0x7D => (ADC, ABX),
+        0x7E => (ROR, ABX),
+        0x81 => (STA, IZX),
+        0x84 => (STY, ZPG),
+        0x85 => (STA, ZPG),
+        0x86 => (STX, ZPG),
+        0x88 => (DEY, IMP),
+        0x8A => (TXA, IMP),
+        0x8C => (STY, ABS),
+        0x8D => (STA, ABS),
+        0x8E => (STX, ABS),
+        0x90 => (BCC, REL),
+        0x91 => (STA, IZY),
+        0x94 => (STY, ZPX),
+        0x95 => (STA, ZPX),
+        0x96 => (STX, ZPY),
+        0x98 => (TYA, IMP),
+        0x99 => (STA, ABY),
+        0x9A => (TXS, IMP),
+        0x9D => (STA, ABX),
+        0xA0 => (LDY, IMM),
+        0xA1 => (LDA, IZX),
+        0xA2 => (LDX, IMM),
+        0xA4 => (LDY, ZPG),
+        0xA5 => (LDA, ZPG),
+        0xA6 => (LDX, ZPG),
+        0xA8 => (TAY, IMP),
+        0xA9 => (LDA, IMM),
+        0xAA => (TAX, IMP),
+        0xAC => (LDY, ABS),
+        0xAD => (LDA, ABS),
+        0xAE => (LDX, ABS),
+        0xB0 => (BCS, REL),
+        0xB1 => (LDA, IZY),
+        0xB4 => (LDY, ZPX),
+        0xB5 => (LDA, ZPX),
+        0xB6 => (LDX, ZPY),
+        0xB8 => (CLV, IMP),
+        0xB9 => (LDA, ABY),
+        0xBA => (TSX, IMP),
+        0xBC => (LDY, ABX),
+        0xBD => (LDA, ABX),
+        0xBE => (LDX, ABY),
+        0xC0 => (CPY, IMM),
+        0xC1 => (CMP, IZX),
+        0xC4 => (CPY, ZPG),
+        0xC5 => (CMP, ZPG),
+        0xC6 => (DEC, ZPG),
+        0xC8 => (INY, IMP),
+        0xC9 => (CMP, IMM),
+        0xCA => (DEX, IMP),
+        0xCC => (CPY, ABS),
+        0xCD => (CMP, ABS),
+        0xCE => (DEC, ABS),
+        0xD0 => (BNE, REL),
+        0xD1 => (CMP, IZY),
+        0xD5 => (CMP, ZPX),
+        0xD6 => (DEC, ZPX),
+        0xD8 => (CLD, IMP),
+        0xD9 => (CMP, ABY),
+        0xDD => (CMP, ABX),
+        0xDE => (DEC, ABX),
+        0xE0 => (CPX, IMM),
+        0xE1 => (SBC, IZX),
+        0xE4 => (CPX, ZPG),
+        0xE5 => (SBC, ZPG),
+        0xE6 => (INC, ZPG),
+        0xE8 => (INX, IMP),
+        0xE9 => (SBC, IMM),
+        0xEA => (NOP, IMP),
+        0xEC => (CPX, ABS),
+        0xED => (SBC, ABS),
+        0xEE => (INC, ABS),
+        0xF0 => (BEQ, REL),
+        0xF1 => (SBC, IZY),
+        0xF5 => (SBC, ZPX),
+        0xF6 => (INC, ZPX),
+        0xF8 => (SED, IMP),
+        0xF9 => (SBC, ABY),
+        0xFD => (SBC, ABX),
+        0xFE => (INC, ABX),
+        _ => return None,
+    };
+    Some(Instruction { op, mode })
+}
+
+/// Look up the instruction encoded by an undocumented opcode.
+/// Returns `None` for documented opcodes.
+pub fn decode_undocumented(opcode: Data) -> Option<Instruction> {
+    use AddressingMode::*;
+    use Operation::*;
+    let (op, mode) = match opcode {
+        0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => {
+            (JAM, IMP)
+        }
+        0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => (NOP, IMP),
+        0x80 | 0x82 | 0x89 | 0xC2 | 0xE2 => (NOP, IMM),
+        0x04 | 0x44 | 0x64 => (NOP, ZPG),
+        0x14 | 0x34 | 0x54 | 0x74 | 0xD4 | 0xF4 => (NOP, ZPX),
+        0x0C => (NOP, ABS),
+        0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => (NOP, ABX),
+        0x03 => (SLO, IZX),
+        0x07 => (SLO, ZPG),
+        0x0B | 0x2B => (ANC, IMM),
+        0x0F => (SLO, ABS),
+        0x13 => (SLO, IZY),
+        0x17 => (SLO, ZPX),
+        0x1B => (SLO, ABY),
+        0x1F => (SLO, ABX),
+        0x23 => (RLA, IZX),
+        0x27 => (RLA, ZPG),
+        0x2F => (RLA, ABS),
+        0x33 => (RLA, IZY),
+        0x37 => (RLA, ZPX),
+        0x3B => (RLA, ABY),
+        0x3F => (RLA, ABX),
+        0x43 => (SRE, IZX),
+        0x47 => (SRE, ZPG),
+        0x4B => (ALR, IMM),
+        0x4F => (SRE, ABS),
+        0x53 => (SRE, IZY),
+        0x57 => (SRE, ZPX),
+        0x5B => (SRE, ABY),
+        0x5F => (SRE, ABX),
+        0x63 => (RRA, IZX),
+        0x67 => (RRA, ZPG),
+        0x6B => (ARR, IMM),
+        0x6F => (RRA, ABS),
+        0x73 => (RRA, IZY),
+        0x77 => (RRA, ZPX),
+        0x7B => (RRA, ABY),
+        0x7F => (RRA, ABX),
+        0x83 => (SAX, IZX),
+        0x87 => (SAX, ZPG),
+        0x8B => (XAA, IMM),
+        0x8F => (SAX, ABS),
+        0x93 => (SHA, IZY),
+        0x97 => (SAX, ZPY),
+        0x9B => (TAS, ABY),
+        0x9C => (SHY, ABX),
+        0x9E => (SHX, ABY),
+        0x9F => (SHA, ABY),
+        0xA3 => (LAX, IZX),
+        0xA7 => (LAX, ZPG),
+        0xAB => (LXA, IMM),
+        0xAF => (LAX, ABS),
+        0xB3 => (LAX, IZY),
+        0xB7 => (LAX, ZPY),
+        0xBB => (LAS, ABY),
+        0xBF => (LAX, ABY),
+        0xC3 => (DCP, IZX),
+        0xC7 => (DCP, ZPG),
+        0xCB => (SBX, IMM),
+        0xCF => (DCP, ABS),
+        0xD3 => (DCP, IZY),
+        0xD7 => (DCP, ZPX),
+        0xDB => (DCP, ABY),
+        0xDF => (DCP, ABX),
+        0xE3 => (ISC, IZX),
+        0xE7 => (ISC, ZPG),
+        0xEB => (SBC, IMM),
+        0xEF => (ISC, ABS),
+        0xF3 => (ISC, IZY),
+        0xF7 => (ISC, ZPX),
+        0xFB => (ISC, ABY),
+        0xFF => (ISC, ABX),
+        _ => return None,
+    };
+    Some(Instruction { op, mode })
+}
+
+/// Look up an instruction the 65C02 adds to the documented NMOS set,
+/// including the Rockwell bit instructions and the WDC WAI and STP.
+/// Returns `None` for all other opcodes.
+pub fn decode_65c02(opcode: Data) -> Option<Instruction> {
+    use AddressingMode::*;
+    use Operation::*;
+    let (op, mode) = match opcode {
+        0x04 => (TSB, ZPG),
+        0x0C => (TSB, ABS),
+        0x12 => (ORA, IZP),
+        0x14 => (TRB, ZPG),
+        0x1A => (INC, ACC),
+        0x1C => (TRB, ABS),
+        0x32 => (AND, IZP),
+        0x34 => (BIT, ZPX),
+        0x3A => (DEC, ACC),
+        0x3C => (BIT, ABX),
+        0x52 => (EOR, IZP),
+        0x5A => (PHY, IMP),
+        0x64 => (STZ, ZPG),
+        0x72 => (ADC, IZP),
+        0x74 => (STZ, ZPX),
+        0x7A => (PLY, IMP),
+        0x7C => (JMP, IAX),
+        0x80 => (BRA, REL),
+        0x89 => (BIT, IMM),
+        0x92 => (STA, IZP),
+        0x9C => (STZ, ABS),
+        0x9E => (STZ, ABX),
+        0xB2 => (LDA, IZP),
+        0xCB => (WAI, IMP),
+        0xD2 => (CMP, IZP),
+        0xDA => (PHX, IMP),
+        0xDB => (STP, IMP),
+        0xF2 => (SBC, IZP),
+        0xFA => (PLX, IMP),
+        _ if opcode & 0x0F == 0x07 && opcode < 0x80 => (RMB, ZPG),
+        _ if opcode & 0x0F == 0x07 => (SMB, ZPG),
+        _ if opcode & 0x0F == 0x0F && opcode < 0x80 => (BBR, ZPR),
+        _ if opcode & 0x0F == 0x0F => (BBS, ZPR),
+        _ => return None,
+    };
+    Some(Instruction { op, mode })
+}
+
+/// The chips of the 6502 family this emulator can behave like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Variant {
+    /// Original NMOS 6502, including its undocumented opcodes.
+    #[default]
+    NMOS,
+    /// 65C02 without the bit instructions, as made by GTE and NCR.
+    CMOS,
+    /// Rockwell R65C02, adding RMB, SMB, BBR and BBS.
+    ROCKWELL,
+    /// WDC W65C02S, adding WAI and STP on top of the Rockwell set.
+    WDC,
+    /// Ricoh 2A03 from the NES: an NMOS 6502 without decimal mode.
+    RP2A03,
+}
+
+impl Variant {
+    /// Look up the instruction an opcode encodes on this chip.
+    /// Unused 65C02 opcodes decode to NOPs of various lengths.
+    pub fn decode(self, opcode: Data) -> Instruction {
+        use AddressingMode::*;
+        if !self.is_cmos() {
+            return decode(opcode)
+                .or_else(|| decode_undocumented(opcode))
+                .expect("every NMOS opcode decodes");
+        }
+        let extra = decode_65c02(opcode).filter(|i| match i.op {
+            Operation::RMB | Operation::SMB | Operation::BBR | Operation::BBS => {
+                self != Variant::CMOS
+            }
+            Operation::WAI | Operation::STP => self == Variant::WDC,
+            _ => true,
+        });
+        extra.or_else(|| decode(opcode)).unwrap_or_else(|| {
+            let mode = match opcode {
+                0x02 | 0x22 | 0x42 | 0x62 | 0x82 | 0xC2 | 0xE2 => IMM,
+                0x44 => ZPG,
+                0x54 | 0xD4 | 0xF4 => ZPX,
+                0x5C | 0xDC | 0xFC => ABS,
+                _ => IMP,
+            };
+            Instruction {
+                op: Operation::NOP,
+                mode,
+            }
+        })
+    }
+
+    /// Whether this is one of the CMOS chips, which fix most of the
+    /// NMOS quirks.
+    pub fn is_cmos(self) -> bool {
+        matches!(self, Variant::CMOS | Variant::ROCKWELL | Variant::WDC)
+    }
+
+    /// Whether the D flag switches ADC and SBC to decimal mode.
+    pub fn has_decimal_mode(self) -> bool {
+        self != Variant::RP2A03
+    }
+}
+
+impl std::str::FromStr for Variant {
+    type Err = EmuError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "nmos" | "6502" => Ok(Variant::NMOS),
+            "cmos" | "65c02" => Ok(Variant::CMOS),
+            "rockwell" | "r65c02" => Ok(Variant::ROCKWELL),
+            "wdc" | "w65c02" => Ok(Variant::WDC),
+            "2a03" | "rp2a03" => Ok(Variant::RP2A03),
+            _ => Err(EmuError::Config(format!("unknown CPU variant: {}", name))),
+        }
+    }
+}
+
+/// Behaviour of the unstable undocumented opcodes, which differs
+/// between chips and even with temperature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unstable {
+    /// Constant ORed into the accumulator by XAA.
+    pub xaa_magic: Data,
+
+    /// Constant ORed into the accumulator by LXA.
+    pub lxa_magic: Data,
+
+    /// Whether SHA, SHX, SHY and TAS AND the value they store with the
+    /// high byte of the target address plus one.
+    pub and_high_byte: bool,
+}
+
+impl Unstable {
+    pub fn new() -> Unstable {
+        Unstable {
+            xaa_magic: 0xEE,
+            lxa_magic: 0xEE,
+            and_high_byte: true,
+        }
+    }
+}
+
+impl Default for Unstable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Everything that can stop the emulator short of a panic.
+#[derive(Debug)]
+pub enum EmuError {
+    /// A device refused an access, or nothing was there in strict mode.
+    Memory(MemoryError),
+    /// A JAM opcode halted the CPU.
+    Jam { opcode: Data, addr: Address },
+    /// The machine can't be set up as asked.
+    Config(String),
+    /// Loading an image or other file failed.
+    Io(std::io::Error),
+    /// An image was malformed.
+    Load(LoadError),
+}
+
+impl std::fmt::Display for EmuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EmuError::Memory(err) => write!(f, "{}", err),
+            EmuError::Jam { opcode, addr } => {
+                write!(f, "CPU jammed by ${:02X} at ${:04X}", opcode, addr)
+            }
+            EmuError::Config(msg) => write!(f, "invalid configuration: {}", msg),
+            EmuError::Io(err) => write!(f, "{}", err),
+            EmuError::Load(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for EmuError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmuError::Memory(err) => Some(err),
+            EmuError::Io(err) => Some(err),
+            EmuError::Load(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<MemoryError> for EmuError {
+    fn from(err: MemoryError) -> Self {
+        EmuError::Memory(err)
+    }
+}
+
+impl From<std::io::Error> for EmuError {
+    fn from(err: std::io::Error) -> Self {
+        EmuError::Io(err)
+    }
+}
+
+impl From<LoadError> for EmuError {
+    fn from(err: LoadError) -> Self {
+        EmuError::Load(err)
+    }
+}
+
+/// An instruction or interrupt sequence run by `Cpu::step_instruction`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Executed {
+    /// Where the opcode was fetched from.
+    pub pc: Address,
+    /// For interrupt sequences, the opcode fetched and thrown away.
+    pub opcode: Data,
+    pub instruction: Instruction,
+    operands: [Data; 2],
+    operand_count: usize,
+    pub cycles: usize,
+    pub outcome: StepOutcome,
+}
+
+impl Executed {
+    /// Operand bytes following the opcode, in program order.
+    pub fn operands(&self) -> &[Data] {
+        &self.operands[..self.operand_count]
+    }
+}
+
+/// How a step ended, when nothing went wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction completed.
+    Retired,
+    /// An interrupt sequence ran instead of an instruction, or the
+    /// CPU is held in reset.
+    Interrupted(Interrupt),
+    /// STP halted the CPU.
+    Halted,
+    /// WAI is waiting for an interrupt.
+    Waiting,
+    /// A breakpoint or watchpoint was hit.
+    Break(Hit),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusMode {
+    READ = 1,
+    WRITE = 0,
+}
+
+/// Micro-operations an instruction is broken down into.
+/// Each one takes exactly one bus cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    /// Read opcode at PC into IR and decode it.
+    FetchOpcode,
+    /// Read at PC and throw the value away.
+    DummyReadPc,
+    /// Read at PC and throw the value away, then increment PC.
+    IncrementPc,
+    /// Read immediate operand or branch offset at PC into MDR.
+    FetchOperand,
+    /// Read zero page address at PC into MAR.
+    FetchZeroPage,
+    /// Read from the zero page address in MAR while adding the index.
+    IndexZeroPage,
+    /// Read low address byte at PC into MAR.
+    FetchAddrLo,
+    /// Read high address byte at PC into MAR, indexing the low byte.
+    FetchAddrHi,
+    /// Read from the not yet fixed address in MAR and carry the index
+    /// into its high byte. Only skipped by read instructions which did
+    /// not cross a page boundary.
+    FixAddrHi,
+    /// Read low byte of an indirect address pointed to by MAR.
+    ReadPointerLo,
+    /// Read high byte of an indirect address, replacing MAR and
+    /// indexing its low byte. The pointer never crosses into the next
+    /// page, so zero page pointers wrap and `JMP ($xxFF)` reads the
+    /// high byte from `$xx00`.
+    ReadPointerHi,
+    /// Read at PC while adding the branch offset to its low byte.
+    /// Skipped if the branch is not taken.
+    BranchTaken,
+    /// Read at PC while fixing its high byte.
+    /// Skipped if the branch target is on the same page.
+    BranchFixPc,
+    /// Read the last operand byte again at PC - 1, carrying the index
+    /// into the high byte of MAR.
+    DummyReadPrev,
+    /// Read from $FFFF, forever.
+    Halt,
+    /// Read at PC until an interrupt line is asserted.
+    Wait,
+    /// Read operand from MAR into MDR.
+    ReadOperand,
+    /// Write register value to MAR.
+    WriteOperand,
+    /// Write unmodified MDR back to MAR while modifying it.
+    DummyWrite,
+    /// Write modified MDR back to MAR.
+    WriteResult,
+    /// Push high byte of PC.
+    PushPch,
+    /// Push low byte of PC.
+    PushPcl,
+    /// Push accumulator or status register.
+    PushReg,
+    /// Read from the top of the stack and throw the value away.
+    DummyReadStack,
+    /// Pull a value into MDR.
+    Pull,
+    /// Pull status register.
+    PullStatus,
+    /// Pull low byte of PC.
+    PullPcl,
+    /// Pull high byte of PC.
+    PullPch,
+    /// Read low byte of the interrupt vector into PC.
+    FetchVectorLo,
+    /// Read high byte of the interrupt vector into PC.
+    FetchVectorHi,
+}
+
+const MAX_STEPS: usize = 8;
+
+/// Steps of the current instruction that follow the opcode fetch.
+#[derive(Debug, Clone, Copy)]
+struct Steps {
+    buf: [Step; MAX_STEPS],
+    len: usize,
+    pos: usize,
+}
+
+impl Steps {
+    fn new() -> Steps {
+        Steps {
+            buf: [Step::FetchOpcode; MAX_STEPS],
+            len: 0,
+            pos: 0,
+        }
+    }
+
+    fn push(&mut self, steps: &[Step]) {
+        for step in steps {
+            self.buf[self.len] = *step;
+            self.len += 1;
+        }
+    }
+
+    /// The step for the upcoming cycle. Once all steps are done,
+    /// the next instruction is fetched.
+    fn current(&self) -> Step {
+        if self.pos < self.len {
+            self.buf[self.pos]
+        } else {
+            Step::FetchOpcode
+        }
+    }
+
+    fn advance(&mut self) {
+        self.pos += 1;
+    }
+
+    fn is_done(&self) -> bool {
+        self.pos >= self.len
+    }
+}
+
+const STACK_PAGE: Address = 0x0100;
+const NMI_VECTOR: Address = 0xFFFA;
+const RESET_VECTOR: Address = 0xFFFC;
+const IRQ_VECTOR: Address = 0xFFFE;
+
+/// Hardware interrupts. Their sequences run like a BRK instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    /// Interrupt Request
+    IRQ,
+    /// Non-Maskable Interrupt
+    NMI,
+    /// Reset
+    RES,
+}
+
+#[derive(Debug)]
+pub struct Cpu {
+    state: CpuState,
+    pub addr_bus: Address,
+
+    /// Holds the last value driven onto the bus until something else
+    /// drives it. Reads nobody responds to see this value (open bus).
+    pub data_bus: Data,
+
+    pub rwb: BusMode,
+
+    /// Set during cycles that fetch an opcode.
+    pub sync: bool,
+
+    /// Interrupt request line, set while asserted.
+    /// Level-triggered and ignored while the interrupt disable flag is set.
+    /// Devices can also assert it through `Memory::irq`.
+    pub irq: bool,
+
+    /// Non-maskable interrupt line, set while asserted.
+    /// Edge-triggered: it must be released before it can fire again.
+    /// Devices can also assert it through `Memory::nmi`.
+    pub nmi: bool,
+
+    /// Reset line, set while asserted.
+    /// The CPU is held in reset until the line is released.
+    pub res: bool,
+
+    /// Behaviour of unstable undocumented opcodes.
+    pub unstable: Unstable,
+
+    variant: Variant,
+    instruction: Instruction,
+    steps: Steps,
+    page_crossed: bool,
+
+    /// Zero page value tested by BBR and BBS.
+    tested: Data,
+
+    /// Interrupt sequence currently running in place of a BRK.
+    interrupt: Option<Interrupt>,
+    vector: Address,
+    /// Interrupt lines as devices drove them during the current cycle.
+    device_irq: bool,
+    device_nmi: bool,
+    nmi_prev: bool,
+    nmi_pending: bool,
+    reset_pending: bool,
+
+    /// Interrupt polling results at the end of the last two cycles.
+    polled: bool,
+    polled_earlier: bool,
+
+    /// Whether an interrupt sequence replaces the next opcode fetch.
+    take_interrupt: bool,
+
+    cycles: u64,
+    breakpoints: Breakpoints,
+}
+
+impl Cpu {
+    pub fn new() -> Cpu {
+        Cpu::with_variant(Variant::NMOS)
+    }
+
+    pub fn with_variant(variant: Variant) -> Cpu {
+        let state = CpuState::new();
+        let addr = state.mar;
+        let data = state.mdr;
+        Cpu {
+            state,
+            addr_bus: addr,
+            data_bus: data,
+            rwb: BusMode::READ,
+            sync: false,
+            instruction: Instruction {
+                op: Operation::NOP,
+                mode: AddressingMode::IMP,
+            },
+            irq: false,
+            nmi: false,
+            res: false,
+            unstable: Unstable::new(),
+            variant,
+            steps: Steps::new(),
+            page_crossed: false,
+            tested: 0,
+            interrupt: None,
+            vector: IRQ_VECTOR,
+            device_irq: false,
+            device_nmi: false,
+            nmi_prev: false,
+            nmi_pending: false,
+            reset_pending: false,
+            polled: false,
+            polled_earlier: false,
+            take_interrupt: false,
+            cycles: 0,
+            breakpoints: Breakpoints::new(),
+        }
+    }
+
+    /// Abort whatever the CPU is doing and run the reset sequence,
+    /// as if the reset line had been pulsed.
+    pub fn reset(&mut self) {
+        self.steps = Steps::new();
+        self.reset_pending = true;
+    }
+
+    pub fn state(&self) -> &CpuState {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut CpuState {
+        &mut self.state
+    }
+
+    pub fn breakpoints(&self) -> &Breakpoints {
+        &self.breakpoints
+    }
+
+    pub fn breakpoints_mut(&mut self) -> &mut Breakpoints {
+        &mut self.breakpoints
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Whether a JAM or STP opcode has halted the CPU. Only a reset gets
+    /// it going again.
+    pub fn is_halted(&self) -> bool {
+        self.steps.current() == Step::Halt
+    }
+
+    /// Whether WAI is waiting for an interrupt line to be asserted.
+    pub fn is_waiting(&self) -> bool {
+        self.steps.current() == Step::Wait
+    }
+
+    /// Run one bus cycle against `mem`. Reads nobody responds to leave
+    /// the data bus alone.
+    pub fn cycle<M: Memory + ?Sized>(&mut self, mem: &mut M) -> Result<(), MemoryError> {
+        self.device_irq = mem.irq();
+        self.device_nmi = mem.nmi();
+        self.setup_cycle();
+        let addr = self.addr_bus;
+        let result = match self.rwb {
+            BusMode::READ => mem.try_read(&addr).map(|val| {
+                if let Some(val) = val {
+                    self.data_bus = val;
+                }
+            }),
+            BusMode::WRITE => mem.try_write(addr, self.data_bus),
+        };
+        self.complete_cycle();
+        result
+    }
+
+    /// Run cycles until the current instruction or interrupt sequence
+    /// is done, or the CPU stops.
+    pub fn step<M: Memory + ?Sized>(&mut self, mem: &mut M) -> Result<StepOutcome, EmuError> {
+        self.step_instruction(mem).map(|executed| executed.outcome)
+    }
+
+    /// Like `step`, but also report what was executed.
+    ///
+    /// Watchpoints are checked on every bus cycle, and breakpoints at the
+    /// PC the step ends with. A hit turns a completed instruction or
+    /// interrupt sequence into `StepOutcome::Break`.
+    pub fn step_instruction<M: Memory + ?Sized>(
+        &mut self,
+        mem: &mut M,
+    ) -> Result<Executed, EmuError> {
+        use Step::*;
+        let watching = self.breakpoints.is_watching();
+        let mut hit = None;
+        let mut executed = Executed {
+            pc: self.state.pc,
+            opcode: self.state.ir,
+            instruction: self.instruction,
+            operands: [0; 2],
+            operand_count: 0,
+            cycles: 0,
+            outcome: StepOutcome::Retired,
+        };
+        loop {
+            let step = self.steps.current();
+            self.cycle(mem)?;
+            executed.cycles += 1;
+            match step {
+                FetchOpcode => {
+                    executed.opcode = self.state.ir;
+                    executed.instruction = self.instruction;
+                }
+                FetchOperand | FetchZeroPage | FetchAddrLo | FetchAddrHi
+                    if executed.operand_count < executed.operands.len() =>
+                {
+                    executed.operands[executed.operand_count] = self.data_bus;
+                    executed.operand_count += 1;
+                }
+                _ => {}
+            }
+            if watching && hit.is_none() {
+                hit = self
+                    .breakpoints
+                    .check_bus(self.addr_bus, self.rwb, self.data_bus);
+            }
+            if let Some(outcome) = self.outcome()? {
+                executed.outcome = match outcome {
+                    StepOutcome::Retired | StepOutcome::Interrupted(_) => hit
+                        .or_else(|| self.breakpoints.check_pc(self, mem))
+                        .map_or(outcome, StepOutcome::Break),
+                    _ => outcome,
+                };
+                return Ok(executed);
+            }
+        }
+    }
+
+    /// Run `n` cycles, stopping early on errors.
+    pub fn run_cycles<M: Memory + ?Sized>(&mut self, mem: &mut M, n: u64) -> Result<(), EmuError> {
+        for _ in 0..n {
+            self.cycle(mem)?;
+            if self.is_halted() {
+                self.outcome()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Step until `done` holds between two instructions, a breakpoint or
+    /// watchpoint is hit, or the CPU halts or waits.
+    pub fn run_until<M, F>(&mut self, mem: &mut M, mut done: F) -> Result<StepOutcome, EmuError>
+    where
+        M: Memory + ?Sized,
+        F: FnMut(&Cpu) -> bool,
+    {
+        self.run_with(mem, |cpu, _, outcome| {
+            let stopped = matches!(
+                outcome,
+                StepOutcome::Halted | StepOutcome::Waiting | StepOutcome::Break(_)
+            );
+            stopped || done(cpu)
+        })
+    }
+
+    /// Step until `stop` says so, given the memory and how the last step
+    /// ended. Returns that outcome.
+    pub fn run_with<M, F>(&mut self, mem: &mut M, mut stop: F) -> Result<StepOutcome, EmuError>
+    where
+        M: Memory + ?Sized,
+        F: FnMut(&Cpu, &M, StepOutcome) -> bool,
+    {
+        loop {
+            let outcome = self.step(mem)?;
+            if stop(self, mem, outcome) {
+                return Ok(outcome);
+            }
+        }
+    }
+
+    /// Number of cycles run since the CPU was created.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Step until something other than an instruction completing
+    /// happens.
+    pub fn run<M: Memory + ?Sized>(&mut self, mem: &mut M) -> Result<StepOutcome, EmuError> {
+        self.run_with(mem, |_, _, outcome| outcome != StepOutcome::Retired)
+    }
+
+    /// What the last cycle ended with, if it ended a step.
+    fn outcome(&self) -> Result<Option<StepOutcome>, EmuError> {
+        if self.res {
+            return Ok(Some(StepOutcome::Interrupted(Interrupt::RES)));
+        }
+        let outcome = match self.steps.current() {
+            Step::Halt if self.instruction.op == Operation::JAM => {
+                return Err(EmuError::Jam {
+                    opcode: self.state.ir,
+                    addr: self.state.pc.wrapping_sub(1),
+                })
+            }
+            Step::Halt => StepOutcome::Halted,
+            Step::Wait => StepOutcome::Waiting,
+            Step::FetchOpcode => match self.interrupt {
+                Some(interrupt) => StepOutcome::Interrupted(interrupt),
+                None => StepOutcome::Retired,
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(outcome))
+    }
+
+    /// Execute first part of a cycle.
+    /// At the end, bus fields must hold desired values.
+    pub fn setup_cycle(&mut self) {
+        use Step::*;
+        let step = self.steps.current();
+        self.sync = step == FetchOpcode;
+        let pc = self.state.pc;
+        let cmos = self.variant.is_cmos();
+        match step {
+            FetchOpcode | DummyReadPc | IncrementPc | FetchOperand | FetchZeroPage
+            | FetchAddrLo | FetchAddrHi | BranchTaken | BranchFixPc | Wait => self.read(pc),
+            // The 65C02 avoids reading from the unfixed address.
+            FixAddrHi if cmos && self.page_crossed => self.read(pc.wrapping_sub(1)),
+            DummyReadPrev => self.read(pc.wrapping_sub(1)),
+            IndexZeroPage | ReadPointerLo | FixAddrHi | ReadOperand => self.read(self.state.mar),
+            ReadPointerHi => {
+                let mar = self.state.mar;
+                let addr = match self.instruction.mode {
+                    AddressingMode::IND if cmos => mar.wrapping_add(1),
+                    AddressingMode::IAX => mar.wrapping_add(1),
+                    _ => (mar & 0xFF00) | (mar as Data).wrapping_add(1) as Address,
+                };
+                self.read(addr)
+            }
+            WriteOperand => self.write(self.store_addr(), self.store_value()),
+            // The 65C02 reads again instead of writing twice.
+            DummyWrite if cmos => self.read(self.state.mar),
+            DummyWrite | WriteResult => self.write(self.state.mar, self.state.mdr),
+            // The reset sequence goes through the motions of pushing
+            // onto the stack, but reads instead of writing.
+            PushPch | PushPcl | PushReg if self.interrupt == Some(Interrupt::RES) => {
+                self.read(self.state.stack_addr())
+            }
+            PushPch => self.push((pc >> 8) as Data),
+            PushPcl => self.push(pc as Data),
+            PushReg => self.push(self.push_value()),
+            DummyReadStack => self.read(self.state.stack_addr()),
+            Pull | PullStatus | PullPcl | PullPch => self.pull(),
+            FetchVectorLo => self.read(self.vector),
+            FetchVectorHi => self.read(self.vector + 1),
+            Halt => self.read(0xFFFF),
+        }
+    }
+
+    /// Execute final part of a cycle.
+    /// The outside world should have reacted on the bus by now.
+    pub fn complete_cycle(&mut self) {
+        use Step::*;
+        self.cycles += 1;
+        let nmi = self.nmi || self.device_nmi;
+        if nmi && !self.nmi_prev {
+            self.nmi_pending = true;
+        }
+        self.nmi_prev = nmi;
+        if self.res {
+            self.reset();
+            return;
+        }
+
+        let step = self.steps.current();
+        let data = self.data_bus;
+        let s = &mut self.state;
+        match step {
+            FetchOpcode => {
+                s.ir = data;
+                match self.pending_interrupt() {
+                    Some(interrupt) => self.begin_interrupt(interrupt),
+                    None => {
+                        self.state.pc = self.state.pc.wrapping_add(1);
+                        self.decode_instruction();
+                    }
+                }
+            }
+            DummyReadPc | DummyReadStack => {}
+            IncrementPc => s.pc = s.pc.wrapping_add(1),
+            FetchOperand => {
+                s.mdr = data;
+                s.pc = s.pc.wrapping_add(1);
+            }
+            FetchZeroPage => {
+                s.mar = data as Address;
+                s.pc = s.pc.wrapping_add(1);
+            }
+            IndexZeroPage => {
+                let index = self.instruction.mode.zero_page_index(s);
+                s.mar = (s.mar as Data).wrapping_add(index) as Address;
+            }
+            FetchAddrLo => {
+                s.mar = data as Address;
+                s.pc = s.pc.wrapping_add(1);
+            }
+            FetchAddrHi => {
+                s.pc = s.pc.wrapping_add(1);
+                let base = (data as Address) << 8 | s.mar;
+                self.index_address(base);
+            }
+            FixAddrHi | DummyReadPrev => {
+                if self.page_crossed {
+                    s.mar = s.mar.wrapping_add(0x0100);
+                }
+            }
+            ReadPointerLo => s.mdr = data,
+            ReadPointerHi => {
+                let base = (data as Address) << 8 | s.mdr as Address;
+                if self.instruction.mode == AddressingMode::IZY {
+                    self.index_address(base);
+                } else {
+                    s.mar = base;
+                }
+            }
+            BranchTaken => {
+                let offset = s.mdr as i8 as Address;
+                s.mar = s.pc.wrapping_add(offset);
+                self.page_crossed = (s.mar ^ s.pc) & 0xFF00 != 0;
+                s.pc = (s.pc & 0xFF00) | (s.mar & 0x00FF);
+            }
+            BranchFixPc => s.pc = s.mar,
+            ReadOperand => {
+                s.mdr = data;
+                self.tested = data;
+            }
+            DummyWrite => self.state.mdr = self.modify(self.state.mdr),
+            WriteOperand => {
+                if self.instruction.op == Operation::TAS {
+                    s.sp = s.a & s.x;
+                }
+            }
+            WriteResult | Halt | Wait => {}
+            PushPch | PushPcl => s.sp = s.sp.wrapping_sub(1),
+            PushReg => {
+                s.sp = s.sp.wrapping_sub(1);
+                if self.instruction.op == Operation::BRK {
+                    s.set_flag(Flag::INT, true);
+                    if self.variant.is_cmos() {
+                        s.set_flag(Flag::DEC, false);
+                    }
+                    self.select_vector();
+                }
+            }
+            Pull => {
+                s.sp = s.sp.wrapping_add(1);
+                s.mdr = data;
+            }
+            PullStatus => {
+                s.sp = s.sp.wrapping_add(1);
+                s.set_sr(data);
+            }
+            PullPcl => {
+                s.sp = s.sp.wrapping_add(1);
+                s.pc = (s.pc & 0xFF00) | data as Address;
+            }
+            PullPch => {
+                s.sp = s.sp.wrapping_add(1);
+                s.pc = (data as Address) << 8 | (s.pc & 0x00FF);
+            }
+            FetchVectorLo => s.pc = (s.pc & 0xFF00) | data as Address,
+            FetchVectorHi => s.pc = (data as Address) << 8 | (s.pc & 0x00FF),
+        }
+        let advance = match step {
+            FetchOpcode | Halt => false,
+            Wait => self.irq_asserted() || self.nmi_pending,
+            _ => true,
+        };
+        if advance {
+            self.steps.advance();
+        }
+        self.skip_steps();
+        if self.steps.is_done() {
+            self.execute();
+            self.take_interrupt = self.interrupt_polled_in_time();
+        }
+        self.poll_interrupts();
+    }
+
+    /// Whether an interrupt was detected in time to be taken after the
+    /// instruction that just finished. Interrupts are polled at the end
+    /// of the second to last cycle. Taken branches that stay on the same
+    /// page do not poll in their second cycle.
+    fn interrupt_polled_in_time(&self) -> bool {
+        match self.instruction.op {
+            Operation::BRK => false,
+            // WAI finishes as soon as an interrupt line is asserted.
+            Operation::WAI => {
+                self.nmi_pending || (self.irq_asserted() && !self.state.get_flag(Flag::INT))
+            }
+            _ if self.instruction.mode == AddressingMode::REL
+                && self.branch_condition()
+                && !self.page_crossed =>
+            {
+                self.polled_earlier
+            }
+            _ => self.polled,
+        }
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.irq || self.device_irq
+    }
+
+    fn poll_interrupts(&mut self) {
+        self.polled_earlier = self.polled;
+        self.polled = self.nmi_pending || (self.irq_asserted() && !self.state.get_flag(Flag::INT));
+    }
+
+    fn pending_interrupt(&self) -> Option<Interrupt> {
+        if self.reset_pending {
+            Some(Interrupt::RES)
+        } else if self.take_interrupt && self.nmi_pending {
+            Some(Interrupt::NMI)
+        } else if self.take_interrupt {
+            Some(Interrupt::IRQ)
+        } else {
+            None
+        }
+    }
+
+    /// Run an interrupt sequence instead of the opcode just fetched,
+    /// which is discarded without incrementing PC.
+    fn begin_interrupt(&mut self, interrupt: Interrupt) {
+        use Step::*;
+        self.instruction = Instruction {
+            op: Operation::BRK,
+            mode: AddressingMode::IMP,
+        };
+        self.interrupt = Some(interrupt);
+        self.steps = Steps::new();
+        self.steps.push(&[
+            DummyReadPc,
+            PushPch,
+            PushPcl,
+            PushReg,
+            FetchVectorLo,
+            FetchVectorHi,
+        ]);
+        self.take_interrupt = false;
+        if interrupt == Interrupt::RES {
+            self.reset_pending = false;
+            self.nmi_pending = false;
+        }
+    }
+
+    /// Pick the vector for BRK or an interrupt sequence. An NMI
+    /// occurring until now hijacks BRK and IRQ sequences.
+    fn select_vector(&mut self) {
+        self.vector = if self.interrupt == Some(Interrupt::RES) {
+            RESET_VECTOR
+        } else if self.nmi_pending {
+            self.nmi_pending = false;
+            NMI_VECTOR
+        } else {
+            IRQ_VECTOR
+        };
+    }
+
+    fn read(&mut self, addr: Address) {
+        self.addr_bus = addr;
+        self.rwb = BusMode::READ;
+    }
+
+    fn write(&mut self, addr: Address, data: Data) {
+        self.addr_bus = addr;
+        self.data_bus = data;
+        self.rwb = BusMode::WRITE; // set _after_ data_bus is valid
+    }
+
+    /// Add the index for the current addressing mode to `base`, but only
+    /// to its low byte. The high byte gets fixed up in an extra cycle.
+    fn index_address(&mut self, base: Address) {
+        let index = self.instruction.mode.address_index(&self.state);
+        let addr = base.wrapping_add(index as Address);
+        self.page_crossed = (addr ^ base) & 0xFF00 != 0;
+        self.state.mar = (base & 0xFF00) | (addr & 0x00FF);
+    }
+
+    /// Skip upcoming steps which turn out to be unnecessary.
+    fn skip_steps(&mut self) {
+        loop {
+            let skip = match self.steps.current() {
+                // The 65C02 also saves a cycle on shifts and rotates.
+                Step::FixAddrHi => {
+                    use Operation::*;
+                    let fast = match self.instruction.op {
+                        ASL | LSR | ROL | ROR => self.variant.is_cmos(),
+                        op => op.access() == Access::Read,
+                    };
+                    !self.page_crossed && fast
+                }
+                Step::BranchFixPc => !self.page_crossed,
+                Step::BranchTaken => !self.branch_condition(),
+                _ => false,
+            };
+            if !skip {
+                break;
+            }
+            self.steps.advance();
+        }
+    }
+
+    /// Write a value to the stack. The stack pointer is decremented
+    /// once the cycle completes.
+    fn push(&mut self, val: Data) {
+        self.write(self.state.stack_addr(), val);
+    }
+
+    /// Read the value on top of the stack. The stack pointer is
+    /// incremented once the cycle completes.
+    fn pull(&mut self) {
+        self.read(self.state.stack_top());
+    }
+
+    /// Decode IR and queue up the steps needed to execute it.
+    fn decode_instruction(&mut self) {
+        use Operation::*;
+        use Step::*;
+
+        let ir = self.state.ir;
+        let cmos = self.variant.is_cmos();
+        let instruction = self.variant.decode(ir);
+        self.instruction = instruction;
+        self.interrupt = None;
+        self.steps = Steps::new();
+        self.page_crossed = false;
+
+        let steps = &mut self.steps;
+        match instruction.op {
+            BRK => steps.push(&[
+                FetchOperand,
+                PushPch,
+                PushPcl,
+                PushReg,
+                FetchVectorLo,
+                FetchVectorHi,
+            ]),
+            JSR => steps.push(&[FetchAddrLo, DummyReadStack, PushPch, PushPcl, FetchAddrHi]),
+            RTS => steps.push(&[DummyReadPc, DummyReadStack, PullPcl, PullPch, IncrementPc]),
+            RTI => steps.push(&[DummyReadPc, DummyReadStack, PullStatus, PullPcl, PullPch]),
+            PHA | PHP | PHX | PHY => steps.push(&[DummyReadPc, PushReg]),
+            PLA | PLX | PLY => steps.push(&[DummyReadPc, DummyReadStack, Pull]),
+            PLP => steps.push(&[DummyReadPc, DummyReadStack, PullStatus]),
+            JAM | STP => steps.push(&[DummyReadPc, Halt]),
+            WAI => steps.push(&[DummyReadPc, Wait]),
+            JMP if cmos && instruction.mode == AddressingMode::IND => steps.push(&[
+                FetchAddrLo,
+                FetchAddrHi,
+                DummyReadPrev,
+                ReadPointerLo,
+                ReadPointerHi,
+            ]),
+            // Unused 65C02 opcodes ending in binary 11 take a single cycle.
+            NOP if cmos && ir & 0x03 == 0x03 => {}
+            NOP if cmos && ir == 0x5C => steps.push(&[
+                FetchAddrLo,
+                FetchAddrHi,
+                ReadOperand,
+                DummyReadPc,
+                DummyReadPc,
+                DummyReadPc,
+                DummyReadPc,
+            ]),
+            _ => {
+                steps.push(instruction.mode.steps());
+                if instruction.mode.has_memory_operand() && instruction.op != JMP {
+                    match instruction.op.access() {
+                        Access::Read => steps.push(&[ReadOperand]),
+                        Access::Write => steps.push(&[WriteOperand]),
+                        Access::Modify => steps.push(&[ReadOperand, DummyWrite, WriteResult]),
+                    }
+                }
+                // The 65C02 takes an extra cycle to fix up decimal results.
+                if cmos && matches!(instruction.op, ADC | SBC) && self.state.get_flag(Flag::DEC) {
+                    self.steps.push(&[DummyReadPc]);
+                }
+            }
+        }
+    }
+
+    /// Value written by store instructions.
+    fn store_value(&self) -> Data {
+        let s = &self.state;
+        match self.instruction.op {
+            Operation::STX => s.x,
+            Operation::STY => s.y,
+            Operation::STZ => 0,
+            Operation::SAX => s.a & s.x,
+            Operation::SHA | Operation::TAS => self.and_high_byte(s.a & s.x),
+            Operation::SHX => self.and_high_byte(s.x),
+            Operation::SHY => self.and_high_byte(s.y),
+            _ => s.a,
+        }
+    }
+
+    /// Address written by store instructions. When SHA, SHX, SHY and TAS
+    /// cross a page boundary, the stored value replaces the high byte.
+    fn store_addr(&self) -> Address {
+        use Operation::*;
+        let mar = self.state.mar;
+        match self.instruction.op {
+            SHA | SHX | SHY | TAS if self.page_crossed => {
+                (self.store_value() as Address) << 8 | (mar & 0x00FF)
+            }
+            _ => mar,
+        }
+    }
+
+    /// AND a value with the high byte of the unindexed address plus one.
+    fn and_high_byte(&self, val: Data) -> Data {
+        if !self.unstable.and_high_byte {
+            return val;
+        }
+        let high = (self.state.mar >> 8) as Data;
+        let base_high = if self.page_crossed {
+            high.wrapping_sub(1)
+        } else {
+            high
+        };
+        val & base_high.wrapping_add(1)
+    }
+
+    /// Value pushed by PHA, PHP and BRK.
+    fn push_value(&self) -> Data {
+        match self.instruction.op {
+            Operation::PHA => self.state.a,
+            Operation::PHX => self.state.x,
+            Operation::PHY => self.state.y,
+            Operation::BRK if self.interrupt.is_some() => self.state.sr(),
+            _ => self.state.sr() | Flag::BRK.to_mask(),
+        }
+    }
+
+    /// Finish the current instruction once all its bus cycles are done.
+    fn execute(&mut self) {
+        use Operation::*;
+        let m = self.state.mdr;
+        match self.instruction.op {
+            ADC => self.add_with_carry(m),
+            SBC => self.subtract_with_borrow(m),
+            AND => self.load_a(self.state.a & m),
+            ORA => self.load_a(self.state.a | m),
+            EOR => self.load_a(self.state.a ^ m),
+            CMP => self.compare(self.state.a, m),
+            CPX => self.compare(self.state.x, m),
+            CPY => self.compare(self.state.y, m),
+            BIT if self.instruction.mode == AddressingMode::IMM => {
+                let s = &mut self.state;
+                s.set_flag(Flag::ZRO, s.a & m == 0);
+            }
+            BIT => {
+                let s = &mut self.state;
+                s.set_flag(Flag::ZRO, s.a & m == 0);
+                s.set_flag(Flag::NEG, m & 0x80 != 0);
+                s.set_flag(Flag::OVF, m & 0x40 != 0);
+            }
+            LDA | PLA => self.state.transfer(Target::MDR, Target::ACC),
+            LDX | PLX => self.state.transfer(Target::MDR, Target::X),
+            LDY | PLY => self.state.transfer(Target::MDR, Target::Y),
+            ASL | LSR | ROL | ROR | INC | DEC if self.instruction.mode == AddressingMode::ACC => {
+                self.state.a = self.modify(self.state.a)
+            }
+            INX => self.load_x(self.state.x.wrapping_add(1)),
+            INY => self.load_y(self.state.y.wrapping_add(1)),
+            DEX => self.load_x(self.state.x.wrapping_sub(1)),
+            DEY => self.load_y(self.state.y.wrapping_sub(1)),
+            TAX => self.state.transfer(Target::ACC, Target::X),
+            TAY => self.state.transfer(Target::ACC, Target::Y),
+            TXA => self.state.transfer(Target::X, Target::ACC),
+            TYA => self.state.transfer(Target::Y, Target::ACC),
+            TSX => self.state.transfer(Target::SP, Target::X),
+            TXS => self.state.transfer(Target::X, Target::SP),
+            CLC => self.state.set_flag(Flag::CRY, false),
+            CLD => self.state.set_flag(Flag::DEC, false),
+            CLI => self.state.set_flag(Flag::INT, false),
+            CLV => self.state.set_flag(Flag::OVF, false),
+            SEC => self.state.set_flag(Flag::CRY, true),
+            SED => self.state.set_flag(Flag::DEC, true),
+            SEI => self.state.set_flag(Flag::INT, true),
+            JMP | JSR => self.state.pc = self.state.mar,
+            BCC | BCS | BNE | BEQ | BPL | BMI | BVC | BVS => {}
+            ASL | LSR | ROL | ROR | INC | DEC | STA | STX | STY | PHA | PHP | PLP | RTI | RTS
+            | BRK | NOP => {}
+            SLO => self.load_a(self.state.a | m),
+            RLA => self.load_a(self.state.a & m),
+            SRE => self.load_a(self.state.a ^ m),
+            RRA => self.add_with_carry(m),
+            DCP => self.compare(self.state.a, m),
+            ISC => self.subtract_with_borrow(m),
+            LAX => {
+                self.state.x = m;
+                self.load_a(m);
+            }
+            LAS => {
+                let val = m & self.state.sp;
+                self.state.sp = val;
+                self.state.x = val;
+                self.load_a(val);
+            }
+            ANC => {
+                self.load_a(self.state.a & m);
+                let s = &mut self.state;
+                s.set_flag(Flag::CRY, s.get_flag(Flag::NEG));
+            }
+            ALR => {
+                let val = self.state.a & m;
+                self.state.set_flag(Flag::CRY, val & 0x01 != 0);
+                self.load_a(val >> 1);
+            }
+            ARR => self.and_rotate_right(m),
+            SBX => {
+                let val = self.state.a & self.state.x;
+                self.state.set_flag(Flag::CRY, val >= m);
+                self.load_x(val.wrapping_sub(m));
+            }
+            XAA => {
+                let s = &self.state;
+                let val = (s.a | self.unstable.xaa_magic) & s.x & m;
+                self.load_a(val);
+            }
+            LXA => {
+                let val = (self.state.a | self.unstable.lxa_magic) & m;
+                self.state.x = val;
+                self.load_a(val);
+            }
+            SAX | SHA | SHX | SHY | TAS | JAM => {}
+            BRA | BBR | BBS | PHX | PHY | STZ | TRB | TSB | RMB | SMB | WAI | STP => {}
+        }
+    }
+
+    /// ARR: AND, then ROR the accumulator. C and V come from bits 6 and 5
+    /// of the result; in decimal mode, the NMOS chip also applies a
+    /// half-baked BCD fixup to each nibble.
+    fn and_rotate_right(&mut self, m: Data) {
+        let carry = self.state.get_flag(Flag::CRY) as Data;
+        let val = self.state.a & m;
+        let rotated = val >> 1 | carry << 7;
+        if !self.decimal_mode() {
+            self.load_a(rotated);
+            self.state.set_flag(Flag::CRY, rotated & 0x40 != 0);
+            self.state
+                .set_flag(Flag::OVF, (rotated ^ rotated << 1) & 0x40 != 0);
+            return;
+        }
+        let s = &mut self.state;
+        s.set_flags_from_val(rotated);
+        s.set_flag(Flag::OVF, (val ^ rotated) & 0x40 != 0);
+        let mut result = rotated;
+        if (val & 0x0F) + (val & 0x01) > 0x05 {
+            result = (result & 0xF0) | (result.wrapping_add(0x06) & 0x0F);
+        }
+        let high_carry = (val & 0xF0) as Word + (val & 0x10) as Word > 0x50;
+        if high_carry {
+            result = result.wrapping_add(0x60);
+        }
+        s.set_flag(Flag::CRY, high_carry);
+        s.a = result;
+    }
+
+    fn load_a(&mut self, val: Data) {
+        self.state.a = val;
+        self.state.set_flags_from_val(val);
+    }
+
+    fn load_x(&mut self, val: Data) {
+        self.state.x = val;
+        self.state.set_flags_from_val(val);
+    }
+
+    fn load_y(&mut self, val: Data) {
+        self.state.y = val;
+        self.state.set_flags_from_val(val);
+    }
+
+    fn add_with_carry(&mut self, m: Data) {
+        if self.decimal_mode() {
+            self.decimal_add(m);
+        } else {
+            self.binary_add(m);
+        }
+    }
+
+    fn subtract_with_borrow(&mut self, m: Data) {
+        if self.decimal_mode() {
+            self.decimal_subtract(m);
+        } else {
+            self.binary_add(!m);
+        }
+    }
+
+    fn decimal_mode(&self) -> bool {
+        self.state.get_flag(Flag::DEC) && self.variant.has_decimal_mode()
+    }
+
+    fn binary_add(&mut self, m: Data) {
+        let a = self.state.a;
+        let carry = self.state.get_flag(Flag::CRY) as Word;
+        let sum = a as Word + m as Word + carry;
+        let result = sum as Data;
+        self.state.set_flag(Flag::CRY, sum > 0xFF);
+        self.state
+            .set_flag(Flag::OVF, (a ^ result) & (m ^ result) & 0x80 != 0);
+        self.load_a(result);
+    }
+
+    /// Decimal mode ADC. On NMOS chips, Z reflects the binary sum,
+    /// while N and V are taken from the sum before its high digit gets
+    /// adjusted. The 65C02 sets N and Z from the result. Invalid BCD
+    /// operands produce the same garbage as the real thing.
+    fn decimal_add(&mut self, m: Data) {
+        let a = self.state.a;
+        let carry = self.state.get_flag(Flag::CRY) as Word;
+        let binary = (a as Word + m as Word + carry) as Data;
+
+        let mut lo = (a & 0x0F) as Word + (m & 0x0F) as Word + carry;
+        if lo >= 0x0A {
+            lo = ((lo + 0x06) & 0x0F) + 0x10;
+        }
+        let mut sum = (a & 0xF0) as Word + (m & 0xF0) as Word + lo;
+        let signed = (a & 0xF0) as i8 as i16 + (m & 0xF0) as i8 as i16 + lo as i16;
+        self.state.set_flag(Flag::NEG, sum & 0x80 != 0);
+        self.state
+            .set_flag(Flag::OVF, !(-128..=127).contains(&signed));
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        self.state.set_flag(Flag::CRY, sum >= 0x100);
+        self.state.set_flag(Flag::ZRO, binary == 0);
+        self.state.a = sum as Data;
+        if self.variant.is_cmos() {
+            self.state.set_flags_from_val(sum as Data);
+        }
+    }
+
+    /// Decimal mode SBC. On NMOS chips, all flags are set as in binary
+    /// mode. The 65C02 adjusts the result differently and sets N and Z
+    /// from it.
+    fn decimal_subtract(&mut self, m: Data) {
+        let a = self.state.a;
+        let borrow = !self.state.get_flag(Flag::CRY) as i16;
+        self.binary_add(!m);
+
+        if self.variant.is_cmos() {
+            let lo = (a & 0x0F) as i16 - (m & 0x0F) as i16 - borrow;
+            let mut diff = a as i16 - m as i16 - borrow;
+            if diff < 0 {
+                diff -= 0x60;
+            }
+            if lo < 0 {
+                diff -= 0x06;
+            }
+            self.load_a(diff as Data);
+            return;
+        }
+
+        let mut lo = (a & 0x0F) as i16 - (m & 0x0F) as i16 - borrow;
+        if lo < 0 {
+            lo = ((lo - 0x06) & 0x0F) - 0x10;
+        }
+        let mut diff = (a & 0xF0) as i16 - (m & 0xF0) as i16 + lo;
+        if diff < 0 {
+            diff -= 0x60;
+        }
+        self.state.a = diff as Data;
+    }
+
+    fn compare(&mut self, reg: Data, m: Data) {
+        self.state.set_flag(Flag::CRY, reg >= m);
+        self.state.set_flags_from_val(reg.wrapping_sub(m));
+    }
+
+    /// Whether the current branch instruction is taken.
+    fn branch_condition(&self) -> bool {
+        let s = &self.state;
+        match self.instruction.op {
+            Operation::BCC => !s.get_flag(Flag::CRY),
+            Operation::BCS => s.get_flag(Flag::CRY),
+            Operation::BNE => !s.get_flag(Flag::ZRO),
+            Operation::BEQ => s.get_flag(Flag::ZRO),
+            Operation::BPL => !s.get_flag(Flag::NEG),
+            Operation::BMI => s.get_flag(Flag::NEG),
+            Operation::BVC => !s.get_flag(Flag::OVF),
+            Operation::BVS => s.get_flag(Flag::OVF),
+            Operation::BRA => true,
+            Operation::BBR => self.tested & self.bit_mask() == 0,
+            Operation::BBS => self.tested & self.bit_mask() != 0,
+            _ => false,
+        }
+    }
+
+    /// Bit addressed by RMB, SMB, BBR and BBS opcodes.
+    fn bit_mask(&self) -> Data {
+        1 << (self.state.ir >> 4 & 0x07)
+    }
+
+    /// Apply a read-modify-write operation to a value.
+    fn modify(&mut self, val: Data) -> Data {
+        let a = self.state.a;
+        match self.instruction.op {
+            Operation::TSB | Operation::TRB => {
+                self.state.set_flag(Flag::ZRO, a & val == 0);
+                return if self.instruction.op == Operation::TSB {
+                    val | a
+                } else {
+                    val & !a
+                };
+            }
+            Operation::RMB => return val & !self.bit_mask(),
+            Operation::SMB => return val | self.bit_mask(),
+            _ => {}
+        }
+        let carry = self.state.get_flag(Flag::CRY) as Data;
+        let (result, carry_out) = match self.instruction.op {
+            Operation::ASL | Operation::SLO => (val << 1, Some(val & 0x80 != 0)),
+            Operation::LSR | Operation::SRE => (val >> 1, Some(val & 0x01 != 0)),
+            Operation::ROL | Operation::RLA => (val << 1 | carry, Some(val & 0x80 != 0)),
+            Operation::ROR | Operation::RRA => (val >> 1 | carry << 7, Some(val & 0x01 != 0)),
+            Operation::INC | Operation::ISC => (val.wrapping_add(1), None),
+            Operation::DEC | Operation::DCP => (val.wrapping_sub(1), None),
+            _ => (val, None),
+        };
+        if let Some(c) = carry_out {
+            self.state.set_flag(Flag::CRY, c);
+        }
+        self.state.set_flags_from_val(result);
+        result
+    }
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
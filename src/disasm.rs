@@ -0,0 +1,152 @@
+use crate::cpu::*;
+use crate::memory::*;
+use crate::types::*;
+
+/// An instruction decoded from memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Disassembled {
+    pub addr: Address,
+    pub opcode: Data,
+    pub instruction: Instruction,
+    operands: [Data; 2],
+}
+
+impl Disassembled {
+    /// Operand bytes following the opcode.
+    pub fn operands(&self) -> &[Data] {
+        &self.operands[..self.instruction.mode.operand_len()]
+    }
+
+    pub fn size(&self) -> usize {
+        self.instruction.size()
+    }
+
+    /// Address of the instruction after this one.
+    pub fn next(&self) -> Address {
+        self.addr.wrapping_add(self.size() as Address)
+    }
+
+    /// Assembler syntax, like `LDA ($12),Y`.
+    pub fn text(&self) -> String {
+        format_instruction(self.addr, self.opcode, self.instruction, self.operands())
+    }
+}
+
+/// A listing line: address, bytes and assembler syntax.
+impl std::fmt::Display for Disassembled {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let bytes: Vec<String> = std::iter::once(&self.opcode)
+            .chain(self.operands())
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        write!(
+            f,
+            "{:04X}  {:<8}  {}",
+            self.addr,
+            bytes.join(" "),
+            self.text()
+        )
+    }
+}
+
+/// Executed instructions print like disassembled ones, and interrupt
+/// sequences by name.
+impl std::fmt::Display for Executed {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.outcome {
+            StepOutcome::Interrupted(interrupt) => write!(f, "{:?}", interrupt),
+            _ => f.write_str(&format_instruction(
+                self.pc,
+                self.opcode,
+                self.instruction,
+                self.operands(),
+            )),
+        }
+    }
+}
+
+/// Decode the instruction at `addr` as `variant` does.
+/// Addresses nothing responds to read as zero.
+pub fn disassemble_at<M: Memory + ?Sized>(
+    variant: Variant,
+    mem: &M,
+    addr: Address,
+) -> Disassembled {
+    let byte = |offset: Address| mem.read(&addr.wrapping_add(offset)).unwrap_or(0);
+    let opcode = byte(0);
+    let instruction = variant.decode(opcode);
+    let mut operands = [0; 2];
+    for (i, operand) in operands
+        .iter_mut()
+        .take(instruction.mode.operand_len())
+        .enumerate()
+    {
+        *operand = byte(1 + i as Address);
+    }
+    Disassembled {
+        addr,
+        opcode,
+        instruction,
+        operands,
+    }
+}
+
+/// Decode all instructions starting between `start` and `end`, inclusive.
+pub fn disassemble<M: Memory + ?Sized>(
+    variant: Variant,
+    mem: &M,
+    start: Address,
+    end: Address,
+) -> Vec<Disassembled> {
+    let mut lines = Vec::new();
+    let mut addr = start as u32;
+    while addr <= end as u32 {
+        let line = disassemble_at(variant, mem, addr as Address);
+        addr += line.size() as u32;
+        lines.push(line);
+    }
+    lines
+}
+
+/// Assembler syntax of an instruction at `addr`. Branch targets are shown
+/// as addresses, and the bit numbers of RMB, SMB, BBR and BBS are part of
+/// the mnemonic, like in `BBS3 $12,$0610`.
+pub fn format_instruction(
+    addr: Address,
+    opcode: Data,
+    instruction: Instruction,
+    operands: &[Data],
+) -> String {
+    use AddressingMode::*;
+    use Operation::*;
+    let byte = |i: usize| operands.get(i).copied().unwrap_or(0);
+    let word = Address::from_le_bytes([byte(0), byte(1)]);
+    let branch = |offset: Data, size: Address| {
+        addr.wrapping_add(size)
+            .wrapping_add(offset as i8 as Address)
+    };
+
+    let mut mnemonic = format!("{:?}", instruction.op);
+    if matches!(instruction.op, RMB | SMB | BBR | BBS) {
+        mnemonic.push(char::from(b'0' + (opcode >> 4 & 7)));
+    }
+    let operand = match instruction.mode {
+        IMP => return mnemonic,
+        ACC => "A".to_string(),
+        IMM => format!("#${:02X}", byte(0)),
+        ZPG => format!("${:02X}", byte(0)),
+        ZPX => format!("${:02X},X", byte(0)),
+        ZPY => format!("${:02X},Y", byte(0)),
+        ABS => format!("${:04X}", word),
+        ABX => format!("${:04X},X", word),
+        ABY => format!("${:04X},Y", word),
+        IND => format!("(${:04X})", word),
+        IZX => format!("(${:02X},X)", byte(0)),
+        IZY => format!("(${:02X}),Y", byte(0)),
+        REL => format!("${:04X}", branch(byte(0), 2)),
+        IZP => format!("(${:02X})", byte(0)),
+        IAX => format!("(${:04X},X)", word),
+        ZPR => format!("${:02X},${:04X}", byte(0), branch(byte(1), 3)),
+    };
+    format!("{} {}", mnemonic, operand)
+}
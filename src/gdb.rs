@@ -0,0 +1,483 @@
+//! A GDB remote serial protocol stub, so debugger frontends can drive the
+//! CPU over TCP.
+//!
+//! Registers are PC (16 bits), then SP, A, X, Y and P (8 bits each), as
+//! described by the target XML the stub serves. Software and hardware
+//! breakpoints map to `Cpu` breakpoints, and write, read and access
+//! watchpoints to its watchpoints.
+
+use crate::breakpoints::*;
+use crate::cpu::*;
+use crate::memory::*;
+use crate::types::*;
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.luvemix.6502">
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="a" bitsize="8" type="uint8"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// Byte GDB sends to interrupt a running target.
+const INTERRUPT: u8 = 0x03;
+
+/// Steps to run between checks for an interrupt from GDB.
+const POLL_STEPS: usize = 1024;
+
+/// Signal numbers for stop replies.
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// Error replies.
+const EINVAL: &str = "E16";
+const EFAULT: &str = "E0e";
+
+/// A CPU and its memory, driven by a GDB session.
+pub struct GdbStub<M: Memory> {
+    pub cpu: Cpu,
+    pub mem: M,
+    stop: String,
+}
+
+/// One end of a connection, framing packets.
+struct Connection {
+    stream: TcpStream,
+    /// Whether packets are acknowledged, as they are until GDB asks for
+    /// no-ack mode.
+    ack: bool,
+    /// Bytes received while checking for interrupts, to be read first.
+    pending: VecDeque<u8>,
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0, |sum, b| sum.wrapping_add(b))
+}
+
+fn hex_bytes(bytes: &[Data]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<Data>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| Data::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+/// An address and a length, like `0200,10`.
+fn parse_range(text: &str) -> Option<(Address, u32)> {
+    let (addr, len) = text.split_once(',')?;
+    let (addr, len) = (parse_hex(addr)?, parse_hex(len)?);
+    match addr.checked_add(len)? <= 0x10000 {
+        true => Some((addr as Address, len)),
+        false => None,
+    }
+}
+
+impl Connection {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        if let Some(byte) = self.pending.pop_front() {
+            return Ok(Some(byte));
+        }
+        let mut byte = [0];
+        loop {
+            match self.stream.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// The next packet's contents, or None when GDB hung up. Packets with
+    /// bad checksums are asked for again.
+    fn read_packet(&mut self) -> std::io::Result<Option<String>> {
+        loop {
+            // Skip acknowledgements, and interrupts while not running.
+            loop {
+                match self.read_byte()? {
+                    Some(b'$') => break,
+                    Some(_) => {}
+                    None => return Ok(None),
+                }
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+            let mut sum = [0; 2];
+            for digit in sum.iter_mut() {
+                match self.read_byte()? {
+                    Some(byte) => *digit = byte,
+                    None => return Ok(None),
+                }
+            }
+            let data = String::from_utf8_lossy(&data).into_owned();
+            let expected = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|sum| Data::from_str_radix(sum, 16).ok());
+            if expected == Some(checksum(&data)) {
+                if self.ack {
+                    self.stream.write_all(b"+")?;
+                }
+                return Ok(Some(data));
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    /// Send a packet, again until GDB acknowledges it.
+    fn send(&mut self, data: &str) -> std::io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum(data));
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+            if !self.ack {
+                return Ok(());
+            }
+            let mut other = Vec::new();
+            let acked = loop {
+                match self.read_byte()? {
+                    Some(b'+') | None => break true,
+                    Some(b'-') => break false,
+                    Some(byte) => other.push(byte),
+                }
+            };
+            // Keep whatever came ahead of the acknowledgement, like a
+            // packet sent while the CPU was running.
+            for byte in other.into_iter().rev() {
+                self.pending.push_front(byte);
+            }
+            if acked {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Whether GDB asked to stop, or went away. Anything else it sent is
+    /// kept for reading later.
+    fn interrupted(&mut self) -> std::io::Result<bool> {
+        let mut bytes = [0; 64];
+        self.stream.set_nonblocking(true)?;
+        let result = self.stream.read(&mut bytes);
+        self.stream.set_nonblocking(false)?;
+        let len = match result {
+            Ok(0) => return Ok(true),
+            Ok(len) => len,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        let mut interrupted = false;
+        for byte in &bytes[..len] {
+            match *byte {
+                INTERRUPT => interrupted = true,
+                byte => self.pending.push_back(byte),
+            }
+        }
+        Ok(interrupted)
+    }
+}
+
+impl<M: Memory> GdbStub<M> {
+    pub fn new(cpu: Cpu, mem: M) -> Self {
+        GdbStub {
+            cpu,
+            mem,
+            stop: format!("S{:02x}", SIGTRAP),
+        }
+    }
+
+    /// Handle one GDB session, until it detaches, kills the target or
+    /// hangs up.
+    pub fn serve(&mut self, stream: TcpStream) -> std::io::Result<()> {
+        stream.set_nodelay(true)?;
+        let mut conn = Connection {
+            stream,
+            ack: true,
+            pending: VecDeque::new(),
+        };
+        while let Some(packet) = conn.read_packet()? {
+            let reply = match packet.as_bytes().first() {
+                Some(b'k') => return Ok(()),
+                Some(b'D') => {
+                    conn.send("OK")?;
+                    return Ok(());
+                }
+                Some(b'c') | Some(b's') => self.resume(&packet, &mut conn)?,
+                _ => self.reply(&packet),
+            };
+            conn.send(&reply)?;
+            if packet == "QStartNoAckMode" {
+                conn.ack = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// The reply to a packet that does not run the CPU.
+    fn reply(&mut self, packet: &str) -> String {
+        if !packet.is_char_boundary(1) {
+            return String::new();
+        }
+        let (kind, args) = packet.split_at(1);
+        let reply = match kind {
+            "?" => Some(self.stop.clone()),
+            "g" => Some(self.read_registers()),
+            "G" => self.write_registers(args),
+            "p" => parse_hex(args).and_then(|reg| self.read_register(reg)),
+            "P" => args.split_once('=').and_then(|(reg, val)| {
+                let bytes = parse_hex_bytes(val)?;
+                self.write_register(parse_hex(reg)?, &bytes)
+            }),
+            "m" => return self.read_memory(args),
+            "M" => return self.write_memory(args),
+            "Z" | "z" => self.breakpoint(kind == "Z", args),
+            "H" => Some("OK".to_string()),
+            "q" | "Q" => return self.query(packet),
+            _ => return String::new(),
+        };
+        reply.unwrap_or_else(|| EINVAL.to_string())
+    }
+
+    fn query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+".to_string();
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let (offset, len) = match range.split_once(',') {
+                Some((offset, len)) => (parse_hex(offset), parse_hex(len)),
+                None => (None, None),
+            };
+            return match (offset, len) {
+                (Some(offset), Some(len)) => {
+                    let start = (offset as usize).min(TARGET_XML.len());
+                    let end = (start + len as usize).min(TARGET_XML.len());
+                    let more = if end < TARGET_XML.len() { "m" } else { "l" };
+                    format!("{}{}", more, &TARGET_XML[start..end])
+                }
+                _ => EINVAL.to_string(),
+            };
+        }
+        match packet {
+            "QStartNoAckMode" => "OK".to_string(),
+            "qAttached" => "1".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn registers(&self) -> [Data; 7] {
+        let state = self.cpu.state();
+        let [pc_lo, pc_hi] = state.pc.to_le_bytes();
+        [
+            pc_lo,
+            pc_hi,
+            state.sp,
+            state.a,
+            state.x,
+            state.y,
+            state.sr(),
+        ]
+    }
+
+    /// Byte offset and size of register `reg` in the `g` packet.
+    fn register_bytes(reg: u32) -> Option<(usize, usize)> {
+        match reg {
+            0 => Some((0, 2)),
+            1..=5 => Some((reg as usize + 1, 1)),
+            _ => None,
+        }
+    }
+
+    fn set_registers(&mut self, bytes: &[Data; 7]) {
+        let state = self.cpu.state_mut();
+        state.pc = Address::from_le_bytes([bytes[0], bytes[1]]);
+        state.sp = bytes[2];
+        state.a = bytes[3];
+        state.x = bytes[4];
+        state.y = bytes[5];
+        state.set_sr(bytes[6]);
+    }
+
+    fn read_registers(&self) -> String {
+        hex_bytes(&self.registers())
+    }
+
+    fn write_registers(&mut self, args: &str) -> Option<String> {
+        let bytes = parse_hex_bytes(args)?;
+        self.set_registers(bytes.as_slice().try_into().ok()?);
+        Some("OK".to_string())
+    }
+
+    fn read_register(&self, reg: u32) -> Option<String> {
+        let (offset, size) = Self::register_bytes(reg)?;
+        Some(hex_bytes(&self.registers()[offset..offset + size]))
+    }
+
+    fn write_register(&mut self, reg: u32, bytes: &[Data]) -> Option<String> {
+        let (offset, size) = Self::register_bytes(reg)?;
+        if bytes.len() != size {
+            return None;
+        }
+        let mut registers = self.registers();
+        registers[offset..offset + size].copy_from_slice(bytes);
+        self.set_registers(&registers);
+        Some("OK".to_string())
+    }
+
+    /// `m ADDR,LEN`. Addresses nothing responds to read as zero.
+    fn read_memory(&self, args: &str) -> String {
+        let (addr, len) = match parse_range(args) {
+            Some(range) => range,
+            None => return EINVAL.to_string(),
+        };
+        let mut bytes = Vec::new();
+        for i in 0..len {
+            match self.mem.try_read(&(addr + i as Address)) {
+                Ok(val) => bytes.push(val.unwrap_or(0)),
+                Err(_) => return EFAULT.to_string(),
+            }
+        }
+        hex_bytes(&bytes)
+    }
+
+    /// `M ADDR,LEN:BYTES`.
+    fn write_memory(&mut self, args: &str) -> String {
+        let parsed = args.split_once(':').and_then(|(range, data)| {
+            let (addr, len) = parse_range(range)?;
+            let bytes = parse_hex_bytes(data)?;
+            (bytes.len() == len as usize).then_some((addr, bytes))
+        });
+        let (addr, bytes) = match parsed {
+            Some(parsed) => parsed,
+            None => return EINVAL.to_string(),
+        };
+        for (i, val) in bytes.iter().enumerate() {
+            if self.mem.try_write(addr + i as Address, *val).is_err() {
+                return EFAULT.to_string();
+            }
+        }
+        "OK".to_string()
+    }
+
+    /// `Z TYPE,ADDR,KIND` and `z TYPE,ADDR,KIND`. For breakpoints, KIND
+    /// is a hint at the instruction size, and only ADDR matters. For
+    /// watchpoints, it is the number of bytes watched.
+    fn breakpoint(&mut self, insert: bool, args: &str) -> Option<String> {
+        let (kind, range) = args.split_once(',')?;
+        // Ignore conditions and commands GDB may append after a `;`.
+        let range = range.split(';').next()?;
+        let access = match kind {
+            "0" | "1" => None,
+            "2" => Some(Access::WRITE),
+            "3" => Some(Access::READ),
+            "4" => Some(Access::ANY),
+            _ => return Some(String::new()),
+        };
+        let breakpoints = self.cpu.breakpoints_mut();
+        match access {
+            None => {
+                let (addr, _) = range.split_once(',')?;
+                let addr = parse_hex(addr).filter(|addr| *addr <= 0xFFFF)? as Address;
+                match insert {
+                    true => breakpoints.add(addr),
+                    false => {
+                        breakpoints.remove(addr);
+                    }
+                }
+            }
+            Some(access) => {
+                let (addr, len) = parse_range(range)?;
+                let end = addr.checked_add((len.max(1) - 1) as Address)?;
+                match insert {
+                    true => breakpoints.watch(Watchpoint::new(addr, end, access)),
+                    false => {
+                        breakpoints.unwatch(addr, end);
+                    }
+                }
+            }
+        }
+        Some("OK".to_string())
+    }
+
+    /// `c [ADDR]` and `s [ADDR]`: run or step, and reply how it stopped.
+    fn resume(&mut self, packet: &str, conn: &mut Connection) -> std::io::Result<String> {
+        let (kind, addr) = packet.split_at(1);
+        if !addr.is_empty() {
+            match parse_hex(addr).filter(|addr| *addr <= 0xFFFF) {
+                Some(addr) => self.cpu.state_mut().pc = addr as Address,
+                None => return Ok(EINVAL.to_string()),
+            }
+        }
+        let single = kind == "s";
+        let mut steps: usize = 0;
+        self.stop = loop {
+            match self.cpu.step(&mut self.mem) {
+                Ok(StepOutcome::Break(Hit::Watchpoint { addr, mode, .. })) => {
+                    break format!(
+                        "T{:02x}{}:{:04x};",
+                        SIGTRAP,
+                        self.watch_kind(addr, mode),
+                        addr
+                    )
+                }
+                Ok(StepOutcome::Break(_)) | Ok(StepOutcome::Halted) => {
+                    break format!("S{:02x}", SIGTRAP)
+                }
+                Ok(_) if single => break format!("S{:02x}", SIGTRAP),
+                Ok(_) => {}
+                Err(EmuError::Jam { .. }) => break format!("S{:02x}", SIGILL),
+                Err(_) => break format!("S{:02x}", SIGSEGV),
+            }
+            steps += 1;
+            if steps.is_multiple_of(POLL_STEPS) && conn.interrupted()? {
+                break format!("S{:02x}", SIGINT);
+            }
+        };
+        Ok(self.stop.clone())
+    }
+
+    /// How GDB calls the kind of watchpoint that caught an access.
+    fn watch_kind(&self, addr: Address, mode: BusMode) -> &'static str {
+        let exact = match mode {
+            BusMode::READ => Access::READ,
+            BusMode::WRITE => Access::WRITE,
+        };
+        let exact_hit = self
+            .cpu
+            .breakpoints()
+            .watchpoints()
+            .iter()
+            .any(|watchpoint| {
+                watchpoint.access == exact && (watchpoint.start..=watchpoint.end).contains(&addr)
+            });
+        match (exact_hit, mode) {
+            (true, BusMode::WRITE) => "watch",
+            (true, BusMode::READ) => "rwatch",
+            (false, _) => "awatch",
+        }
+    }
+}
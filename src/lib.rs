@@ -0,0 +1,14 @@
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod asm;
+pub mod breakpoints;
+pub mod bus;
+pub mod cpu;
+pub mod disasm;
+pub mod gdb;
+pub mod loader;
+pub mod mapper;
+pub mod memory;
+pub mod monitor;
+pub mod runner;
+pub mod types;
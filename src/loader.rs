@@ -0,0 +1,299 @@
+use crate::cpu::EmuError;
+use crate::memory::*;
+use crate::types::*;
+
+/// File formats images can be loaded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Raw bytes, loaded at an address given separately.
+    BIN,
+    /// Intel HEX
+    HEX,
+    /// Motorola S-records (S19, S28, S37)
+    SREC,
+    /// Commodore program: a little-endian load address, then the bytes.
+    PRG,
+}
+
+impl Format {
+    /// Guess the format from a file name, falling back to the contents.
+    /// PRG files can only be told apart by their name.
+    pub fn detect(name: &str, bytes: &[Data]) -> Format {
+        let ext = name
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("hex") | Some("ihx") | Some("ihex") => return Format::HEX,
+            Some("s19") | Some("s28") | Some("s37") | Some("srec") | Some("mot") => {
+                return Format::SREC
+            }
+            Some("prg") => return Format::PRG,
+            _ => {}
+        }
+        let is_text = bytes
+            .iter()
+            .all(|b| b.is_ascii_graphic() || b.is_ascii_whitespace());
+        match bytes {
+            [b':', ..] if is_text => Format::HEX,
+            [b'S', b'0'..=b'9', ..] if is_text => Format::SREC,
+            _ => Format::BIN,
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = EmuError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "bin" | "raw" => Ok(Format::BIN),
+            "hex" | "ihex" => Ok(Format::HEX),
+            "srec" | "s19" | "s28" | "s37" => Ok(Format::SREC),
+            "prg" => Ok(Format::PRG),
+            _ => Err(EmuError::Config(format!("unknown image format: {}", name))),
+        }
+    }
+}
+
+/// Errors found while loading an image. Lines count from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// A line that is not a valid record.
+    Syntax { line: usize, msg: String },
+    /// A record whose checksum does not match its contents.
+    Checksum {
+        line: usize,
+        expected: Data,
+        found: Data,
+    },
+    /// Data beyond the 64 KiB address space.
+    OutOfRange(u32),
+    /// A PRG file too short to hold its load address.
+    MissingHeader,
+    /// Intel HEX without an end-of-file record.
+    MissingEnd,
+    /// The memory refused the data.
+    Memory(MemoryError),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadError::Syntax { line, msg } => write!(f, "line {}: {}", line, msg),
+            LoadError::Checksum {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: checksum is ${:02X}, expected ${:02X}",
+                line, found, expected
+            ),
+            LoadError::OutOfRange(addr) => write!(f, "data at ${:X} beyond 64 KiB", addr),
+            LoadError::MissingHeader => write!(f, "PRG file without load address"),
+            LoadError::MissingEnd => write!(f, "no end-of-file record"),
+            LoadError::Memory(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// What ended up where.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Loaded {
+    /// Lowest address written to, if any.
+    pub start: Option<Address>,
+    /// Highest address written to, if any.
+    pub end: Option<Address>,
+    /// Number of bytes written.
+    pub len: usize,
+    /// Where to start executing, if the image says so.
+    pub entry: Option<Address>,
+}
+
+impl Loaded {
+    fn write(&mut self, mem: &mut impl Memory, addr: u32, data: &[Data]) -> Result<(), LoadError> {
+        let end = match addr.checked_add(data.len() as u32) {
+            Some(end) if end <= 0x10000 => end,
+            _ => return Err(LoadError::OutOfRange(addr.max(0x10000))),
+        };
+        for (i, val) in data.iter().enumerate() {
+            let addr = (addr + i as u32) as Address;
+            mem.try_write(addr, *val).map_err(LoadError::Memory)?;
+        }
+        if !data.is_empty() {
+            let (first, last) = (addr as Address, (end - 1) as Address);
+            self.start = Some(self.start.map_or(first, |start| start.min(first)));
+            self.end = Some(self.end.map_or(last, |end| end.max(last)));
+            self.len += data.len();
+        }
+        Ok(())
+    }
+}
+
+/// Load an image in any format. `addr` is only used for raw binaries.
+pub fn load(
+    format: Format,
+    bytes: &[Data],
+    addr: Address,
+    mem: &mut impl Memory,
+) -> Result<Loaded, LoadError> {
+    match format {
+        Format::BIN => load_bin(bytes, addr, mem),
+        Format::HEX => load_hex(&String::from_utf8_lossy(bytes), mem),
+        Format::SREC => load_srec(&String::from_utf8_lossy(bytes), mem),
+        Format::PRG => load_prg(bytes, mem),
+    }
+}
+
+pub fn load_bin(bytes: &[Data], addr: Address, mem: &mut impl Memory) -> Result<Loaded, LoadError> {
+    let mut loaded = Loaded::default();
+    loaded.write(mem, addr as u32, bytes)?;
+    Ok(loaded)
+}
+
+pub fn load_prg(bytes: &[Data], mem: &mut impl Memory) -> Result<Loaded, LoadError> {
+    match bytes {
+        [lo, hi, data @ ..] => load_bin(data, Address::from_le_bytes([*lo, *hi]), mem),
+        _ => Err(LoadError::MissingHeader),
+    }
+}
+
+/// Decode the hex digits of a record, after its start marker.
+fn record_bytes(line: usize, digits: &str) -> Result<Vec<Data>, LoadError> {
+    let syntax = |msg: &str| LoadError::Syntax {
+        line,
+        msg: msg.to_string(),
+    };
+    if !digits.len().is_multiple_of(2) {
+        return Err(syntax("odd number of hex digits"));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            digits
+                .get(i..i + 2)
+                .and_then(|pair| Data::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| syntax("invalid hex digit"))
+        })
+        .collect()
+}
+
+fn check_sum(line: usize, expected: Data, found: Data) -> Result<(), LoadError> {
+    match expected == found {
+        true => Ok(()),
+        false => Err(LoadError::Checksum {
+            line,
+            expected,
+            found,
+        }),
+    }
+}
+
+fn sum(bytes: &[Data]) -> Data {
+    bytes.iter().fold(0, |sum, b| sum.wrapping_add(*b))
+}
+
+/// An entry point, which has to be in the 64 KiB address space.
+fn entry(addr: u32) -> Result<Address, LoadError> {
+    match addr {
+        0..=0xFFFF => Ok(addr as Address),
+        _ => Err(LoadError::OutOfRange(addr)),
+    }
+}
+
+/// Load Intel HEX: data, end-of-file, extended segment and linear
+/// address records, and start address records giving the entry point.
+pub fn load_hex(text: &str, mem: &mut impl Memory) -> Result<Loaded, LoadError> {
+    let mut loaded = Loaded::default();
+    let mut base: u32 = 0;
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let syntax = |msg: &str| LoadError::Syntax {
+            line: line_no,
+            msg: msg.to_string(),
+        };
+        let digits = line
+            .strip_prefix(':')
+            .ok_or_else(|| syntax("record does not start with ':'"))?;
+        let bytes = record_bytes(line_no, digits)?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(syntax("record length does not match"));
+        }
+        let (record, checksum) = bytes.split_at(bytes.len() - 1);
+        check_sum(line_no, sum(record).wrapping_neg(), checksum[0])?;
+
+        let offset = u16::from_be_bytes([record[1], record[2]]) as u32;
+        let data = &record[4..];
+        match (record[3], data) {
+            (0x00, _) => loaded.write(mem, base + offset, data)?,
+            (0x01, _) => return Ok(loaded),
+            (0x02, [hi, lo]) => base = (u16::from_be_bytes([*hi, *lo]) as u32) << 4,
+            (0x04, [hi, lo]) => base = (u16::from_be_bytes([*hi, *lo]) as u32) << 16,
+            (0x03, [cs_hi, cs_lo, ip_hi, ip_lo]) => {
+                let cs = u16::from_be_bytes([*cs_hi, *cs_lo]) as u32;
+                let ip = u16::from_be_bytes([*ip_hi, *ip_lo]) as u32;
+                loaded.entry = Some(entry((cs << 4) + ip)?);
+            }
+            (0x05, [b3, b2, b1, b0]) => {
+                loaded.entry = Some(entry(u32::from_be_bytes([*b3, *b2, *b1, *b0]))?);
+            }
+            (0x02..=0x05, _) => return Err(syntax("wrong record length for its type")),
+            (kind, _) => return Err(syntax(&format!("unknown record type {:02X}", kind))),
+        }
+    }
+    Err(LoadError::MissingEnd)
+}
+
+/// Load Motorola S-records. S1/S2/S3 carry data with 16, 24 and 32-bit
+/// addresses, S9/S8/S7 the entry point. Header and count records are
+/// checked, but otherwise ignored.
+pub fn load_srec(text: &str, mem: &mut impl Memory) -> Result<Loaded, LoadError> {
+    let mut loaded = Loaded::default();
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let syntax = |msg: &str| LoadError::Syntax {
+            line: line_no,
+            msg: msg.to_string(),
+        };
+        let kind = match line.as_bytes() {
+            [b'S', kind @ b'0'..=b'9', ..] => kind - b'0',
+            _ => return Err(syntax("record does not start with 'S' and a digit")),
+        };
+        let bytes = record_bytes(line_no, &line[2..])?;
+        if bytes.len() < 2 || bytes.len() != bytes[0] as usize + 1 {
+            return Err(syntax("record length does not match"));
+        }
+        let (record, checksum) = bytes.split_at(bytes.len() - 1);
+        check_sum(line_no, !sum(record), checksum[0])?;
+
+        let addr_len = match kind {
+            0 | 1 | 5 | 9 => 2,
+            2 | 6 | 8 => 3,
+            3 | 7 => 4,
+            _ => return Err(syntax(&format!("unknown record type S{}", kind))),
+        };
+        if record.len() < 1 + addr_len {
+            return Err(syntax("record too short for its address"));
+        }
+        let addr = record[1..=addr_len]
+            .iter()
+            .fold(0u32, |addr, b| addr << 8 | *b as u32);
+        let data = &record[1 + addr_len..];
+        match kind {
+            1..=3 => loaded.write(mem, addr, data)?,
+            7..=9 => loaded.entry = Some(entry(addr)?),
+            _ => {}
+        }
+    }
+    Ok(loaded)
+}
@@ -1,297 +1,305 @@
-mod types {
-    pub type Byte = u8;
-    pub type Word = u16;
-    pub type Address = Word;
-    pub type Data = Byte;
-    pub type Flags = Data;
-
-    pub const DATA_WIDTH: u8 = 8;
+use luvemix_rust::bus::*;
+use luvemix_rust::cpu::*;
+use luvemix_rust::disasm::*;
+use luvemix_rust::gdb::*;
+use luvemix_rust::loader::*;
+use luvemix_rust::memory::*;
+use luvemix_rust::monitor::*;
+use luvemix_rust::runner::*;
+use luvemix_rust::types::*;
+
+const USAGE: &str = "\
+usage: luvemix run IMAGE [options]
+       luvemix disasm IMAGE [options]
+       luvemix debug IMAGE [options]
+       luvemix gdb IMAGE [options]
+
+Load an image into RAM, then run, disassemble or debug it. The debugger
+reads commands from standard input; type help for a list. The gdb command
+waits for one GDB remote protocol connection on localhost instead.
+
+options:
+    --format FORMAT    bin, hex (Intel HEX), srec (S19/S28/S37) or prg;
+                       detected from the file name or contents by default
+    --load ADDR        where to load a raw binary image (default 0x0000)
+    --cpu VARIANT      nmos, cmos, rockwell, wdc or 2a03 (default nmos)
+
+run, debug and gdb options:
+    --start ADDR       where to start; without it, the entry point in the
+                       image or else the reset vector is used
+
+run options:
+    --max-cycles N     stop after N cycles
+    --trap ADDR        stop when PC reaches ADDR; may be given more than once
+    --dump START:END   print memory from START to END when done
+
+disasm options:
+    --range START:END  what to disassemble (default: all of the image)
+
+gdb options:
+    --port PORT        TCP port to listen on (default 6502)
+
+Numbers are decimal, or hexadecimal with a 0x or $ prefix.
+Running stops at BRK, JAM, STP, WAI, a trap address or the cycle limit.";
+
+const RUN_OPTIONS: &[&str] = &[
+    "--format",
+    "--load",
+    "--cpu",
+    "--start",
+    "--max-cycles",
+    "--trap",
+    "--dump",
+];
+const DISASM_OPTIONS: &[&str] = &["--format", "--load", "--cpu", "--range"];
+const DEBUG_OPTIONS: &[&str] = &["--format", "--load", "--cpu", "--start"];
+const GDB_OPTIONS: &[&str] = &["--format", "--load", "--cpu", "--start", "--port"];
+
+/// Settings for all commands.
+#[derive(Debug)]
+struct Options {
+    image: String,
+    format: Option<Format>,
+    load: Address,
+    start: Option<Address>,
+    variant: Variant,
+    max_cycles: Option<u64>,
+    traps: Vec<Address>,
+    dump: Option<(Address, Address)>,
+    range: Option<(Address, Address)>,
+    port: u16,
 }
 
-mod cpu {
-
-    use crate::types::*;
-
-    pub enum Flag {
-        /// Zero
-        ZRO = 0b0000_0010,
+fn config_error(msg: String) -> EmuError {
+    EmuError::Config(msg)
+}
 
-        /// Negative
-        NEG = 0b1000_0000,
-    }
+fn parse_number(text: &str) -> Result<u64, EmuError> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix('$')) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| config_error(format!("not a number: {}", text)))
+}
 
-    impl Flag {
-        pub fn to_mask(self) -> Flags {
-            self as Flags
-        }
+fn parse_address(text: &str) -> Result<Address, EmuError> {
+    match parse_number(text)? {
+        addr if addr <= Address::MAX as u64 => Ok(addr as Address),
+        _ => Err(config_error(format!("address out of range: {}", text))),
     }
+}
 
-    /// Sources and destinations when transferring data between registers.
-    pub enum Target {
-        ACC,
-        MDR,
+fn parse_port(text: &str) -> Result<u16, EmuError> {
+    match parse_number(text)? {
+        port if port <= u16::MAX as u64 => Ok(port as u16),
+        _ => Err(config_error(format!("port out of range: {}", text))),
     }
+}
 
-    #[derive(Debug)]
-    pub struct CpuState {
-        /// Program Counter
-        pub pc: Address,
-
-        /// Stack Pointer
-        pub sp: Address,
-
-        /// Accumulator Register
-        pub a: Data,
-
-        // Status Register
-        sr: Flags,
-
-        // Instruction Register
-        pub ir: Data,
-
-        // Memory Address Register
-        pub mar: Address,
-
-        // Memory Data Register
-        pub mdr: Data,
+/// Parse an inclusive range like `0x0200:0x02FF`.
+fn parse_range(text: &str) -> Result<(Address, Address), EmuError> {
+    let (start, end) = text
+        .split_once(':')
+        .ok_or_else(|| config_error(format!("not a range: {}", text)))?;
+    let (start, end) = (parse_address(start)?, parse_address(end)?);
+    if start > end {
+        return Err(config_error(format!("empty range: {}", text)));
     }
+    Ok((start, end))
+}
 
-    impl CpuState {
-        pub fn new() -> CpuState {
-            CpuState {
-                sr: 0,
-                pc: 0,
-                sp: 0,
-                a: 0,
-                ir: 0,
-                mar: 0,
-                mdr: 0,
+/// Parse the arguments of a command taking the given options.
+fn parse_args(args: &[String], allowed: &[&str]) -> Result<Options, EmuError> {
+    let mut image = None;
+    let mut opts = Options {
+        image: String::new(),
+        format: None,
+        load: 0x0000,
+        start: None,
+        variant: Variant::default(),
+        max_cycles: None,
+        traps: Vec::new(),
+        dump: None,
+        range: None,
+        port: 6502,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            if image.replace(arg.clone()).is_some() {
+                return Err(config_error(format!("unexpected argument: {}", arg)));
             }
+            continue;
         }
-
-        pub fn get_flag(&self, flag: Flag) -> bool {
-            self.sr & flag.to_mask() != 0
-        }
-
-        pub fn set_flag(&mut self, flag: Flag, val: bool) {
-            let mask = flag.to_mask();
-            let flag_val = if val { self.sr | mask } else { self.sr & !mask };
-            self.sr = flag_val;
-        }
-
-        pub fn set_flags_from_val(&mut self, val: Data) {
-            self.set_flag(Flag::ZRO, val == 0);
-            self.set_flag(Flag::NEG, (val >> DATA_WIDTH - 1) > 0);
+        if !allowed.contains(&arg.as_str()) {
+            return Err(config_error(format!("unknown option: {}", arg)));
         }
-
-        /// Transfer values between registers via implied internal bus.
-        pub fn transfer(&mut self, src: Target, dst: Target) {
-            let val: Byte = match src {
-                Target::ACC => self.a,
-                Target::MDR => self.mdr,
-            };
-            match dst {
-                Target::ACC => {
-                    self.a = val;
-                    self.set_flags_from_val(val);
-                }
-                Target::MDR => self.mdr = val,
-            }
+        let val = args
+            .next()
+            .ok_or_else(|| config_error(format!("{} needs a value", arg)))?;
+        match arg.as_str() {
+            "--format" => opts.format = Some(val.parse()?),
+            "--load" => opts.load = parse_address(val)?,
+            "--start" => opts.start = Some(parse_address(val)?),
+            "--cpu" => opts.variant = val.parse()?,
+            "--max-cycles" => opts.max_cycles = Some(parse_number(val)?),
+            "--trap" => opts.traps.push(parse_address(val)?),
+            "--dump" => opts.dump = Some(parse_range(val)?),
+            "--range" => opts.range = Some(parse_range(val)?),
+            "--port" => opts.port = parse_port(val)?,
+            _ => unreachable!("option {} allowed, but not handled", arg),
         }
     }
+    opts.image = image.ok_or_else(|| config_error("no image given".to_string()))?;
+    Ok(opts)
+}
 
-    pub trait Memory {
-        fn read(&self, addr: &Address) -> Option<Data>;
-        fn write(&mut self, addr: Address, val: Data);
-    }
-
-    use std::collections::HashMap;
+/// Read a file, naming it in errors.
+fn read_file(path: &str) -> Result<Vec<Data>, EmuError> {
+    std::fs::read(path)
+        .map_err(|err| std::io::Error::new(err.kind(), format!("{}: {}", path, err)).into())
+}
 
-    #[derive(Debug)]
-    pub struct CheapoMemory {
-        map: HashMap<Address, Data>,
-    }
+/// A bus with RAM everywhere, holding the image.
+fn load_image(opts: &Options) -> Result<(Bus, Loaded), EmuError> {
+    let bytes = read_file(&opts.image)?;
+    let format = opts
+        .format
+        .unwrap_or_else(|| Format::detect(&opts.image, &bytes));
+    let mut bus = Bus::new();
+    bus.attach_at(Ram::new(), Mapping::new(0x0000, 0xFFFF));
+    let loaded = load(format, &bytes, opts.load, &mut bus)?;
+    Ok((bus, loaded))
+}
 
-    impl CheapoMemory {
-        pub fn new() -> CheapoMemory {
-            CheapoMemory {
-                map: HashMap::new(),
-            }
+/// A CPU ready to run the image: at the start address if there is one,
+/// or else reset.
+fn start_cpu(opts: &Options, loaded: &Loaded, bus: &mut Bus) -> Result<Cpu, EmuError> {
+    let mut cpu = Cpu::with_variant(opts.variant);
+    cpu.state_mut().sp = 0xFF;
+    match opts.start.or(loaded.entry) {
+        Some(start) => cpu.state_mut().pc = start,
+        None => {
+            cpu.reset();
+            cpu.step(bus)?;
         }
     }
+    Ok(cpu)
+}
 
-    impl Memory for CheapoMemory {
-        fn read(&self, addr: &Address) -> Option<Data> {
-            let val = self.map.get(&addr);
-            match val {
-                None => None,
-                Some(data) => Some(*data),
-            }
+/// `luvemix run`. Returns the exit status: 0 when the program stopped
+/// by itself, 1 on errors and 2 when the cycle limit was reached. Why it
+/// stopped goes to stderr, leaving only registers and memory on stdout.
+fn run_command(args: &[String]) -> Result<i32, EmuError> {
+    let opts = parse_args(args, RUN_OPTIONS)?;
+    let (mut bus, loaded) = load_image(&opts)?;
+    let mut cpu = start_cpu(&opts, &loaded, &mut bus)?;
+
+    let limits = Limits {
+        traps: opts.traps.clone(),
+        max_cycles: opts.max_cycles,
+        self_jumps: false,
+    };
+    let status = match run(&mut cpu, &mut bus, &limits) {
+        Ok(Stop::Hit(hit)) => {
+            eprintln!("{}", hit);
+            0
         }
-
-        fn write(&mut self, addr: Address, val: Data) {
-            self.map.insert(addr, val);
+        Ok(Stop::Brk(addr)) => {
+            eprintln!("BRK at ${:04X}", addr);
+            0
         }
-    }
-
-    #[derive(Debug)]
-    pub enum BusMode {
-        READ = 1,
-        WRITE = 0,
-    }
-
-    #[derive(Debug)]
-    pub struct Cpu {
-        state: CpuState,
-        pub addr_bus: Address,
-        pub data_bus: Data,
-        pub rwb: BusMode,
-    }
-
-    impl Cpu {
-        pub fn new() -> Cpu {
-            let state = CpuState::new();
-            let addr = state.mar;
-            let data = state.mdr;
-            Cpu {
-                state: state,
-                addr_bus: addr,
-                data_bus: data,
-                rwb: BusMode::READ,
-            }
+        Ok(Stop::Halted) => {
+            eprintln!("halted");
+            0
         }
-
-        /// Execute first part of a cycle.
-        /// At the end, bus fields must hold desired values.
-        pub fn setup_cycle(&mut self) {
-            // Just give us something to do for now.
-            self.addr_bus = 0xFF;
-            self.data_bus = 42;
-            self.rwb = BusMode::WRITE; // set _after_ data_bus is valid
+        Ok(Stop::Waiting) => {
+            eprintln!("waiting for an interrupt");
+            0
         }
-
-        /// Execute final part of a cycle.
-        /// The outside world should have reacted on the bus by now.
-        pub fn complete_cycle(&mut self) {
-            self.state.mdr = self.data_bus;
-            self.state.transfer(Target::MDR, Target::ACC);
+        Ok(Stop::Jam { opcode, addr }) => {
+            eprintln!("{}", EmuError::Jam { opcode, addr });
+            0
+        }
+        Ok(Stop::Trap(addr)) => {
+            eprintln!("trap at ${:04X}", addr);
+            0
+        }
+        Ok(Stop::CycleLimit) => {
+            eprintln!("cycle limit reached");
+            2
+        }
+        Err(err) => {
+            eprintln!("luvemix: {}", err);
+            1
         }
+    };
+    println!("{} cycles={}", cpu.state(), cpu.cycles());
+    if let Some((start, end)) = opts.dump {
+        print!("{}", dump(&bus, start, end));
     }
+    Ok(status)
 }
 
-#[cfg(test)]
-mod test {
-
-    use crate::cpu::*;
-    use crate::types::*;
-
-    #[test]
-    fn test_get_set_flag() {
-        let mut cpu = CpuState::new();
-
-        assert_eq!(cpu.get_flag(Flag::ZRO), false);
-        cpu.set_flag(Flag::ZRO, true);
-        assert_eq!(cpu.get_flag(Flag::ZRO), true);
-        cpu.set_flag(Flag::ZRO, false);
-        assert_eq!(cpu.get_flag(Flag::ZRO), false);
-    }
-
-    #[test]
-    fn test_get_set_flag_ignores_other_flags() {
-        let mut cpu = CpuState::new();
-
-        assert_eq!(cpu.get_flag(Flag::ZRO), false);
-        cpu.set_flag(Flag::NEG, true);
-        assert_eq!(cpu.get_flag(Flag::ZRO), false);
-        cpu.set_flag(Flag::ZRO, false);
-        assert_eq!(cpu.get_flag(Flag::NEG), true);
-    }
-
-    #[test]
-    fn test_transfer_set_a() {
-        let mut cpu = CpuState::new();
-        cpu.mdr = 42;
-
-        cpu.transfer(Target::MDR, Target::ACC);
-
-        assert_eq!(cpu.a, 42);
-    }
-
-    #[test]
-    fn test_transfer_set_a_sets_zro() {
-        let mut cpu = CpuState::new();
-        cpu.mdr = 0;
-
-        cpu.transfer(Target::MDR, Target::ACC);
-
-        assert_eq!(cpu.get_flag(Flag::ZRO), true);
-
-        cpu.mdr = 42;
-        cpu.transfer(Target::MDR, Target::ACC);
-
-        assert_eq!(cpu.get_flag(Flag::ZRO), false);
-    }
-
-    #[test]
-    fn test_transfer_set_a_sets_neg() {
-        let mut cpu = CpuState::new();
-
-        cpu.mdr = 1 << DATA_WIDTH - 1;
-        cpu.transfer(Target::MDR, Target::ACC);
-
-        assert_eq!(cpu.get_flag(Flag::NEG), true);
-
-        cpu.mdr = 0;
-        cpu.transfer(Target::MDR, Target::ACC);
-
-        assert_eq!(cpu.get_flag(Flag::NEG), false);
+/// `luvemix disasm`.
+fn disasm_command(args: &[String]) -> Result<i32, EmuError> {
+    let opts = parse_args(args, DISASM_OPTIONS)?;
+    let (bus, loaded) = load_image(&opts)?;
+    let (start, end) = match (opts.range, loaded.start, loaded.end) {
+        (Some(range), _, _) => range,
+        (None, Some(start), Some(end)) => (start, end),
+        _ => return Err(config_error("image is empty".to_string())),
+    };
+    for line in disassemble(opts.variant, &bus, start, end) {
+        println!("{}", line);
     }
+    Ok(0)
+}
 
-    #[test]
-    fn test_cheapo_memory_readwrite() {
-        let mut m = CheapoMemory::new();
-
-        assert_eq!(m.read(&0), None);
-
-        m.write(0, 42);
-
-        assert_eq!(m.read(&0).unwrap(), 42);
-
-        m.write(0, 43);
+/// `luvemix debug`.
+fn debug_command(args: &[String]) -> Result<i32, EmuError> {
+    let opts = parse_args(args, DEBUG_OPTIONS)?;
+    let (mut bus, loaded) = load_image(&opts)?;
+    let cpu = start_cpu(&opts, &loaded, &mut bus)?;
+    let stdin = std::io::stdin();
+    Monitor::new(cpu, bus).repl(stdin.lock(), &mut std::io::stdout())?;
+    Ok(0)
+}
 
-        assert_eq!(m.read(&0).unwrap(), 43);
-    }
+/// `luvemix gdb`.
+fn gdb_command(args: &[String]) -> Result<i32, EmuError> {
+    let opts = parse_args(args, GDB_OPTIONS)?;
+    let (mut bus, loaded) = load_image(&opts)?;
+    let cpu = start_cpu(&opts, &loaded, &mut bus)?;
+    let listener = std::net::TcpListener::bind(("127.0.0.1", opts.port))?;
+    println!("waiting for GDB on {}", listener.local_addr()?);
+    let (stream, peer) = listener.accept()?;
+    println!("connected to {}", peer);
+    GdbStub::new(cpu, bus).serve(stream)?;
+    Ok(0)
 }
 
 fn main() {
-    use cpu::*;
-    let state = CpuState::new();
-    println!("Hello {:?}", state);
-    println!("A {:?}", state.a);
-    println!("Zero {:?}", state.get_flag(Flag::ZRO));
-    println!("Negative {:?}", state.get_flag(Flag::NEG));
-
-    let mut cpu = Cpu::new();
-    let mut mem = CheapoMemory::new();
-
-    cpu.setup_cycle();
-
-    match cpu.rwb {
-        BusMode::READ => {
-            let addr = cpu.addr_bus;
-            let val = mem.read(&addr);
-            let val = val.unwrap();
-            cpu.data_bus = val;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("run") => run_command(&args[1..]),
+        Some("disasm") => disasm_command(&args[1..]),
+        Some("debug") => debug_command(&args[1..]),
+        Some("gdb") => gdb_command(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(0)
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            Ok(64)
         }
-        BusMode::WRITE => {
-            let addr = cpu.addr_bus;
-            let data = cpu.data_bus;
-            mem.write(addr, data);
+    };
+    match result {
+        Ok(status) => std::process::exit(status),
+        Err(err) => {
+            eprintln!("luvemix: {}", err);
+            std::process::exit(1);
         }
     }
-
-    cpu.complete_cycle();
-
-    println!("{:?}", cpu);
-    println!("{:?}", mem);
 }
@@ -0,0 +1,220 @@
+use crate::cpu::*;
+use crate::memory::*;
+use crate::types::*;
+
+/// Decides which bank an address refers to. Control registers
+/// written through the same address space switch banks around.
+pub trait Mapper {
+    /// Bank and offset within it that an access to `addr` goes to,
+    /// or `None` if there is nothing.
+    fn map(&self, addr: Address, mode: BusMode) -> Option<(usize, Address)>;
+
+    /// Handle a write to a control register.
+    /// Returns whether `addr` was a register and took the value.
+    fn write_register(&mut self, addr: Address, val: Data) -> bool;
+
+    /// Read back a control register, if `addr` is one.
+    fn read_register(&self, _addr: Address) -> Option<Data> {
+        None
+    }
+}
+
+/// Memory made of several banks, arranged by a `Mapper`.
+pub struct Banked<M: Mapper> {
+    pub mapper: M,
+    banks: Vec<Box<dyn Memory>>,
+}
+
+impl<M: Mapper> Banked<M> {
+    pub fn new(mapper: M, banks: Vec<Box<dyn Memory>>) -> Banked<M> {
+        Banked { mapper, banks }
+    }
+
+    fn bank_for(&self, addr: Address, mode: BusMode) -> Option<(&dyn Memory, Address)> {
+        let (bank, offset) = self.mapper.map(addr, mode)?;
+        Some((self.banks.get(bank)?.as_ref(), offset))
+    }
+}
+
+impl<M: Mapper> Memory for Banked<M> {
+    fn read(&self, addr: &Address) -> Option<Data> {
+        if let Some(val) = self.mapper.read_register(*addr) {
+            return Some(val);
+        }
+        let (bank, offset) = self.bank_for(*addr, BusMode::READ)?;
+        bank.read(&offset)
+    }
+
+    fn write(&mut self, addr: Address, val: Data) {
+        let _ = self.try_write(addr, val);
+    }
+
+    fn try_write(&mut self, addr: Address, val: Data) -> Result<(), MemoryError> {
+        if self.mapper.write_register(addr, val) {
+            return Ok(());
+        }
+        match self.mapper.map(addr, BusMode::WRITE) {
+            Some((bank, offset)) if bank < self.banks.len() => self.banks[bank]
+                .try_write(offset, val)
+                .map_err(|err| err.at(addr)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<M: Mapper + std::fmt::Debug> std::fmt::Debug for Banked<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Banked")
+            .field("mapper", &self.mapper)
+            .field("banks", &self.banks.len())
+            .finish()
+    }
+}
+
+/// NES UxROM: a switchable 16 KiB bank followed by the last bank,
+/// which is fixed. Writing anywhere selects the switchable bank.
+/// Meant to be mapped at $8000-$FFFF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UxRom {
+    banks: usize,
+    selected: usize,
+}
+
+impl UxRom {
+    pub const BANK_SIZE: Address = 0x4000;
+
+    pub fn new(banks: usize) -> UxRom {
+        assert!(banks > 0, "UxROM needs at least one bank");
+        UxRom { banks, selected: 0 }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+}
+
+impl Mapper for UxRom {
+    fn map(&self, addr: Address, _mode: BusMode) -> Option<(usize, Address)> {
+        let offset = addr % UxRom::BANK_SIZE;
+        match addr / UxRom::BANK_SIZE {
+            0 => Some((self.selected, offset)),
+            1 => Some((self.banks - 1, offset)),
+            _ => None,
+        }
+    }
+
+    fn write_register(&mut self, _addr: Address, val: Data) -> bool {
+        self.selected = val as usize % self.banks;
+        true
+    }
+}
+
+/// A window into a number of banks, selected through a register.
+/// Outside the window, bank 0 shows through; the window shows bank
+/// 1 plus the selected number. This is how many designs with 128 KiB
+/// or more of RAM work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    start: Address,
+    size: Address,
+    register: Address,
+    banks: usize,
+    selected: usize,
+}
+
+impl Window {
+    /// A window of `size` bytes at `start`, switching between `banks`
+    /// banks, controlled by writes to `register`.
+    pub fn new(start: Address, size: Address, register: Address, banks: usize) -> Window {
+        assert!(banks > 0, "window needs at least one bank");
+        Window {
+            start,
+            size,
+            register,
+            banks,
+            selected: 0,
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+}
+
+impl Mapper for Window {
+    fn map(&self, addr: Address, _mode: BusMode) -> Option<(usize, Address)> {
+        match addr.checked_sub(self.start) {
+            Some(offset) if offset < self.size => Some((1 + self.selected, offset)),
+            _ => Some((0, addr)),
+        }
+    }
+
+    fn write_register(&mut self, addr: Address, val: Data) -> bool {
+        if addr != self.register {
+            return false;
+        }
+        self.selected = val as usize % self.banks;
+        true
+    }
+
+    fn read_register(&self, addr: Address) -> Option<Data> {
+        (addr == self.register).then_some(self.selected as Data)
+    }
+}
+
+/// The C64 processor port at $0001 and the part of the PLA deciding
+/// between RAM and ROM. Banks are 64 KiB of RAM, the 8 KiB BASIC ROM
+/// and the 8 KiB KERNAL ROM. Writes to ROM go to the RAM underneath.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct C64Pla {
+    port: Data,
+}
+
+impl C64Pla {
+    pub const RAM: usize = 0;
+    pub const BASIC: usize = 1;
+    pub const KERNAL: usize = 2;
+
+    const PORT: Address = 0x0001;
+    const LORAM: Data = 0x01;
+    const HIRAM: Data = 0x02;
+
+    pub fn new() -> C64Pla {
+        C64Pla { port: 0x37 }
+    }
+}
+
+impl Default for C64Pla {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mapper for C64Pla {
+    fn map(&self, addr: Address, mode: BusMode) -> Option<(usize, Address)> {
+        let loram = self.port & C64Pla::LORAM != 0;
+        let hiram = self.port & C64Pla::HIRAM != 0;
+        let bank = match addr {
+            _ if mode == BusMode::WRITE => C64Pla::RAM,
+            0xA000..=0xBFFF if loram && hiram => C64Pla::BASIC,
+            0xE000..=0xFFFF if hiram => C64Pla::KERNAL,
+            _ => C64Pla::RAM,
+        };
+        match bank {
+            C64Pla::RAM => Some((bank, addr)),
+            _ => Some((bank, addr & 0x1FFF)),
+        }
+    }
+
+    fn write_register(&mut self, addr: Address, val: Data) -> bool {
+        if addr != C64Pla::PORT {
+            return false;
+        }
+        self.port = val;
+        true
+    }
+
+    fn read_register(&self, addr: Address) -> Option<Data> {
+        (addr == C64Pla::PORT).then_some(self.port)
+    }
+}
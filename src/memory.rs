@@ -0,0 +1,262 @@
+use crate::types::*;
+
+pub trait Memory {
+    fn read(&self, addr: &Address) -> Option<Data>;
+    fn write(&mut self, addr: Address, val: Data);
+
+    /// Write a value, failing if the device refuses it.
+    /// By default, all writes succeed.
+    fn try_write(&mut self, addr: Address, val: Data) -> Result<(), MemoryError> {
+        self.write(addr, val);
+        Ok(())
+    }
+
+    /// Read a value, failing if the access is not allowed.
+    /// By default, only `read` decides.
+    fn try_read(&self, addr: &Address) -> Result<Option<Data>, MemoryError> {
+        Ok(self.read(addr))
+    }
+
+    /// Whether the device asserts the interrupt request line.
+    /// By default, it never does.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Whether the device asserts the non-maskable interrupt line.
+    /// By default, it never does.
+    fn nmi(&self) -> bool {
+        false
+    }
+}
+
+/// Errors raised by memory devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryError {
+    /// Write to read-only memory.
+    ReadOnly(Address),
+    /// Access to an address without a device, reported in strict mode.
+    Unmapped(Address),
+}
+
+impl MemoryError {
+    /// The same error, reported for a different address.
+    pub fn at(self, addr: Address) -> MemoryError {
+        match self {
+            MemoryError::ReadOnly(_) => MemoryError::ReadOnly(addr),
+            MemoryError::Unmapped(_) => MemoryError::Unmapped(addr),
+        }
+    }
+}
+
+impl std::fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MemoryError::ReadOnly(addr) => write!(f, "write to ROM at ${:04X}", addr),
+            MemoryError::Unmapped(addr) => write!(f, "access to unmapped ${:04X}", addr),
+        }
+    }
+}
+
+impl std::error::Error for MemoryError {}
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Shared devices, so their owner can keep a handle to them after
+/// attaching them to a bus.
+impl<M: Memory> Memory for Rc<RefCell<M>> {
+    fn read(&self, addr: &Address) -> Option<Data> {
+        self.borrow().read(addr)
+    }
+
+    fn write(&mut self, addr: Address, val: Data) {
+        self.borrow_mut().write(addr, val)
+    }
+
+    fn try_write(&mut self, addr: Address, val: Data) -> Result<(), MemoryError> {
+        self.borrow_mut().try_write(addr, val)
+    }
+
+    fn try_read(&self, addr: &Address) -> Result<Option<Data>, MemoryError> {
+        self.borrow().try_read(addr)
+    }
+
+    fn irq(&self) -> bool {
+        self.borrow().irq()
+    }
+
+    fn nmi(&self) -> bool {
+        self.borrow().nmi()
+    }
+}
+
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub struct CheapoMemory {
+    map: HashMap<Address, Data>,
+}
+
+impl CheapoMemory {
+    pub fn new() -> CheapoMemory {
+        CheapoMemory {
+            map: HashMap::new(),
+        }
+    }
+}
+
+impl Default for CheapoMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Memory for CheapoMemory {
+    fn read(&self, addr: &Address) -> Option<Data> {
+        self.map.get(addr).copied()
+    }
+
+    fn write(&mut self, addr: Address, val: Data) {
+        self.map.insert(addr, val);
+    }
+}
+
+/// Contents of RAM at power-on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerOn {
+    /// All cells hold $00.
+    ZEROS,
+    /// All cells hold $FF.
+    ONES,
+    /// Pseudo-random values, reproducible from the seed.
+    RANDOM(u64),
+    /// Blocks of 64 bytes alternating between $00 and $FF, as seen in
+    /// many machines built from DRAM chips.
+    DRAM,
+}
+
+const RAM_SIZE: usize = 0x10000;
+
+/// RAM covering the whole 64 KiB address space, backed by a flat array.
+pub struct Ram {
+    cells: Box<[Data]>,
+}
+
+impl Ram {
+    pub fn new() -> Ram {
+        Ram::with_pattern(PowerOn::ZEROS)
+    }
+
+    pub fn with_pattern(pattern: PowerOn) -> Ram {
+        let mut cells = vec![0; RAM_SIZE].into_boxed_slice();
+        match pattern {
+            PowerOn::ZEROS => {}
+            PowerOn::ONES => cells.fill(0xFF),
+            PowerOn::RANDOM(seed) => {
+                // xorshift64*, which must not start from zero
+                let mut x = seed | 1;
+                for cell in cells.iter_mut() {
+                    x ^= x >> 12;
+                    x ^= x << 25;
+                    x ^= x >> 27;
+                    *cell = (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as Data;
+                }
+            }
+            PowerOn::DRAM => {
+                for (addr, cell) in cells.iter_mut().enumerate() {
+                    *cell = if addr & 0x40 == 0 { 0x00 } else { 0xFF };
+                }
+            }
+        }
+        Ram { cells }
+    }
+}
+
+impl Default for Ram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for Ram {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Ram {{ {} bytes }}", self.cells.len())
+    }
+}
+
+impl Memory for Ram {
+    fn read(&self, addr: &Address) -> Option<Data> {
+        Some(self.cells[*addr as usize])
+    }
+
+    fn write(&mut self, addr: Address, val: Data) {
+        self.cells[addr as usize] = val;
+    }
+}
+
+/// What ROM does about writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// Drop them silently, like real hardware.
+    IGNORE,
+    /// Drop them, but report them on stderr.
+    LOG,
+    /// Refuse them with `MemoryError::ReadOnly`.
+    ERROR,
+}
+
+/// Read-only memory holding a firmware image. Reads beyond the end of
+/// the image return `None`.
+pub struct Rom {
+    bytes: Box<[Data]>,
+    pub policy: WritePolicy,
+}
+
+impl Rom {
+    pub fn new(bytes: &[Data]) -> Rom {
+        Rom {
+            bytes: bytes.into(),
+            policy: WritePolicy::IGNORE,
+        }
+    }
+
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Rom> {
+        Ok(Rom::new(&std::fs::read(path)?))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl std::fmt::Debug for Rom {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Rom {{ {} bytes, {:?} }}", self.bytes.len(), self.policy)
+    }
+}
+
+impl Memory for Rom {
+    fn read(&self, addr: &Address) -> Option<Data> {
+        self.bytes.get(*addr as usize).copied()
+    }
+
+    fn write(&mut self, addr: Address, val: Data) {
+        let _ = self.try_write(addr, val);
+    }
+
+    fn try_write(&mut self, addr: Address, val: Data) -> Result<(), MemoryError> {
+        match self.policy {
+            WritePolicy::IGNORE => Ok(()),
+            WritePolicy::LOG => {
+                eprintln!("ignoring write of ${:02X} to ROM at ${:04X}", val, addr);
+                Ok(())
+            }
+            WritePolicy::ERROR => Err(MemoryError::ReadOnly(addr)),
+        }
+    }
+}
@@ -0,0 +1,407 @@
+//! An interactive machine code monitor: step through a program, set
+//! breakpoints, and look at and change registers and memory.
+
+use crate::breakpoints::*;
+use crate::cpu::*;
+use crate::disasm::*;
+use crate::memory::*;
+use crate::runner::*;
+use crate::types::*;
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+
+pub const HELP: &str = "\
+commands:
+    step [N]            s   execute N instructions (default 1)
+    next                n   like step, but run subroutines called by JSR
+                            until they return
+    continue            c   run until a breakpoint, BRK, a jump to itself
+                            or the CPU halts
+    break [ADDR [if COND]]  b
+                            set a breakpoint at ADDR, or list them;
+                            with a condition like A == $42 && [$0200] != 0
+                            it only stops when that holds
+    delete ADDR             remove the breakpoint at ADDR
+    watch [START [END]]     stop after writes to memory, or list watchpoints
+    rwatch START [END]      stop after reads
+    awatch START [END]      stop after reads or writes
+    unwatch START [END]     remove watchpoints
+    regs                r   show registers and the next instruction
+    set REG VALUE           set PC, SP, A, X, Y or P
+    mem START [END]     m   dump memory (default 64 bytes)
+    fill START END VAL      fill memory from START to END with VAL
+    poke ADDR VAL...        write bytes, starting at ADDR
+    disasm [ADDR] [N]   d   disassemble N instructions (default 8),
+                            starting at ADDR (default PC)
+    help                h   show this text
+    quit                q   leave
+
+Addresses and values are hexadecimal, counts decimal.";
+
+/// A CPU and its memory, driven by monitor commands.
+pub struct Monitor<M: Memory> {
+    pub cpu: Cpu,
+    pub mem: M,
+}
+
+fn hex(text: &str, max: u32) -> Result<u32, String> {
+    let digits = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    match u32::from_str_radix(digits, 16) {
+        Ok(val) if val <= max => Ok(val),
+        Ok(_) => Err(format!("out of range: {}", text)),
+        Err(_) => Err(format!("not a hex number: {}", text)),
+    }
+}
+
+fn address(text: &str) -> Result<Address, String> {
+    hex(text, Address::MAX as u32).map(|val| val as Address)
+}
+
+fn value(text: &str) -> Result<Data, String> {
+    hex(text, Data::MAX as u32).map(|val| val as Data)
+}
+
+fn count(text: &str) -> Result<usize, String> {
+    text.parse().map_err(|_| format!("not a count: {}", text))
+}
+
+/// Check the number of arguments a command got.
+fn expect_args(args: &[&str], min: usize, max: usize) -> Result<(), String> {
+    match args.len() {
+        n if n < min => Err("missing argument".to_string()),
+        n if n > max => Err(format!("unexpected argument: {}", args[max])),
+        _ => Ok(()),
+    }
+}
+
+/// Memory as hex, 16 bytes per line. Addresses nothing responds to show
+/// as `--`.
+pub fn dump<M: Memory + ?Sized>(mem: &M, start: Address, end: Address) -> String {
+    let mut text = String::new();
+    let mut addr = start as u32;
+    while addr <= end as u32 {
+        let line_end = (addr + 15).min(end as u32);
+        let bytes: Vec<String> = (addr..=line_end)
+            .map(|a| match mem.read(&(a as Address)) {
+                Some(val) => format!("{:02X}", val),
+                None => "--".to_string(),
+            })
+            .collect();
+        let _ = writeln!(text, "{:04X}: {}", addr, bytes.join(" "));
+        addr = line_end + 1;
+    }
+    text
+}
+
+impl<M: Memory> Monitor<M> {
+    pub fn new(cpu: Cpu, mem: M) -> Self {
+        Monitor { cpu, mem }
+    }
+
+    /// Read commands until `quit` or the end of input, prompting for
+    /// each.
+    pub fn repl<R: BufRead, W: Write>(&mut self, mut input: R, out: &mut W) -> std::io::Result<()> {
+        write!(out, "{}", self.where_am_i())?;
+        loop {
+            write!(out, "> ")?;
+            out.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(out)?;
+                return Ok(());
+            }
+            if !self.command(&line, out)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Execute one command line, writing its output. Returns false when
+    /// asked to quit.
+    pub fn command<W: Write>(&mut self, line: &str, out: &mut W) -> std::io::Result<bool> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (name.to_ascii_lowercase(), args),
+            None => return Ok(true),
+        };
+        let result = match name.as_str() {
+            "s" | "step" => self.step(args),
+            "n" | "next" => self.next(args),
+            "c" | "continue" => self.resume(args),
+            "b" | "break" => self.set_breakpoint(args),
+            "delete" => self.delete_breakpoint(args),
+            "watch" if args.is_empty() => Ok(self.watchpoints()),
+            "watch" => self.watch(args, Access::WRITE),
+            "rwatch" => self.watch(args, Access::READ),
+            "awatch" => self.watch(args, Access::ANY),
+            "unwatch" => self.unwatch(args),
+            "r" | "regs" => expect_args(args, 0, 0).map(|_| self.where_am_i()),
+            "set" => self.set_register(args),
+            "m" | "mem" => self.mem_command(args),
+            "fill" => self.fill(args),
+            "poke" => self.poke(args),
+            "d" | "disasm" => self.disasm(args),
+            "h" | "help" => Ok(format!("{}\n", HELP)),
+            "q" | "quit" => return Ok(false),
+            _ => Err(format!("unknown command: {}, try help", name)),
+        };
+        match result {
+            Ok(text) => write!(out, "{}", text)?,
+            Err(msg) => writeln!(out, "error: {}", msg)?,
+        }
+        Ok(true)
+    }
+
+    /// Registers and the next instruction.
+    fn where_am_i(&self) -> String {
+        let pc = self.cpu.state().pc;
+        format!(
+            "{} cycles={}\n{}\n",
+            self.cpu.state(),
+            self.cpu.cycles(),
+            disassemble_at(self.cpu.variant(), &self.mem, pc)
+        )
+    }
+
+    /// Report how running ended, if there is anything to say, then where
+    /// the CPU is.
+    fn report(&self, stop: Result<Option<Stop>, EmuError>) -> String {
+        let reason = match stop {
+            Ok(Some(Stop::Hit(hit))) => format!("{}\n", hit),
+            Ok(Some(Stop::Brk(addr))) => format!("BRK at ${:04X}\n", addr),
+            Ok(Some(Stop::Halted)) => "halted\n".to_string(),
+            Ok(Some(Stop::Waiting)) => "waiting for an interrupt\n".to_string(),
+            Ok(Some(Stop::Jam { opcode, addr })) => {
+                format!("{}\n", EmuError::Jam { opcode, addr })
+            }
+            Ok(Some(Stop::Trap(addr))) => format!("trap at ${:04X}\n", addr),
+            Ok(Some(Stop::CycleLimit)) | Ok(None) => String::new(),
+            Err(err) => format!("{}\n", err),
+        };
+        reason + &self.where_am_i()
+    }
+
+    /// Execute one instruction, unless the CPU cannot go on.
+    fn step_once(&mut self) -> Result<Option<Stop>, EmuError> {
+        self.cpu.step(&mut self.mem).map(Stop::after)
+    }
+
+    /// Run until a breakpoint, BRK, a jump to itself or `until` is
+    /// reached, or the CPU stops. Reaching `until` is not reported. The
+    /// instruction at PC is always executed, so that running can go on
+    /// from where it stopped.
+    fn run_until(&mut self, until: Option<Address>) -> Result<Option<Stop>, EmuError> {
+        let from = self.cpu.state().pc;
+        if let Some(stop) = self.step_once()? {
+            return Ok(Some(stop));
+        }
+        let limits = Limits {
+            traps: until.into_iter().collect(),
+            self_jumps: true,
+            ..Limits::default()
+        };
+        let stop = match limits.check(&self.cpu, &self.mem, Some(from)) {
+            Some(stop) => stop,
+            None => run(&mut self.cpu, &mut self.mem, &limits)?,
+        };
+        match stop {
+            Stop::Trap(pc) if until == Some(pc) => Ok(None),
+            stop => Ok(Some(stop)),
+        }
+    }
+
+    fn step(&mut self, args: &[&str]) -> Result<String, String> {
+        expect_args(args, 0, 1)?;
+        let n = args.first().map_or(Ok(1), |text| count(text))?;
+        let mut stop = Ok(None);
+        for _ in 0..n {
+            match self.step_once() {
+                Ok(None) => {}
+                Ok(Some(reason)) => {
+                    stop = Ok(Some(reason));
+                    break;
+                }
+                Err(err) => {
+                    stop = Err(err);
+                    break;
+                }
+            }
+        }
+        Ok(self.report(stop))
+    }
+
+    fn next(&mut self, args: &[&str]) -> Result<String, String> {
+        expect_args(args, 0, 0)?;
+        let line = disassemble_at(self.cpu.variant(), &self.mem, self.cpu.state().pc);
+        if line.instruction.op != Operation::JSR {
+            return self.step(args);
+        }
+        let stop = self.run_until(Some(line.next()));
+        Ok(self.report(stop))
+    }
+
+    fn resume(&mut self, args: &[&str]) -> Result<String, String> {
+        expect_args(args, 0, 0)?;
+        let stop = self.run_until(None);
+        Ok(self.report(stop))
+    }
+
+    fn set_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        let breakpoints = self.cpu.breakpoints_mut();
+        match args {
+            [] => Ok(breakpoints
+                .breakpoints()
+                .map(|(addr, condition)| match condition {
+                    Some(condition) => format!("${:04X} if {}\n", addr, condition),
+                    None => format!("${:04X}\n", addr),
+                })
+                .collect()),
+            [addr] => {
+                breakpoints.add(address(addr)?);
+                Ok(String::new())
+            }
+            [addr, keyword, condition @ ..] if keyword.eq_ignore_ascii_case("if") => {
+                let addr = address(addr)?;
+                let condition = condition
+                    .join(" ")
+                    .parse()
+                    .map_err(|err| format!("{}", err))?;
+                breakpoints.add_if(addr, condition);
+                Ok(String::new())
+            }
+            [_, arg, ..] => Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+
+    fn delete_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        expect_args(args, 1, 1)?;
+        let addr = address(args[0])?;
+        match self.cpu.breakpoints_mut().remove(addr) {
+            true => Ok(String::new()),
+            false => Err(format!("no breakpoint at ${:04X}", addr)),
+        }
+    }
+
+    /// A range of one or more addresses.
+    fn range(args: &[&str]) -> Result<(Address, Address), String> {
+        expect_args(args, 1, 2)?;
+        let start = address(args[0])?;
+        let end = args.get(1).map_or(Ok(start), |end| address(end))?;
+        match start <= end {
+            true => Ok((start, end)),
+            false => Err("empty range".to_string()),
+        }
+    }
+
+    fn watch(&mut self, args: &[&str], access: Access) -> Result<String, String> {
+        let (start, end) = Self::range(args)?;
+        self.cpu
+            .breakpoints_mut()
+            .watch(Watchpoint::new(start, end, access));
+        Ok(String::new())
+    }
+
+    fn unwatch(&mut self, args: &[&str]) -> Result<String, String> {
+        let (start, end) = Self::range(args)?;
+        match self.cpu.breakpoints_mut().unwatch(start, end) {
+            true => Ok(String::new()),
+            false => Err("no such watchpoint".to_string()),
+        }
+    }
+
+    fn watchpoints(&self) -> String {
+        let mut text = String::new();
+        for watchpoint in self.cpu.breakpoints().watchpoints() {
+            let access = match watchpoint.access {
+                Access::READ => "read",
+                Access::WRITE => "write",
+                Access::ANY => "access",
+            };
+            let _ = match watchpoint.start == watchpoint.end {
+                true => writeln!(text, "{} ${:04X}", access, watchpoint.start),
+                false => writeln!(
+                    text,
+                    "{} ${:04X}-${:04X}",
+                    access, watchpoint.start, watchpoint.end
+                ),
+            };
+        }
+        text
+    }
+
+    fn set_register(&mut self, args: &[&str]) -> Result<String, String> {
+        expect_args(args, 2, 2)?;
+        let state = self.cpu.state_mut();
+        match args[0].to_ascii_lowercase().as_str() {
+            "pc" => state.pc = address(args[1])?,
+            "sp" => state.sp = value(args[1])?,
+            "a" => state.a = value(args[1])?,
+            "x" => state.x = value(args[1])?,
+            "y" => state.y = value(args[1])?,
+            "p" => state.set_sr(value(args[1])?),
+            reg => return Err(format!("unknown register: {}", reg)),
+        }
+        Ok(self.where_am_i())
+    }
+
+    fn mem_command(&mut self, args: &[&str]) -> Result<String, String> {
+        expect_args(args, 1, 2)?;
+        let start = address(args[0])?;
+        let end = match args.get(1) {
+            Some(end) => address(end)?,
+            None => start.saturating_add(0x3F),
+        };
+        if start > end {
+            return Err("empty range".to_string());
+        }
+        Ok(dump(&self.mem, start, end))
+    }
+
+    fn write(&mut self, addr: Address, val: Data) -> Result<(), String> {
+        self.mem.try_write(addr, val).map_err(|err| err.to_string())
+    }
+
+    fn fill(&mut self, args: &[&str]) -> Result<String, String> {
+        expect_args(args, 3, 3)?;
+        let (start, end, val) = (address(args[0])?, address(args[1])?, value(args[2])?);
+        if start > end {
+            return Err("empty range".to_string());
+        }
+        for addr in start..=end {
+            self.write(addr, val)?;
+        }
+        Ok(String::new())
+    }
+
+    fn poke(&mut self, args: &[&str]) -> Result<String, String> {
+        expect_args(args, 2, usize::MAX)?;
+        let addr = address(args[0])?;
+        let vals = args[1..]
+            .iter()
+            .map(|text| value(text))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (i, val) in vals.into_iter().enumerate() {
+            self.write(addr.wrapping_add(i as Address), val)?;
+        }
+        Ok(String::new())
+    }
+
+    fn disasm(&mut self, args: &[&str]) -> Result<String, String> {
+        expect_args(args, 0, 2)?;
+        let mut addr = match args.first() {
+            Some(addr) => address(addr)?,
+            None => self.cpu.state().pc,
+        };
+        let n = args.get(1).map_or(Ok(8), |text| count(text))?;
+        let mut text = String::new();
+        for _ in 0..n {
+            let line = disassemble_at(self.cpu.variant(), &self.mem, addr);
+            let _ = writeln!(text, "{}", line);
+            addr = line.next();
+        }
+        Ok(text)
+    }
+}
@@ -0,0 +1,98 @@
+//! Running a program until something stops it, as the run command and
+//! the monitor do.
+
+use crate::breakpoints::*;
+use crate::cpu::*;
+use crate::memory::*;
+use crate::types::*;
+
+/// Why running stopped, short of an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// A breakpoint or watchpoint.
+    Hit(Hit),
+    /// BRK is next, at this address.
+    Brk(Address),
+    Halted,
+    Waiting,
+    /// A JAM opcode halted the CPU.
+    Jam {
+        opcode: Data,
+        addr: Address,
+    },
+    /// PC reached a trap address, or an instruction jumped to itself.
+    Trap(Address),
+    CycleLimit,
+}
+
+impl Stop {
+    /// Whether a step ended in a way that stops running.
+    pub fn after(outcome: StepOutcome) -> Option<Stop> {
+        match outcome {
+            StepOutcome::Halted => Some(Stop::Halted),
+            StepOutcome::Waiting => Some(Stop::Waiting),
+            StepOutcome::Break(hit) => Some(Stop::Hit(hit)),
+            StepOutcome::Retired | StepOutcome::Interrupted(_) => None,
+        }
+    }
+}
+
+/// When `run` stops, besides BRK, breakpoints and the CPU stopping by
+/// itself.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    /// Stop when PC reaches one of these.
+    pub traps: Vec<Address>,
+    /// Stop once the CPU has run this many cycles.
+    pub max_cycles: Option<u64>,
+    /// Stop at instructions that jump or branch to themselves, as test
+    /// programs do when they are done.
+    pub self_jumps: bool,
+}
+
+impl Limits {
+    /// Whether to stop before the instruction at PC. `from` is where the
+    /// last instruction started, if one ran.
+    pub fn check<M: Memory + ?Sized>(
+        &self,
+        cpu: &Cpu,
+        mem: &M,
+        from: Option<Address>,
+    ) -> Option<Stop> {
+        let pc = cpu.state().pc;
+        if from.is_some() && (self.traps.contains(&pc) || (self.self_jumps && from == Some(pc))) {
+            return Some(Stop::Trap(pc));
+        }
+        if self.max_cycles.is_some_and(|max| cpu.cycles() >= max) {
+            return Some(Stop::CycleLimit);
+        }
+        if mem.read(&pc) == Some(0x00) {
+            return Some(Stop::Brk(pc));
+        }
+        None
+    }
+}
+
+/// Run until something stops the CPU. Stops before executing BRK, so
+/// the registers show where it was.
+pub fn run<M: Memory + ?Sized>(
+    cpu: &mut Cpu,
+    mem: &mut M,
+    limits: &Limits,
+) -> Result<Stop, EmuError> {
+    if let Some(stop) = limits.check(cpu, mem, None) {
+        return Ok(stop);
+    }
+    let mut from = cpu.state().pc;
+    let mut stop = None;
+    let result = cpu.run_with(mem, |cpu, mem, outcome| {
+        stop = Stop::after(outcome).or_else(|| limits.check(cpu, mem, Some(from)));
+        from = cpu.state().pc;
+        stop.is_some()
+    });
+    match result {
+        Ok(_) => Ok(stop.expect("running only ends at a stop")),
+        Err(EmuError::Jam { opcode, addr }) => Ok(Stop::Jam { opcode, addr }),
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,7 @@
+pub type Byte = u8;
+pub type Word = u16;
+pub type Address = Word;
+pub type Data = Byte;
+pub type Flags = Data;
+
+pub const DATA_WIDTH: u8 = 8;
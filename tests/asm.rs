@@ -0,0 +1,356 @@
+mod common;
+use common::*;
+
+use luvemix_rust::asm;
+use luvemix_rust::asm::*;
+use luvemix_rust::cpu::*;
+use luvemix_rust::disasm::*;
+use luvemix_rust::memory::*;
+use luvemix_rust::types::*;
+
+fn error(variant: Variant, source: &str) -> String {
+    assemble(variant, source).unwrap_err().to_string()
+}
+
+#[test]
+fn test_addressing_mode_syntax() {
+    let source = "
+        NOP
+        ASL A
+        ASL
+        LDA #$2A
+        LDA $12
+        LDA $12,X
+        LDX $12,Y
+        LDA $1234
+        LDA $1234,X
+        LDA $1234,Y
+        JMP ($1234)
+        LDA ($12,X)
+        LDA ($12),Y
+    ";
+    #[rustfmt::skip]
+    let expected = [
+        0xEA,
+        0x0A,
+        0x0A,
+        0xA9, 0x2A,
+        0xA5, 0x12,
+        0xB5, 0x12,
+        0xB6, 0x12,
+        0xAD, 0x34, 0x12,
+        0xBD, 0x34, 0x12,
+        0xB9, 0x34, 0x12,
+        0x6C, 0x34, 0x12,
+        0xA1, 0x12,
+        0xB1, 0x12,
+    ];
+    assert_eq!(asm!(source), expected);
+}
+
+#[test]
+fn test_mnemonics_are_case_insensitive() {
+    assert_eq!(asm!("lda #1 / Sta $0200,x"), [0xA9, 0x01, 0x9D, 0x00, 0x02]);
+}
+
+#[test]
+fn test_documented_opcodes_are_preferred() {
+    assert_eq!(asm!("NOP / SBC #1"), [0xEA, 0xE9, 0x01]);
+}
+
+#[test]
+fn test_cmos_instructions() {
+    let source = "
+        .org $0600
+        BRA next
+        STZ $12
+        next: LDA ($12)
+        JMP ($1234,X)
+        RMB3 $12
+        BBS7 $12,next
+    ";
+    #[rustfmt::skip]
+    let expected = [
+        0x80, 0x02,
+        0x64, 0x12,
+        0xB2, 0x12,
+        0x7C, 0x34, 0x12,
+        0x37, 0x12,
+        0xFF, 0x12, 0xF6,
+    ];
+    assert_eq!(asm!(Variant::WDC, source), expected);
+}
+
+#[test]
+fn test_instructions_depend_on_variant() {
+    assert_eq!(
+        error(Variant::NMOS, "BRA *"),
+        "line 1: unknown instruction BRA"
+    );
+    assert_eq!(
+        error(Variant::CMOS, "RMB0 $12"),
+        "line 1: unknown instruction RMB0"
+    );
+    assert_eq!(asm!(Variant::NMOS, "LAX $12"), [0xA7, 0x12]);
+    assert_eq!(
+        error(Variant::CMOS, "LAX $12"),
+        "line 1: unknown instruction LAX"
+    );
+}
+
+#[test]
+fn test_round_trip_through_disassembler() {
+    for variant in [
+        Variant::NMOS,
+        Variant::CMOS,
+        Variant::ROCKWELL,
+        Variant::WDC,
+        Variant::RP2A03,
+    ] {
+        for opcode in 0..=255 {
+            let mut mem = Ram::new();
+            for (i, val) in [opcode, 0x34, 0x12].iter().enumerate() {
+                mem.write(0x0600 + i as Address, *val);
+            }
+            let line = disassemble_at(variant, &mem, 0x0600);
+            let source = format!(".org $0600 / {}", line.text());
+            let bytes = asm!(variant, &source);
+
+            assert_eq!(variant.decode(bytes[0]), line.instruction, "{}", source);
+            assert_eq!(&bytes[1..], line.operands(), "{}", source);
+        }
+    }
+}
+
+#[test]
+fn test_labels_and_branches() {
+    let assembly = assemble(
+        Variant::NMOS,
+        "
+        .org $0200
+        start:  LDX #3
+        loop:   DEX
+                BNE loop
+                BEQ done
+                NOP
+        done:   JMP start
+        ",
+    )
+    .unwrap();
+    assert_eq!(assembly.symbol("start"), Some(0x0200));
+    assert_eq!(assembly.symbol("loop"), Some(0x0202));
+    assert_eq!(assembly.symbol("done"), Some(0x0208));
+    #[rustfmt::skip]
+    let expected = [
+        0xA2, 0x03,
+        0xCA,
+        0xD0, 0xFD,
+        0xF0, 0x01,
+        0xEA,
+        0x4C, 0x00, 0x02,
+    ];
+    assert_eq!(assembly.to_bytes(), expected);
+}
+
+#[test]
+fn test_local_labels() {
+    let assembly = assemble(
+        Variant::NMOS,
+        "
+        first:  LDX #2
+        @loop:  DEX
+                BNE @loop
+        second: LDY #2
+        @loop:  DEY
+                BNE @loop
+        ",
+    )
+    .unwrap();
+    assert_eq!(assembly.symbol("first@loop"), Some(0x0002));
+    assert_eq!(assembly.symbol("second@loop"), Some(0x0007));
+    assert_eq!(assembly.to_bytes()[3..5], [0xD0, 0xFD]);
+    assert_eq!(assembly.to_bytes()[8..10], [0xD0, 0xFD]);
+}
+
+#[test]
+fn test_forward_references_are_absolute() {
+    // `data` is not known yet when the first LDA is sized.
+    let assembly = assemble(
+        Variant::NMOS,
+        "
+        LDA data
+        data: .byte 1
+        LDA data
+        ",
+    )
+    .unwrap();
+    assert_eq!(assembly.to_bytes(), [0xAD, 0x03, 0x00, 0x01, 0xA5, 0x03]);
+}
+
+#[test]
+fn test_data_directives() {
+    let source = r#"
+        .byte 1, $02, %11, 'A', "hi", -1
+        .word $1234, label
+        label:
+    "#;
+    #[rustfmt::skip]
+    let expected = [
+        0x01, 0x02, 0x03, 0x41, 0x68, 0x69, 0xFF,
+        0x34, 0x12, 0x0B, 0x00,
+    ];
+    assert_eq!(asm!(source), expected);
+}
+
+#[test]
+fn test_org_starts_chunks() {
+    let assembly = assemble(
+        Variant::NMOS,
+        "
+        .org $0200
+        .byte 1, 2
+        .org $0210
+        .byte 3
+        .org $FFFC
+        .word $0200
+        ",
+    )
+    .unwrap();
+    let addrs: Vec<Address> = assembly.chunks.iter().map(|chunk| chunk.addr).collect();
+    assert_eq!(addrs, [0x0200, 0x0210, 0xFFFC]);
+    assert_eq!(assembly.start(), Some(0x0200));
+
+    let mut mem = CheapoMemory::new();
+    assembly.write_to(&mut mem).unwrap();
+    assert_eq!(mem.read(&0x0201), Some(2));
+    assert_eq!(mem.read(&0x0210), Some(3));
+    assert_eq!(mem.read(&0xFFFD), Some(0x02));
+}
+
+#[test]
+fn test_to_bytes_fills_gaps() {
+    assert_eq!(
+        asm!(".org $10 / .byte 1 / .org $13 / .byte 4"),
+        [1, 0, 0, 4]
+    );
+}
+
+#[test]
+fn test_expressions() {
+    let source = "
+        base = $1234
+        size = 2 + 3 * 4
+        LDA #<base
+        LDA #>base
+        LDA #<base + $10
+        LDA #size
+        LDA #(2 + 3) * 4
+        LDA #1 << 4 | 1
+        LDA #$F0 & ~$30 ^ 1
+        LDA #-size
+        .word *, * + 2
+        JMP (base + 1) * 2
+    ";
+    #[rustfmt::skip]
+    let expected = [
+        0xA9, 0x34,
+        0xA9, 0x12,
+        0xA9, 0x44,
+        0xA9, 0x0E,
+        0xA9, 0x14,
+        0xA9, 0x11,
+        0xA9, 0xC1,
+        0xA9, 0xF2,
+        0x10, 0x00, 0x12, 0x00,
+        0x4C, 0x6A, 0x24,
+    ];
+    assert_eq!(asm!(source), expected);
+}
+
+#[test]
+fn test_constants_may_refer_ahead() {
+    let assembly = assemble(Variant::NMOS, "a = b + 1 / b = end / NOP / end:").unwrap();
+    assert_eq!(assembly.symbol("a"), Some(2));
+    assert_eq!(assembly.symbol("b"), Some(1));
+}
+
+#[test]
+fn test_comments() {
+    let source = "
+        ; a whole line
+        LDA #';'    ; a semicolon
+        .byte \"a;b\" / NOP ; after two statements
+    ";
+    assert_eq!(asm!(source), [0xA9, 0x3B, 0x61, 0x3B, 0x62, 0xEA]);
+}
+
+#[test]
+fn test_errors() {
+    let nmos = Variant::NMOS;
+    assert_eq!(error(nmos, "NOP\nFOO"), "line 2: unknown instruction FOO");
+    assert_eq!(
+        error(nmos, "JMP nowhere"),
+        "line 1: undefined symbol nowhere"
+    );
+    assert_eq!(
+        error(nmos, "LDA #256"),
+        "line 1: 256 does not fit in a byte"
+    );
+    assert_eq!(
+        error(nmos, "STA #1"),
+        "line 1: STA does not take this operand"
+    );
+    assert_eq!(
+        error(nmos, "LDA ($1234,X)"),
+        "line 1: $1234 is not in zero page"
+    );
+    assert_eq!(error(nmos, "a: NOP / a: NOP"), "line 1: a defined twice");
+    assert_eq!(
+        error(nmos, ".org $10000"),
+        "line 1: $10000 is not an address"
+    );
+    assert_eq!(error(nmos, ".fill 3"), "line 1: unknown directive .fill");
+    assert_eq!(error(nmos, "a = b\nb = a"), "line 1: undefined symbol b");
+    assert_eq!(error(nmos, "LDA #(1"), "line 1: missing )");
+    assert_eq!(
+        error(nmos, "LDA #1 2"),
+        "line 1: unexpected \"2\" in expression"
+    );
+    assert_eq!(
+        error(nmos, "BNE far / .org $0100 / far: NOP"),
+        "line 1: branch target 254 bytes away"
+    );
+    assert_eq!(
+        error(nmos, ".org $10 / BNE -$7FFFFFFFFFFFFFFF-1"),
+        "line 1: branch target out of range"
+    );
+    assert_eq!(
+        error(nmos, ".org $FFFF / NOP / NOP"),
+        "line 1: code beyond $FFFF"
+    );
+}
+
+#[test]
+fn test_run_assembled_program() {
+    let assembly = assemble(
+        Variant::NMOS,
+        "
+        .org $0200
+                LDX #5
+                LDA #0
+        @add:   CLC
+                ADC #3
+                DEX
+                BNE @add
+                STA result
+        done:   JMP done
+        result = $10
+        ",
+    )
+    .unwrap();
+    let (mut cpu, mut mem) = setup(&assembly.to_bytes());
+    let done = assembly.symbol("done").unwrap();
+    cpu.run_until(&mut mem, |cpu| cpu.state().pc == done)
+        .unwrap();
+    assert_eq!(mem.read(&0x10), Some(15));
+}
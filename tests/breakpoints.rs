@@ -0,0 +1,244 @@
+mod common;
+use common::*;
+
+use luvemix_rust::asm;
+use luvemix_rust::breakpoints::*;
+use luvemix_rust::cpu::*;
+use luvemix_rust::memory::*;
+
+const LOOP: &str = "
+    .org $0200
+            LDX #4
+    loop:   DEX
+            BNE loop
+            STA $10
+            BRK
+";
+
+fn condition(text: &str) -> Condition {
+    text.parse().unwrap()
+}
+
+#[test]
+fn test_breakpoint_stops_run() {
+    let (mut cpu, mut mem) = setup(&asm!(LOOP));
+    cpu.breakpoints_mut().add(0x0202);
+
+    let outcome = cpu.run(&mut mem).unwrap();
+    assert_eq!(outcome, StepOutcome::Break(Hit::Breakpoint(0x0202)));
+    assert_eq!(cpu.state().pc, 0x0202);
+    assert_eq!(cpu.state().x, 4);
+
+    // Going on from a breakpoint executes the instruction there.
+    assert_eq!(
+        cpu.run(&mut mem).unwrap(),
+        StepOutcome::Break(Hit::Breakpoint(0x0202))
+    );
+    assert_eq!(cpu.state().x, 3);
+
+    assert!(cpu.breakpoints_mut().remove(0x0202));
+    assert!(!cpu.breakpoints_mut().remove(0x0202));
+    let outcome = cpu
+        .run_until(&mut mem, |cpu| cpu.state().pc == 0x0207)
+        .unwrap();
+    assert_eq!(outcome, StepOutcome::Retired);
+    assert_eq!(cpu.state().x, 0);
+}
+
+#[test]
+fn test_conditional_breakpoint() {
+    let (mut cpu, mut mem) = setup(&asm!(LOOP));
+    cpu.breakpoints_mut()
+        .add_if(0x0203, condition("X == 1 && !Z"));
+
+    let outcome = cpu.run(&mut mem).unwrap();
+    assert_eq!(outcome, StepOutcome::Break(Hit::Breakpoint(0x0203)));
+    assert_eq!(cpu.state().x, 1);
+
+    let listed: Vec<String> = cpu
+        .breakpoints()
+        .breakpoints()
+        .map(|(addr, condition)| format!("{:04X} {}", addr, condition.unwrap()))
+        .collect();
+    assert_eq!(listed, ["0203 X == 1 && !Z"]);
+}
+
+#[test]
+fn test_run_until_stops_at_breakpoints() {
+    let (mut cpu, mut mem) = setup(&asm!(LOOP));
+    cpu.breakpoints_mut().add(0x0205);
+    let outcome = cpu.run_until(&mut mem, |_| false).unwrap();
+    assert_eq!(outcome, StepOutcome::Break(Hit::Breakpoint(0x0205)));
+}
+
+#[test]
+fn test_conditions() {
+    let (mut cpu, mut mem) = setup(&[]);
+    let state = cpu.state_mut();
+    state.a = 0x42;
+    state.x = 3;
+    state.y = 0xFF;
+    state.pc = 0x0600;
+    state.set_flag(Flag::CRY, true);
+    mem.write(0x0200, 7);
+    mem.write(0x0203, 9);
+
+    for (text, expected) in [
+        ("A == $42", true),
+        ("a == 66", true),
+        ("A != %01000010", false),
+        ("X < 4 && Y >= 255", true),
+        ("X > 3 || Y <= 254", false),
+        ("C && !Z && !N", true),
+        ("P & 1", true),
+        ("(P & $C0) == 0", true),
+        ("[$0200] == 7", true),
+        ("[$0200 + X] == 9", true),
+        ("[$0300] == 0", true),
+        ("PC == $0600 && SP == $FF", true),
+        ("A - 2 == $40", true),
+        ("-1 == 0 - 1", true),
+        ("A ^ $40 | 1 == 3", true),
+    ] {
+        assert_eq!(condition(text).eval(&cpu, &mem), expected, "{}", text);
+    }
+}
+
+#[test]
+fn test_invalid_conditions() {
+    for (text, msg) in [
+        ("A ==", "missing value"),
+        ("Q == 1", "unknown register or flag Q"),
+        ("(A == 1", "missing )"),
+        ("[A", "missing ]"),
+        ("A == 1 2", "unexpected 2"),
+        ("A # 1", "unexpected '#'"),
+    ] {
+        let err = text.parse::<Condition>().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            format!(
+                "invalid configuration: invalid condition {:?}: {}",
+                text, msg
+            )
+        );
+    }
+}
+
+#[test]
+fn test_write_watchpoint() {
+    let (mut cpu, mut mem) = setup(&asm!(".org $0200 / LDA #$2A / STA $10 / LDA $10 / BRK"));
+    cpu.breakpoints_mut()
+        .watch(Watchpoint::new(0x10, 0x1F, Access::WRITE));
+
+    let outcome = cpu.run(&mut mem).unwrap();
+    assert_eq!(
+        outcome,
+        StepOutcome::Break(Hit::Watchpoint {
+            addr: 0x10,
+            mode: BusMode::WRITE,
+            data: 0x2A
+        })
+    );
+    // The instruction completed.
+    assert_eq!(cpu.state().pc, 0x0204);
+    assert_eq!(mem.read(&0x10), Some(0x2A));
+
+    // Reads do not count.
+    assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::Retired);
+}
+
+#[test]
+fn test_read_watchpoint_sees_every_bus_cycle() {
+    // LDA $02FF,X crossing a page reads $0200 first, then $0300.
+    let (mut cpu, mut mem) = setup(&asm!(".org $0200 / LDX #1 / LDA $02FF,X"));
+    mem.write(0x0300, 0x55);
+    cpu.breakpoints_mut()
+        .watch(Watchpoint::new(0x0300, 0x0300, Access::READ));
+    assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::Retired);
+    assert_eq!(
+        cpu.step(&mut mem).unwrap(),
+        StepOutcome::Break(Hit::Watchpoint {
+            addr: 0x0300,
+            mode: BusMode::READ,
+            data: 0x55
+        })
+    );
+
+    let (mut cpu, mut mem) = setup(&asm!(".org $0200 / LDX #1 / LDA $02FF,X"));
+    cpu.breakpoints_mut()
+        .watch(Watchpoint::new(0x0200, 0x0200, Access::ANY));
+    cpu.step(&mut mem).unwrap();
+    assert_eq!(
+        cpu.step(&mut mem).unwrap(),
+        StepOutcome::Break(Hit::Watchpoint {
+            addr: 0x0200,
+            mode: BusMode::READ,
+            data: 0xA2
+        })
+    );
+}
+
+#[test]
+fn test_watchpoint_reports_first_access() {
+    // NMOS INC writes the old value back before the new one.
+    let (mut cpu, mut mem) = setup(&asm!(".org $0200 / INC $10"));
+    mem.write(0x10, 0x41);
+    cpu.breakpoints_mut()
+        .watch(Watchpoint::new(0x10, 0x10, Access::WRITE));
+    let executed = cpu.step_instruction(&mut mem).unwrap();
+    assert_eq!(
+        executed.outcome,
+        StepOutcome::Break(Hit::Watchpoint {
+            addr: 0x10,
+            mode: BusMode::WRITE,
+            data: 0x41
+        })
+    );
+    assert_eq!(executed.cycles, 5);
+    assert_eq!(mem.read(&0x10), Some(0x42));
+}
+
+#[test]
+fn test_unwatch_and_clear() {
+    let mut breakpoints = Breakpoints::new();
+    assert!(breakpoints.is_empty());
+    breakpoints.watch(Watchpoint::new(0x10, 0x1F, Access::READ));
+    breakpoints.watch(Watchpoint::new(0x10, 0x1F, Access::WRITE));
+    breakpoints.watch(Watchpoint::new(0x20, 0x20, Access::ANY));
+    assert!(!breakpoints.unwatch(0x10, 0x10));
+    assert!(breakpoints.unwatch(0x10, 0x1F));
+    assert_eq!(
+        breakpoints.watchpoints(),
+        [Watchpoint::new(0x20, 0x20, Access::ANY)]
+    );
+    breakpoints.add(0x0200);
+    breakpoints.clear();
+    assert!(breakpoints.is_empty());
+}
+
+#[test]
+fn test_hit_display() {
+    let hits = [
+        Hit::Breakpoint(0x0605),
+        Hit::Watchpoint {
+            addr: 0x0200,
+            mode: BusMode::READ,
+            data: 0x2A,
+        },
+        Hit::Watchpoint {
+            addr: 0x0200,
+            mode: BusMode::WRITE,
+            data: 0x00,
+        },
+    ];
+    let texts: Vec<String> = hits.iter().map(Hit::to_string).collect();
+    assert_eq!(
+        texts,
+        [
+            "breakpoint at $0605",
+            "read of $2A from $0200",
+            "write of $00 to $0200"
+        ]
+    );
+}
@@ -0,0 +1,265 @@
+#![allow(clippy::bool_assert_comparison)]
+
+mod common;
+
+use common::*;
+use luvemix_rust::asm;
+use luvemix_rust::bus::*;
+use luvemix_rust::cpu::*;
+use luvemix_rust::memory::*;
+use luvemix_rust::types::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// I/O device latching the last value written to it.
+#[derive(Debug, Default)]
+struct Latch {
+    val: Data,
+    writes: Vec<(Address, Data)>,
+}
+
+impl Memory for Latch {
+    fn read(&self, _addr: &Address) -> Option<Data> {
+        Some(self.val)
+    }
+
+    fn write(&mut self, addr: Address, val: Data) {
+        self.val = val;
+        self.writes.push((addr, val));
+    }
+}
+
+/// I/O device asserting IRQ while its register holds a non-zero value.
+#[derive(Debug, Default)]
+struct Interrupter {
+    val: Data,
+}
+
+impl Memory for Interrupter {
+    fn read(&self, _addr: &Address) -> Option<Data> {
+        Some(self.val)
+    }
+
+    fn write(&mut self, _addr: Address, val: Data) {
+        self.val = val;
+    }
+
+    fn irq(&self) -> bool {
+        self.val != 0
+    }
+}
+
+#[test]
+fn test_bus_maps_device_offsets() {
+    let mut bus = Bus::new();
+    let latch = Rc::new(RefCell::new(Latch::default()));
+    bus.attach_at(latch.clone(), Mapping::new(0xD000, 0xD00F));
+
+    bus.write(0xD003, 0x42);
+
+    assert_eq!(latch.borrow().writes, vec![(0x0003, 0x42)]);
+    assert_eq!(bus.read(&0xD00F), Some(0x42));
+    assert_eq!(bus.decode(0xD010), None);
+    assert_eq!(bus.read(&0xD010), None);
+}
+
+#[test]
+fn test_bus_mirrors() {
+    // 2 KiB of RAM mirrored four times, like on the NES
+    let mut bus = Bus::new();
+    let mut mapping = Mapping::new(0x0000, 0x1FFF);
+    mapping.mask = 0x07FF;
+    bus.attach_at(Ram::new(), mapping);
+
+    bus.write(0x0123, 0x42);
+
+    for addr in [0x0123, 0x0923, 0x1123, 0x1923] {
+        assert_eq!(bus.read(&addr), Some(0x42));
+    }
+}
+
+#[test]
+fn test_bus_same_device_mapped_twice() {
+    let mut bus = Bus::new();
+    let id = bus.attach(Ram::new());
+    bus.map(id, Mapping::new(0x0000, 0x00FF));
+    bus.map(id, Mapping::new(0x8000, 0x80FF));
+
+    bus.write(0x8010, 0x42);
+
+    assert_eq!(bus.read(&0x0010), Some(0x42));
+}
+
+#[test]
+fn test_bus_priorities() {
+    let mut bus = Bus::new();
+    let ram = bus.attach_at(Ram::new(), Mapping::new(0x0000, 0xFFFF));
+    let mut io = Mapping::new(0xD000, 0xDFFF);
+    io.priority = 1;
+    let latch = bus.attach_at(Latch::default(), io);
+    // mapped later, but with lower priority
+    let mut hidden = Mapping::new(0xD000, 0xD0FF);
+    hidden.priority = -1;
+    bus.attach_at(Latch::default(), hidden);
+
+    assert_eq!(bus.decode(0xD000), Some((latch, 0x0000)));
+    assert_eq!(bus.decode(0xE000), Some((ram, 0xE000)));
+
+    // equal priority: the last mapping wins
+    let overlay = bus.attach_at(Ram::new(), Mapping::new(0xE000, 0xEFFF));
+    assert_eq!(bus.decode(0xE000), Some((overlay, 0x0000)));
+}
+
+#[test]
+fn test_bus_attach_and_detach_at_runtime() {
+    let mut bus = Bus::new();
+    bus.attach_at(
+        Ram::with_pattern(PowerOn::ONES),
+        Mapping::new(0x0000, 0xFFFF),
+    );
+    let mut io = Mapping::new(0xD000, 0xD0FF);
+    io.priority = 1;
+    let latch = bus.attach_at(Latch::default(), io);
+    assert_eq!(bus.read(&0xD000), Some(0x00));
+
+    bus.unmap(latch);
+    assert_eq!(bus.read(&0xD000), Some(0xFF));
+
+    bus.map(latch, io);
+    assert_eq!(bus.read(&0xD000), Some(0x00));
+
+    assert!(bus.detach(latch).is_some());
+    assert!(bus.detach(latch).is_none());
+    assert_eq!(bus.read(&0xD000), Some(0xFF));
+}
+
+#[test]
+fn test_bus_drives_cpu() {
+    let mut bus = Bus::new();
+    bus.attach_at(Ram::new(), Mapping::new(0x0000, 0x7FFF));
+    let latch = Rc::new(RefCell::new(Latch::default()));
+    bus.attach_at(latch.clone(), Mapping::new(0xD000, 0xD0FF));
+    // LDA #$42 ; STA $D020
+    load(&mut bus, PROGRAM_START, &[0xA9, 0x42, 0x8D, 0x20, 0xD0]);
+    let mut cpu = Cpu::new();
+    cpu.state_mut().pc = PROGRAM_START;
+
+    for _ in 0..6 {
+        bus.cycle(&mut cpu).unwrap();
+    }
+
+    assert_eq!(cpu.state().a, 0x42);
+    assert_eq!(latch.borrow().writes, vec![(0x0020, 0x42)]);
+}
+
+#[test]
+fn test_bus_rom_write_error() {
+    let mut bus = Bus::new();
+    bus.attach_at(Ram::new(), Mapping::new(0x0000, 0x7FFF));
+    let mut rom = Rom::new(&[0xFF; 0x100]);
+    rom.policy = WritePolicy::ERROR;
+    // 256 bytes mirrored throughout the upper half
+    let mut mapping = Mapping::new(0x8000, 0xFFFF);
+    mapping.mask = 0x00FF;
+    bus.attach_at(rom, mapping);
+    // STA $9010 ; LDA $9010
+    load(
+        &mut bus,
+        PROGRAM_START,
+        &[0x8D, 0x10, 0x90, 0xAD, 0x10, 0x90],
+    );
+    let mut cpu = Cpu::new();
+    cpu.state_mut().pc = PROGRAM_START;
+
+    let results: Vec<_> = (0..8).map(|_| bus.cycle(&mut cpu)).collect();
+
+    assert_eq!(results[3], Err(MemoryError::ReadOnly(0x9010)));
+    assert!(results.iter().filter(|r| r.is_err()).count() == 1);
+    assert_eq!(cpu.state().a, 0xFF);
+}
+
+#[test]
+fn test_bus_open_bus() {
+    let mut bus = Bus::new();
+    bus.attach_at(Ram::new(), Mapping::new(0x0000, 0x7FFF));
+    // LDA $9000 ; STA $9000
+    load(
+        &mut bus,
+        PROGRAM_START,
+        &[0xAD, 0x00, 0x90, 0x8D, 0x00, 0x90],
+    );
+    let mut cpu = Cpu::new();
+    cpu.state_mut().pc = PROGRAM_START;
+
+    for _ in 0..8 {
+        assert_eq!(bus.cycle(&mut cpu), Ok(()));
+    }
+
+    // the last value on the bus was the high byte of the address
+    assert_eq!(cpu.state().a, 0x90);
+    assert_eq!(bus.read(&0x9000), None);
+}
+
+#[test]
+fn test_bus_strict_mode() {
+    let mut bus = Bus::new();
+    bus.strict = true;
+    bus.attach_at(Ram::new(), Mapping::new(0x0000, 0x7FFF));
+    // LDA $9000 ; STA $9001
+    load(
+        &mut bus,
+        PROGRAM_START,
+        &[0xAD, 0x00, 0x90, 0x8D, 0x01, 0x90],
+    );
+    let mut cpu = Cpu::new();
+    cpu.state_mut().pc = PROGRAM_START;
+
+    let results: Vec<_> = (0..8).map(|_| bus.cycle(&mut cpu)).collect();
+
+    assert_eq!(results[3], Err(MemoryError::Unmapped(0x9000)));
+    assert_eq!(results[7], Err(MemoryError::Unmapped(0x9001)));
+    assert_eq!(results.iter().filter(|r| r.is_err()).count(), 2);
+    // the CPU carries on with open bus
+    assert_eq!(cpu.state().a, 0x90);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 6);
+}
+
+#[test]
+fn test_bus_device_raises_irq() {
+    let mut bus = Bus::new();
+    bus.attach_at(Ram::new(), Mapping::new(0x0000, 0xFFFF));
+    let device = Rc::new(RefCell::new(Interrupter::default()));
+    bus.attach_at(device.clone(), Mapping::new(0xD000, 0xD000));
+    let program = asm!(
+        "
+        .org $0200
+                CLI
+                LDA #1
+                STA $D000
+                NOP
+        done:   JMP done
+        .org $0300
+                INC $10
+                LDA #0
+                STA $D000
+                RTI
+        .org $FFFE
+                .word $0300
+        "
+    );
+    load(&mut bus, PROGRAM_START, &program);
+    let mut cpu = Cpu::new();
+    cpu.state_mut().pc = PROGRAM_START;
+    cpu.state_mut().sp = 0xFF;
+
+    assert!(!bus.irq());
+    // CLI, LDA, STA, NOP, the interrupt, the handler and a few loops
+    for _ in 0..12 {
+        cpu.step(&mut bus).unwrap();
+    }
+
+    assert_eq!(cpu.state().pc, 0x0207);
+    assert_eq!(bus.read(&0x0010), Some(1));
+    assert_eq!(device.borrow().val, 0);
+    assert!(!cpu.irq);
+}
@@ -0,0 +1,254 @@
+#![allow(clippy::bool_assert_comparison)]
+
+mod common;
+
+use common::*;
+use luvemix_rust::cpu::*;
+use luvemix_rust::memory::*;
+
+#[test]
+fn test_bus_implied_dummy_read() {
+    // TAX
+    let (mut cpu, mut mem) = setup(&[0xAA, 0x42]);
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![(0x0200, BusMode::READ, 0xAA), (0x0201, BusMode::READ, 0x42)]
+    );
+    assert_eq!(cpu.state().pc, 0x0201);
+}
+
+#[test]
+fn test_bus_zero_page_indexed_dummy_read() {
+    // LDA $10,X
+    let (mut cpu, mut mem) = setup(&[0xB5, 0x10]);
+    cpu.state_mut().x = 4;
+    mem.write(0x0010, 0x11);
+    mem.write(0x0014, 0x42);
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![
+            (0x0200, BusMode::READ, 0xB5),
+            (0x0201, BusMode::READ, 0x10),
+            (0x0010, BusMode::READ, 0x11),
+            (0x0014, BusMode::READ, 0x42),
+        ]
+    );
+}
+
+#[test]
+fn test_bus_indexed_indirect_dummy_read() {
+    // LDA ($20,X)
+    let (mut cpu, mut mem) = setup(&[0xA1, 0x20]);
+    cpu.state_mut().x = 4;
+    load(&mut mem, 0x0024, &[0x34, 0x12]);
+    mem.write(0x1234, 0x42);
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![
+            (0x0200, BusMode::READ, 0xA1),
+            (0x0201, BusMode::READ, 0x20),
+            (0x0020, BusMode::READ, 0x00),
+            (0x0024, BusMode::READ, 0x34),
+            (0x0025, BusMode::READ, 0x12),
+            (0x1234, BusMode::READ, 0x42),
+        ]
+    );
+}
+
+#[test]
+fn test_bus_indexed_read_page_crossing_dummy_read() {
+    // LDA $12F0,X
+    let (mut cpu, mut mem) = setup(&[0xBD, 0xF0, 0x12]);
+    cpu.state_mut().x = 0x20;
+    mem.write(0x1210, 0x11);
+    mem.write(0x1310, 0x42);
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![
+            (0x0200, BusMode::READ, 0xBD),
+            (0x0201, BusMode::READ, 0xF0),
+            (0x0202, BusMode::READ, 0x12),
+            (0x1210, BusMode::READ, 0x11),
+            (0x1310, BusMode::READ, 0x42),
+        ]
+    );
+}
+
+#[test]
+fn test_bus_indexed_write_always_dummy_reads() {
+    // STA $1200,X
+    let (mut cpu, mut mem) = setup(&[0x9D, 0x00, 0x12]);
+    cpu.state_mut().x = 4;
+    cpu.state_mut().a = 0x42;
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![
+            (0x0200, BusMode::READ, 0x9D),
+            (0x0201, BusMode::READ, 0x00),
+            (0x0202, BusMode::READ, 0x12),
+            (0x1204, BusMode::READ, 0x00),
+            (0x1204, BusMode::WRITE, 0x42),
+        ]
+    );
+
+    // STA ($20),Y
+    let (mut cpu, mut mem) = setup(&[0x91, 0x20]);
+    load(&mut mem, 0x0020, &[0xF0, 0x12]);
+    cpu.state_mut().y = 0x20;
+    cpu.state_mut().a = 0x42;
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![
+            (0x0200, BusMode::READ, 0x91),
+            (0x0201, BusMode::READ, 0x20),
+            (0x0020, BusMode::READ, 0xF0),
+            (0x0021, BusMode::READ, 0x12),
+            (0x1210, BusMode::READ, 0x00),
+            (0x1310, BusMode::WRITE, 0x42),
+        ]
+    );
+}
+
+#[test]
+fn test_bus_read_modify_write_double_write() {
+    // INC $10
+    let (mut cpu, mut mem) = setup(&[0xE6, 0x10]);
+    mem.write(0x0010, 0x05);
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![
+            (0x0200, BusMode::READ, 0xE6),
+            (0x0201, BusMode::READ, 0x10),
+            (0x0010, BusMode::READ, 0x05),
+            (0x0010, BusMode::WRITE, 0x05),
+            (0x0010, BusMode::WRITE, 0x06),
+        ]
+    );
+
+    // ASL $1200,X
+    let (mut cpu, mut mem) = setup(&[0x1E, 0x00, 0x12]);
+    cpu.state_mut().x = 1;
+    mem.write(0x1201, 0x21);
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![
+            (0x0200, BusMode::READ, 0x1E),
+            (0x0201, BusMode::READ, 0x00),
+            (0x0202, BusMode::READ, 0x12),
+            (0x1201, BusMode::READ, 0x21),
+            (0x1201, BusMode::READ, 0x21),
+            (0x1201, BusMode::WRITE, 0x21),
+            (0x1201, BusMode::WRITE, 0x42),
+        ]
+    );
+}
+
+#[test]
+fn test_bus_branch_dummy_reads() {
+    // BNE +$10 across a page boundary
+    let (mut cpu, mut mem) = setup(&[]);
+    cpu.state_mut().pc = 0x02F0;
+    load(&mut mem, 0x02F0, &[0xD0, 0x10]);
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![
+            (0x02F0, BusMode::READ, 0xD0),
+            (0x02F1, BusMode::READ, 0x10),
+            (0x02F2, BusMode::READ, 0x00),
+            (0x0202, BusMode::READ, 0x00),
+        ]
+    );
+    assert_eq!(cpu.state().pc, 0x0302);
+}
+
+#[test]
+fn test_bus_stack_instructions() {
+    // PHA, PLA
+    let (mut cpu, mut mem) = setup(&[0x48, 0x68]);
+    cpu.state_mut().a = 0x42;
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![
+            (0x0200, BusMode::READ, 0x48),
+            (0x0201, BusMode::READ, 0x68),
+            (0x01FF, BusMode::WRITE, 0x42),
+        ]
+    );
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![
+            (0x0201, BusMode::READ, 0x68),
+            (0x0202, BusMode::READ, 0x00),
+            (0x01FE, BusMode::READ, 0x00),
+            (0x01FF, BusMode::READ, 0x42),
+        ]
+    );
+}
+
+#[test]
+fn test_bus_jsr_rts() {
+    let (mut cpu, mut mem) = setup(&[0x20, 0x00, 0x30]);
+    mem.write(0x3000, 0x60);
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![
+            (0x0200, BusMode::READ, 0x20),
+            (0x0201, BusMode::READ, 0x00),
+            (0x01FF, BusMode::READ, 0x00),
+            (0x01FF, BusMode::WRITE, 0x02),
+            (0x01FE, BusMode::WRITE, 0x02),
+            (0x0202, BusMode::READ, 0x30),
+        ]
+    );
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![
+            (0x3000, BusMode::READ, 0x60),
+            (0x3001, BusMode::READ, 0x00),
+            (0x01FD, BusMode::READ, 0x00),
+            (0x01FE, BusMode::READ, 0x02),
+            (0x01FF, BusMode::READ, 0x02),
+            (0x0202, BusMode::READ, 0x30),
+        ]
+    );
+    assert_eq!(cpu.state().pc, 0x0203);
+}
@@ -0,0 +1,228 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Write a temporary image, named after the test using it.
+/// Returns its path.
+fn image(name: &str, bytes: &[u8]) -> String {
+    let path = std::env::temp_dir().join(format!("luvemix-{}-{}", std::process::id(), name));
+    std::fs::write(&path, bytes).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn luvemix(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_luvemix"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Run with `input` on standard input.
+fn luvemix_with_input(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_luvemix"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_run_until_brk() {
+    // LDA #$2A ; STA $0200 ; BRK
+    let path = image("brk.bin", &[0xA9, 0x2A, 0x8D, 0x00, 0x02, 0x00]);
+
+    let output = luvemix(&[
+        "run",
+        &path,
+        "--load",
+        "0x0600",
+        "--start",
+        "$0600",
+        "--dump",
+        "0x0200:0x0211",
+    ]);
+    std::fs::remove_file(&path).unwrap();
+
+    let out = stdout(&output);
+    assert_eq!(output.status.code(), Some(0), "{}", out);
+    assert_eq!(stderr(&output), "BRK at $0605\n");
+    assert!(!out.contains("BRK at $0605"), "{}", out);
+    assert!(
+        out.contains("PC=0605 A=2A X=00 Y=00 SP=FF P=nv-bdizc cycles=6"),
+        "{}",
+        out
+    );
+    assert!(out.contains("0200: 2A 00 00"), "{}", out);
+    assert!(out.contains("0210: 00 00\n"), "{}", out);
+}
+
+#[test]
+fn test_run_from_reset_vector_until_jam() {
+    // LDX #$07 ; JAM, with the reset vector pointing at it
+    let mut bytes = vec![0xA2, 0x07, 0x02];
+    bytes.resize(12, 0xEA);
+    bytes.extend([0xF0, 0xFF, 0x00, 0x00]);
+    let path = image("jam.bin", &bytes);
+
+    let output = luvemix(&["run", &path, "--load", "0xFFF0"]);
+    std::fs::remove_file(&path).unwrap();
+
+    let out = stdout(&output);
+    assert_eq!(output.status.code(), Some(0), "{}", out);
+    assert_eq!(stderr(&output), "CPU jammed by $02 at $FFF2\n");
+    assert!(!out.contains("CPU jammed by $02 at $FFF2"), "{}", out);
+    assert!(out.contains("X=07"), "{}", out);
+}
+
+#[test]
+fn test_run_until_trap() {
+    // INX ; JMP $0600
+    let path = image("trap.bin", &[0xE8, 0x4C, 0x00, 0x06]);
+
+    let output = luvemix(&[
+        "run", &path, "--load", "1536", "--start", "1536", "--trap", "0x0600",
+    ]);
+    std::fs::remove_file(&path).unwrap();
+
+    let out = stdout(&output);
+    assert_eq!(output.status.code(), Some(0), "{}", out);
+    assert_eq!(stderr(&output), "trap at $0600\n");
+    assert!(!out.contains("trap at $0600"), "{}", out);
+    assert!(out.contains("X=01"), "{}", out);
+}
+
+#[test]
+fn test_run_cycle_limit() {
+    // JMP $0600
+    let path = image("limit.bin", &[0x4C, 0x00, 0x06]);
+
+    let output = luvemix(&[
+        "run",
+        &path,
+        "--load",
+        "0x600",
+        "--start",
+        "0x600",
+        "--max-cycles",
+        "30",
+        "--cpu",
+        "65c02",
+    ]);
+    std::fs::remove_file(&path).unwrap();
+
+    let out = stdout(&output);
+    assert_eq!(output.status.code(), Some(2), "{}", out);
+    assert_eq!(stderr(&output), "cycle limit reached\n");
+    assert!(!out.contains("cycle limit reached"), "{}", out);
+    assert!(out.contains("cycles=30"), "{}", out);
+}
+
+#[test]
+fn test_run_bad_arguments() {
+    let output = luvemix(&["run", "image.bin", "--speed", "fast"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown option: --speed"));
+
+    let output = luvemix(&["run", "image.bin", "--cpu", "z80"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown CPU variant: z80"));
+
+    let output = luvemix(&["gdb", "image.bin", "--port", "65536"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("port out of range: 65536"));
+
+    let output = luvemix(&["run", "/nonexistent/image.bin"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("/nonexistent/image.bin"));
+
+    let output = luvemix(&[]);
+    assert_eq!(output.status.code(), Some(64));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage: luvemix run"));
+}
+
+#[test]
+fn test_run_detects_format() {
+    // LDA #$2A ; STA $0200 ; BRK, starting at the entry point
+    let text = ":06060000A92A8D00020092\n:0400000500000600F1\n:00000001FF\n";
+    let path = image("detect.hex", text.as_bytes());
+
+    let output = luvemix(&["run", &path]);
+    std::fs::remove_file(&path).unwrap();
+
+    let out = stdout(&output);
+    assert_eq!(output.status.code(), Some(0), "{}", out);
+    assert_eq!(stderr(&output), "BRK at $0605\n");
+
+    let text = "S1090600A92A8D0002008F\nS9030600F6\n";
+    let path = image("checksum.txt", text.as_bytes());
+
+    let output = luvemix(&["run", &path, "--format", "s19"]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("line 1: checksum is $8F, expected $8E")
+    );
+}
+
+#[test]
+fn test_disasm() {
+    // LDA #$2A ; STA $0200 ; BRK
+    let path = image(
+        "disasm.prg",
+        &[0x00, 0x06, 0xA9, 0x2A, 0x8D, 0x00, 0x02, 0x00],
+    );
+
+    let output = luvemix(&["disasm", &path]);
+    let ranged = luvemix(&["disasm", &path, "--range", "0x0602:0x0604", "--cpu", "wdc"]);
+    let bad = luvemix(&["disasm", &path, "--max-cycles", "10"]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "0600  A9 2A     LDA #$2A\n0602  8D 00 02  STA $0200\n0605  00        BRK\n"
+    );
+    assert_eq!(stdout(&ranged), "0602  8D 00 02  STA $0200\n");
+    assert_eq!(bad.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&bad.stderr).contains("unknown option: --max-cycles"));
+}
+
+#[test]
+fn test_debug() {
+    // LDA #$2A ; STA $0200 ; BRK
+    let path = image("debug.bin", &[0xA9, 0x2A, 0x8D, 0x00, 0x02, 0x00]);
+
+    let output = luvemix_with_input(
+        &["debug", &path, "--load", "0x0600", "--start", "0x0600"],
+        "break 602\ncontinue\nstep\nmem 200 201\n",
+    );
+    std::fs::remove_file(&path).unwrap();
+
+    let out = stdout(&output);
+    assert_eq!(output.status.code(), Some(0), "{}", out);
+    assert!(out.starts_with("PC=0600 A=00"), "{}", out);
+    assert!(
+        out.contains("> breakpoint at $0602\nPC=0602 A=2A"),
+        "{}",
+        out
+    );
+    assert!(out.contains("0605  00        BRK\n"), "{}", out);
+    assert!(out.ends_with("> 0200: 2A 00\n> \n"), "{}", out);
+}
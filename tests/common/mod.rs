@@ -0,0 +1,131 @@
+// Helpers shared by the integration tests. Not every test uses all of them.
+#![allow(dead_code)]
+
+use luvemix_rust::cpu::*;
+use luvemix_rust::memory::*;
+use luvemix_rust::types::*;
+
+pub const PROGRAM_START: Address = 0x0200;
+
+pub fn load(mem: &mut impl Memory, addr: Address, bytes: &[Data]) {
+    for (i, byte) in bytes.iter().enumerate() {
+        mem.write(addr + i as Address, *byte);
+    }
+}
+
+/// Cpu about to execute `program`, which is loaded at PROGRAM_START.
+pub fn setup(program: &[Data]) -> (Cpu, CheapoMemory) {
+    setup_variant(Variant::NMOS, program)
+}
+
+pub fn setup_variant(variant: Variant, program: &[Data]) -> (Cpu, CheapoMemory) {
+    let mut cpu = Cpu::with_variant(variant);
+    let mut mem = CheapoMemory::new();
+    load(&mut mem, PROGRAM_START, program);
+    cpu.state_mut().pc = PROGRAM_START;
+    cpu.state_mut().sp = 0xFF;
+    (cpu, mem)
+}
+
+pub fn run_cycle(cpu: &mut Cpu, mem: &mut CheapoMemory) {
+    cpu.setup_cycle();
+    match cpu.rwb {
+        BusMode::READ => cpu.data_bus = mem.read(&cpu.addr_bus).unwrap_or(0),
+        BusMode::WRITE => mem.write(cpu.addr_bus, cpu.data_bus),
+    }
+    cpu.complete_cycle();
+}
+
+/// Run cycles until the next instruction is about to be fetched.
+/// Returns the number of cycles taken.
+pub fn run_instruction(cpu: &mut Cpu, mem: &mut CheapoMemory) -> usize {
+    let mut cycles = 0;
+    loop {
+        run_cycle(cpu, mem);
+        cycles += 1;
+        cpu.setup_cycle();
+        if cpu.sync {
+            return cycles;
+        }
+    }
+}
+
+/// Run one instruction, recording every bus cycle.
+pub fn trace_instruction(cpu: &mut Cpu, mem: &mut CheapoMemory) -> Vec<(Address, BusMode, Data)> {
+    let mut trace = Vec::new();
+    loop {
+        run_cycle(cpu, mem);
+        trace.push((cpu.addr_bus, cpu.rwb, cpu.data_bus));
+        cpu.setup_cycle();
+        if cpu.sync {
+            return trace;
+        }
+    }
+}
+
+pub fn opcodes_for(op: Operation) -> Vec<(Data, AddressingMode)> {
+    (0..=0xFF)
+        .filter_map(
+            |opcode| match decode(opcode).or_else(|| decode_undocumented(opcode)) {
+                Some(i) if i.op == op => Some((opcode, i.mode)),
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+pub const OPERAND_ADDR: Address = 0x1234;
+
+/// Cpu about to execute `opcode`, with memory and index registers
+/// arranged so that its operand is `val`. Returns the operand address.
+pub fn setup_operand(
+    opcode: Data,
+    mode: AddressingMode,
+    val: Data,
+) -> (Cpu, CheapoMemory, Address) {
+    use AddressingMode::*;
+    let (program, addr): (Vec<Data>, Address) = match mode {
+        IMM => (vec![opcode, val], PROGRAM_START + 1),
+        ZPG => (vec![opcode, 0x10], 0x0010),
+        ZPX | ZPY => (vec![opcode, 0x10], 0x0014),
+        ABS => (vec![opcode, 0x34, 0x12], OPERAND_ADDR),
+        ABX | ABY => (vec![opcode, 0x30, 0x12], OPERAND_ADDR),
+        IZX => (vec![opcode, 0x20], OPERAND_ADDR),
+        IZY => (vec![opcode, 0x20], OPERAND_ADDR),
+        _ => panic!("no memory operand for {:?}", mode),
+    };
+    let (mut cpu, mut mem) = setup(&program);
+    cpu.state_mut().x = 4;
+    cpu.state_mut().y = 4;
+    load(&mut mem, 0x0024, &[0x34, 0x12]);
+    load(&mut mem, 0x0020, &[0x30, 0x12]);
+    mem.write(addr, val);
+    (cpu, mem, addr)
+}
+
+/// Run `op` in all its addressing modes with operand `val`,
+/// checking each one with `check`.
+pub fn for_each_mode<F>(op: Operation, val: Data, prepare: fn(&mut CpuState), check: F)
+where
+    F: Fn(&Cpu, &CheapoMemory, Address),
+{
+    let opcodes = opcodes_for(op);
+    assert!(!opcodes.is_empty());
+    for (opcode, mode) in opcodes {
+        if mode == AddressingMode::ACC {
+            continue;
+        }
+        let (mut cpu, mut mem, addr) = setup_operand(opcode, mode, val);
+        prepare(cpu.state_mut());
+        run_instruction(&mut cpu, &mut mem);
+        check(&cpu, &mem, addr);
+    }
+}
+
+/// Cpu running a NOP slide at PROGRAM_START, with interrupt handlers
+/// at 0x3000 (IRQ/BRK), 0x4000 (NMI) and 0x5000 (reset).
+pub fn setup_interrupts() -> (Cpu, CheapoMemory) {
+    let (cpu, mut mem) = setup(&[0xEA; 16]);
+    load(&mut mem, 0xFFFA, &[0x00, 0x40, 0x00, 0x50, 0x00, 0x30]);
+    (cpu, mem)
+}
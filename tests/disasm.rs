@@ -0,0 +1,169 @@
+use luvemix_rust::cpu::*;
+use luvemix_rust::disasm::*;
+use luvemix_rust::memory::*;
+use luvemix_rust::types::*;
+
+fn ram_with(addr: Address, bytes: &[Data]) -> Ram {
+    let mut mem = Ram::new();
+    for (i, val) in bytes.iter().enumerate() {
+        mem.write(addr + i as Address, *val);
+    }
+    mem
+}
+
+fn texts(variant: Variant, bytes: &[Data]) -> Vec<String> {
+    let mem = ram_with(0x0600, bytes);
+    disassemble(variant, &mem, 0x0600, 0x0600 + bytes.len() as Address - 1)
+        .iter()
+        .map(Disassembled::text)
+        .collect()
+}
+
+#[test]
+fn test_addressing_mode_syntax() {
+    #[rustfmt::skip]
+    let program = [
+        0xEA,                   // NOP
+        0x0A,                   // ASL A
+        0xA9, 0x2A,             // LDA #$2A
+        0xA5, 0x12,             // LDA $12
+        0xB5, 0x12,             // LDA $12,X
+        0xB6, 0x12,             // LDX $12,Y
+        0xAD, 0x34, 0x12,       // LDA $1234
+        0xBD, 0x34, 0x12,       // LDA $1234,X
+        0xB9, 0x34, 0x12,       // LDA $1234,Y
+        0x6C, 0x34, 0x12,       // JMP ($1234)
+        0xA1, 0x12,             // LDA ($12,X)
+        0xB1, 0x12,             // LDA ($12),Y
+        0xD0, 0xFE,             // BNE *
+        0x10, 0x10,             // BPL *+$12
+    ];
+
+    assert_eq!(
+        texts(Variant::NMOS, &program),
+        [
+            "NOP",
+            "ASL A",
+            "LDA #$2A",
+            "LDA $12",
+            "LDA $12,X",
+            "LDX $12,Y",
+            "LDA $1234",
+            "LDA $1234,X",
+            "LDA $1234,Y",
+            "JMP ($1234)",
+            "LDA ($12,X)",
+            "LDA ($12),Y",
+            "BNE $061A",
+            "BPL $062E",
+        ]
+    );
+}
+
+#[test]
+fn test_65c02_syntax() {
+    #[rustfmt::skip]
+    let program = [
+        0xB2, 0x12,             // LDA ($12)
+        0x7C, 0x34, 0x12,       // JMP ($1234,X)
+        0x37, 0x12,             // RMB3 $12
+        0xC7, 0x12,             // SMB4 $12
+        0x0F, 0x12, 0xFD,       // BBR0 $12,*
+        0xFF, 0x12, 0x10,       // BBS7 $12,*+$13
+        0x80, 0x00,             // BRA *+2
+    ];
+
+    assert_eq!(
+        texts(Variant::ROCKWELL, &program),
+        [
+            "LDA ($12)",
+            "JMP ($1234,X)",
+            "RMB3 $12",
+            "SMB4 $12",
+            "BBR0 $12,$0609",
+            "BBS7 $12,$061F",
+            "BRA $0611",
+        ]
+    );
+}
+
+#[test]
+fn test_respects_variant() {
+    let program = [0x07, 0x12, 0xCB, 0xEA];
+
+    assert_eq!(texts(Variant::NMOS, &program), ["SLO $12", "SBX #$EA"]);
+    assert_eq!(texts(Variant::CMOS, &program), ["NOP", "ORA ($CB)", "NOP"]);
+    assert_eq!(
+        texts(Variant::ROCKWELL, &program),
+        ["RMB0 $12", "NOP", "NOP"]
+    );
+    assert_eq!(texts(Variant::WDC, &program), ["RMB0 $12", "WAI", "NOP"]);
+}
+
+#[test]
+fn test_listing() {
+    let mut mem = ram_with(0xFFFE, &[0x4C, 0x00]);
+    mem.write(0x0000, 0xA9);
+
+    let line = disassemble_at(Variant::NMOS, &mem, 0xFFFE);
+
+    // operands wrap around to the start of memory
+    assert_eq!(line.operands(), &[0x00, 0xA9]);
+    assert_eq!(line.size(), 3);
+    assert_eq!(line.next(), 0x0001);
+    assert_eq!(line.to_string(), "FFFE  4C 00 A9  JMP $A900");
+
+    let lines = disassemble(Variant::NMOS, &mem, 0xFFFF, 0xFFFF);
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].to_string(), "FFFF  00        BRK");
+}
+
+#[test]
+fn test_operand_lengths_match_cpu() {
+    for variant in [
+        Variant::NMOS,
+        Variant::CMOS,
+        Variant::ROCKWELL,
+        Variant::WDC,
+        Variant::RP2A03,
+    ] {
+        for opcode in 0..=0xFF {
+            let mut mem = ram_with(0x0200, &[opcode, 0x10, 0x03]);
+            let mut cpu = Cpu::with_variant(variant);
+            cpu.state_mut().pc = 0x0200;
+            cpu.state_mut().sp = 0xFF;
+
+            let line = disassemble_at(variant, &mem, 0x0200);
+            let executed = match cpu.step_instruction(&mut mem) {
+                Ok(executed) if executed.outcome == StepOutcome::Retired => executed,
+                _ => continue,
+            };
+
+            assert_eq!(line.instruction, executed.instruction);
+            if line.instruction.op != Operation::BRK {
+                assert_eq!(
+                    line.operands(),
+                    executed.operands(),
+                    "{:?} {:02X}",
+                    variant,
+                    opcode
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_executed_display() {
+    // LDA ($12),Y
+    let mut mem = ram_with(0x0600, &[0xB1, 0x12]);
+    let mut cpu = Cpu::new();
+    cpu.state_mut().pc = 0x0600;
+    cpu.irq = true;
+
+    let executed = cpu.step_instruction(&mut mem).unwrap();
+    assert_eq!(executed.to_string(), "LDA ($12),Y");
+
+    let executed = cpu.step_instruction(&mut mem).unwrap();
+    assert_eq!(executed.to_string(), "IRQ");
+}
@@ -0,0 +1,308 @@
+mod common;
+use common::*;
+
+use luvemix_rust::asm;
+use luvemix_rust::cpu::*;
+use luvemix_rust::gdb::*;
+use luvemix_rust::memory::*;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::JoinHandle;
+
+/// A scripted GDB, talking to a stub serving on another thread.
+struct Client {
+    stream: TcpStream,
+    ack: bool,
+}
+
+type Server = JoinHandle<GdbStub<CheapoMemory>>;
+
+fn connect(cpu: Cpu, mem: CheapoMemory) -> (Client, Server) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut stub = GdbStub::new(cpu, mem);
+        stub.serve(stream).unwrap();
+        stub
+    });
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_nodelay(true).unwrap();
+    (Client { stream, ack: true }, server)
+}
+
+fn start(program: &[u8]) -> (Client, Server) {
+    let (cpu, mem) = setup(program);
+    connect(cpu, mem)
+}
+
+fn frame(data: &str) -> String {
+    let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+    format!("${}#{:02x}", data, sum)
+}
+
+impl Client {
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) {
+        self.stream.write_all(bytes).unwrap();
+    }
+
+    fn receive(&mut self) -> String {
+        assert_eq!(self.read_byte(), b'$');
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let data = String::from_utf8(data).unwrap();
+        let sum = [self.read_byte(), self.read_byte()];
+        assert_eq!(
+            std::str::from_utf8(&sum).unwrap(),
+            &frame(&data)[data.len() + 2..]
+        );
+        if self.ack {
+            self.send_raw(b"+");
+        }
+        data
+    }
+
+    /// Send a packet and return the reply.
+    fn send(&mut self, data: &str) -> String {
+        self.send_raw(frame(data).as_bytes());
+        if self.ack {
+            assert_eq!(self.read_byte(), b'+');
+        }
+        self.receive()
+    }
+
+    fn detach(mut self, server: Server) -> GdbStub<CheapoMemory> {
+        assert_eq!(self.send("D"), "OK");
+        server.join().unwrap()
+    }
+}
+
+#[test]
+fn test_query_supported() {
+    let (mut gdb, server) = start(&[0xEA]);
+    let reply = gdb.send("qSupported:multiprocess+;swbreak+");
+    assert!(reply.contains("qXfer:features:read+"));
+    assert!(reply.contains("QStartNoAckMode+"));
+    assert_eq!(gdb.send("qAttached"), "1");
+    assert_eq!(gdb.send("vMustReplyEmpty"), "");
+    gdb.detach(server);
+}
+
+#[test]
+fn test_target_description_in_chunks() {
+    let (mut gdb, server) = start(&[0xEA]);
+    let mut xml = String::new();
+    loop {
+        let reply = gdb.send(&format!(
+            "qXfer:features:read:target.xml:{:x},40",
+            xml.len()
+        ));
+        let (more, chunk) = reply.split_at(1);
+        assert!(chunk.len() <= 0x40);
+        xml.push_str(chunk);
+        if more == "l" {
+            break;
+        }
+        assert_eq!(more, "m");
+    }
+    assert!(xml.contains(r#"<reg name="pc" bitsize="16" type="code_ptr"/>"#));
+    assert!(xml.contains(r#"<reg name="p" bitsize="8" type="uint8"/>"#));
+    gdb.detach(server);
+}
+
+#[test]
+fn test_read_and_write_registers() {
+    let (mut gdb, server) = start(&[0xEA]);
+    assert_eq!(gdb.send("?"), "S05");
+    assert_eq!(gdb.send("g"), "0002ff00000020");
+    assert_eq!(gdb.send("G3412fd01020381"), "OK");
+    assert_eq!(gdb.send("p0"), "3412");
+    assert_eq!(gdb.send("p2"), "01");
+    assert_eq!(gdb.send("P4=2a"), "OK");
+    assert_eq!(gdb.send("P0=0006"), "OK");
+    assert_eq!(gdb.send("p6"), "E16");
+    assert_eq!(gdb.send("P0=06"), "E16");
+    assert_eq!(gdb.send("G00"), "E16");
+    let stub = gdb.detach(server);
+    let state = stub.cpu.state();
+    assert_eq!(state.pc, 0x0600);
+    assert_eq!(
+        (state.sp, state.a, state.x, state.y),
+        (0xFD, 0x01, 0x02, 0x2A)
+    );
+    assert_eq!(state.sr() & 0x81, 0x81);
+}
+
+#[test]
+fn test_read_and_write_memory() {
+    let (mut gdb, server) = start(&[0xA9, 0x2A]);
+    assert_eq!(gdb.send("m200,2"), "a92a");
+    assert_eq!(gdb.send("M10,3:010203"), "OK");
+    assert_eq!(gdb.send("m10,4"), "01020300");
+    assert_eq!(gdb.send("mfffe,2"), "0000");
+    assert_eq!(gdb.send("mffff,2"), "E16");
+    assert_eq!(gdb.send("M10,2:01"), "E16");
+    assert_eq!(gdb.send("M10,1:zz"), "E16");
+    let stub = gdb.detach(server);
+    assert_eq!(stub.mem.read(&0x0012), Some(3));
+}
+
+#[test]
+fn test_breakpoint_and_continue() {
+    let program = asm!(
+        "
+        .org $0200
+                LDX #3
+        @loop:  DEX
+                BNE @loop
+                STX $10
+        done:   JMP done
+        "
+    );
+    let (mut gdb, server) = start(&program);
+    assert_eq!(gdb.send("Z0,205,1"), "OK");
+    assert_eq!(gdb.send("c"), "S05");
+    assert_eq!(gdb.send("p0"), "0502");
+    assert_eq!(gdb.send("p3"), "00");
+
+    // The kind of a breakpoint is not a length.
+    assert_eq!(gdb.send("Z0,ffff,2"), "OK");
+    assert_eq!(gdb.send("z0,ffff,2"), "OK");
+
+    // Continuing from a breakpoint runs past it.
+    assert_eq!(gdb.send("z0,205,1"), "OK");
+    assert_eq!(gdb.send("Z1,207,1"), "OK");
+    assert_eq!(gdb.send("c"), "S05");
+    assert_eq!(gdb.send("p0"), "0702");
+    assert_eq!(gdb.send("c"), "S05");
+    assert_eq!(gdb.send("p0"), "0702");
+    gdb.detach(server);
+}
+
+#[test]
+fn test_single_step() {
+    let (mut gdb, server) = start(&asm!("LDA #1 / LDX #2 / NOP"));
+    assert_eq!(gdb.send("s"), "S05");
+    assert_eq!(gdb.send("g"), "0202ff01000020");
+    assert_eq!(gdb.send("s"), "S05");
+    assert_eq!(gdb.send("g"), "0402ff01020020");
+    assert_eq!(gdb.send("s200"), "S05");
+    assert_eq!(gdb.send("p0"), "0202");
+    assert_eq!(gdb.send("?"), "S05");
+    gdb.detach(server);
+}
+
+#[test]
+fn test_watchpoints() {
+    let program = asm!(
+        "
+        .org $0200
+                LDA $11
+                STA $10
+        done:   JMP done
+        "
+    );
+    let (mut gdb, server) = start(&program);
+    assert_eq!(gdb.send("Z3,11,1"), "OK");
+    assert_eq!(gdb.send("Z2,10,1"), "OK");
+    assert_eq!(gdb.send("c"), "T05rwatch:0011;");
+    assert_eq!(gdb.send("c"), "T05watch:0010;");
+    assert_eq!(gdb.send("?"), "T05watch:0010;");
+
+    assert_eq!(gdb.send("z3,11,1"), "OK");
+    assert_eq!(gdb.send("z2,10,1"), "OK");
+    assert_eq!(gdb.send("Z4,10,2"), "OK");
+    assert_eq!(gdb.send("s200"), "T05awatch:0011;");
+    assert_eq!(gdb.send("Z5,10,1"), "");
+    assert_eq!(gdb.send("Z2,200,10000"), "E16");
+    assert_eq!(gdb.send("Z2,ffff,2"), "E16");
+    assert_eq!(gdb.send("Z0,10000,1"), "E16");
+    gdb.detach(server);
+}
+
+#[test]
+fn test_jam_stops_with_sigill() {
+    let (mut gdb, server) = start(&[0x02]);
+    assert_eq!(gdb.send("c"), "S04");
+    gdb.detach(server);
+}
+
+#[test]
+fn test_interrupt_running_target() {
+    let (mut gdb, server) = start(&asm!(".org $0200 / loop: JMP loop"));
+    gdb.send_raw(frame("c").as_bytes());
+    assert_eq!(gdb.read_byte(), b'+');
+    gdb.send_raw(&[0x03]);
+    assert_eq!(gdb.receive(), "S02");
+    assert_eq!(gdb.send("p0"), "0002");
+    gdb.detach(server);
+}
+
+#[test]
+fn test_packets_sent_while_running_are_kept() {
+    let (mut gdb, server) = start(&asm!(".org $0200 / loop: JMP loop"));
+    gdb.send_raw(frame("c").as_bytes());
+    assert_eq!(gdb.read_byte(), b'+');
+    let mut bytes = frame("p0").into_bytes();
+    bytes.push(0x03);
+    gdb.send_raw(&bytes);
+    assert_eq!(gdb.receive(), "S02");
+    assert_eq!(gdb.read_byte(), b'+');
+    assert_eq!(gdb.receive(), "0002");
+    gdb.detach(server);
+}
+
+#[test]
+fn test_bad_checksum_is_retransmitted() {
+    let (mut gdb, server) = start(&[0xEA]);
+    gdb.send_raw(b"$g#00");
+    assert_eq!(gdb.read_byte(), b'-');
+    assert_eq!(gdb.send("g"), "0002ff00000020");
+
+    // A negative acknowledgement asks the stub to send again.
+    gdb.send_raw(frame("p0").as_bytes());
+    assert_eq!(gdb.read_byte(), b'+');
+    gdb.ack = false;
+    assert_eq!(gdb.receive(), "0002");
+    gdb.send_raw(b"-");
+    gdb.ack = true;
+    assert_eq!(gdb.receive(), "0002");
+    gdb.detach(server);
+}
+
+#[test]
+fn test_no_ack_mode() {
+    let (mut gdb, server) = start(&[0xEA]);
+    assert_eq!(gdb.send("QStartNoAckMode"), "OK");
+    gdb.ack = false;
+    assert_eq!(gdb.send("p0"), "0002");
+    gdb.detach(server);
+}
+
+#[test]
+fn test_session_ends_when_gdb_hangs_up() {
+    let (mut gdb, server) = start(&[0xEA]);
+    assert_eq!(gdb.send("M200,1:e8"), "OK");
+    drop(gdb);
+    let stub = server.join().unwrap();
+    assert_eq!(stub.mem.read(&0x0200), Some(0xE8));
+}
+
+#[test]
+fn test_kill_ends_session() {
+    let (mut gdb, server) = start(&[0xEA]);
+    gdb.send_raw(frame("k").as_bytes());
+    assert_eq!(gdb.read_byte(), b'+');
+    server.join().unwrap();
+}
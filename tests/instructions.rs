@@ -0,0 +1,1075 @@
+#![allow(clippy::bool_assert_comparison)]
+
+mod common;
+
+use common::*;
+use luvemix_rust::cpu::*;
+use luvemix_rust::memory::*;
+use luvemix_rust::types::*;
+
+#[test]
+fn test_decode_documented_opcodes() {
+    let count = (0..=0xFF).filter(|op| decode(*op).is_some()).count();
+
+    assert_eq!(count, 151);
+}
+
+#[test]
+fn test_lda() {
+    for_each_mode(
+        Operation::LDA,
+        0x80,
+        |_| {},
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0x80);
+            assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), false);
+        },
+    );
+    for_each_mode(
+        Operation::LDA,
+        0,
+        |s| s.a = 1,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0);
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+        },
+    );
+}
+
+#[test]
+fn test_ldx() {
+    for_each_mode(
+        Operation::LDX,
+        0x42,
+        |_| {},
+        |cpu, _, _| {
+            // LDX $nn,Y and LDX $nnnn,Y use Y, so X must be reloaded
+            assert_eq!(cpu.state().x, 0x42);
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), false);
+        },
+    );
+}
+
+#[test]
+fn test_ldy() {
+    for_each_mode(
+        Operation::LDY,
+        0x42,
+        |_| {},
+        |cpu, _, _| {
+            assert_eq!(cpu.state().y, 0x42);
+            assert_eq!(cpu.state().get_flag(Flag::NEG), false);
+        },
+    );
+}
+
+#[test]
+fn test_sta() {
+    for_each_mode(
+        Operation::STA,
+        0,
+        |s| s.a = 0x99,
+        |_, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0x99));
+        },
+    );
+}
+
+#[test]
+fn test_stx() {
+    for_each_mode(
+        Operation::STX,
+        0,
+        |s| s.x = 0x04,
+        |_, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0x04));
+        },
+    );
+}
+
+#[test]
+fn test_sty() {
+    for_each_mode(
+        Operation::STY,
+        0,
+        |s| s.y = 0x04,
+        |_, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0x04));
+        },
+    );
+}
+
+#[test]
+fn test_adc() {
+    for_each_mode(
+        Operation::ADC,
+        0x01,
+        |s| s.a = 0x41,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0x42);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), false);
+            assert_eq!(cpu.state().get_flag(Flag::OVF), false);
+        },
+    );
+    for_each_mode(
+        Operation::ADC,
+        0xFF,
+        |s| {
+            s.a = 0x01;
+            s.set_flag(Flag::CRY, true);
+        },
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0x01);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+            assert_eq!(cpu.state().get_flag(Flag::OVF), false);
+        },
+    );
+    for_each_mode(
+        Operation::ADC,
+        0x50,
+        |s| s.a = 0x50,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0xA0);
+            assert_eq!(cpu.state().get_flag(Flag::OVF), true);
+            assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+        },
+    );
+}
+
+#[test]
+fn test_sbc() {
+    for_each_mode(
+        Operation::SBC,
+        0x01,
+        |s| {
+            s.a = 0x43;
+            s.set_flag(Flag::CRY, true);
+        },
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0x42);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+        },
+    );
+    for_each_mode(
+        Operation::SBC,
+        0x01,
+        |s| s.a = 0x00,
+        |cpu, _, _| {
+            // borrow in and out
+            assert_eq!(cpu.state().a, 0xFE);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), false);
+            assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+        },
+    );
+    for_each_mode(
+        Operation::SBC,
+        0x01,
+        |s| {
+            s.a = 0x80;
+            s.set_flag(Flag::CRY, true);
+        },
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0x7F);
+            assert_eq!(cpu.state().get_flag(Flag::OVF), true);
+        },
+    );
+}
+
+#[test]
+fn test_adc_decimal() {
+    let cases = [
+        // a, m, carry in => a, carry out
+        (0x00, 0x00, false, 0x00, false),
+        (0x09, 0x01, false, 0x10, false),
+        (0x58, 0x46, true, 0x05, true),
+        (0x12, 0x34, false, 0x46, false),
+        (0x81, 0x92, false, 0x73, true),
+        (0x99, 0x00, true, 0x00, true),
+    ];
+    for (a, m, c, result, carry) in cases.iter() {
+        let (mut cpu, mut mem) = setup(&[0x69, *m]);
+        cpu.state_mut().a = *a;
+        cpu.state_mut().set_flag(Flag::CRY, *c);
+        cpu.state_mut().set_flag(Flag::DEC, true);
+
+        run_instruction(&mut cpu, &mut mem);
+
+        assert_eq!(cpu.state().a, *result, "{:02X} + {:02X}", a, m);
+        assert_eq!(cpu.state().get_flag(Flag::CRY), *carry);
+    }
+}
+
+#[test]
+fn test_adc_decimal_nmos_flags() {
+    // $99 + $01 = $00, but Z reflects the binary sum $9A
+    // and N the intermediate sum $A0
+    let (mut cpu, mut mem) = setup(&[0x69, 0x01]);
+    cpu.state_mut().a = 0x99;
+    cpu.state_mut().set_flag(Flag::DEC, true);
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().a, 0x00);
+    assert_eq!(cpu.state().get_flag(Flag::ZRO), false);
+    assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+    assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+}
+
+#[test]
+fn test_sbc_decimal() {
+    let cases = [
+        // a, m, carry in => a, carry out
+        (0x00, 0x00, true, 0x00, true),
+        (0x10, 0x01, true, 0x09, true),
+        (0x46, 0x12, true, 0x34, true),
+        (0x40, 0x13, true, 0x27, true),
+        (0x32, 0x02, false, 0x29, true),
+        (0x12, 0x21, true, 0x91, false),
+        (0x00, 0x01, true, 0x99, false),
+    ];
+    for (a, m, c, result, carry) in cases.iter() {
+        let (mut cpu, mut mem) = setup(&[0xE9, *m]);
+        cpu.state_mut().a = *a;
+        cpu.state_mut().set_flag(Flag::CRY, *c);
+        cpu.state_mut().set_flag(Flag::DEC, true);
+
+        run_instruction(&mut cpu, &mut mem);
+
+        assert_eq!(cpu.state().a, *result, "{:02X} - {:02X}", a, m);
+        assert_eq!(cpu.state().get_flag(Flag::CRY), *carry);
+    }
+}
+
+/// Decimal mode results as described in Bruce Clark's "Decimal Mode"
+/// tutorial, appendix A, for NMOS 6502s.
+/// Returns accumulator and flags (N, V, Z, C).
+fn reference_decimal(subtract: bool, a: Data, m: Data, c: bool) -> (Data, [bool; 4]) {
+    let (a, m, c) = (a as i32, m as i32, c as i32);
+    let signed = |v: i32| v as u8 as i8 as i32;
+    if subtract {
+        // sequence 3, with all flags as in binary mode
+        let binary = a - m - 1 + c;
+        let mut al = (a & 0x0F) - (m & 0x0F) + c - 1;
+        if al < 0 {
+            al = ((al - 0x06) & 0x0F) - 0x10;
+        }
+        let mut r = (a & 0xF0) - (m & 0xF0) + al;
+        if r < 0 {
+            r -= 0x60;
+        }
+        let v = !(-128..=127).contains(&(signed(a) - signed(m) - 1 + c));
+        (
+            r as Data,
+            [binary & 0x80 != 0, v, binary & 0xFF == 0, binary >= 0],
+        )
+    } else {
+        // sequences 1, 2 and 4, with Z as in binary mode
+        let binary = a + m + c;
+        let mut al = (a & 0x0F) + (m & 0x0F) + c;
+        if al >= 0x0A {
+            al = ((al + 0x06) & 0x0F) + 0x10;
+        }
+        let mut r = (a & 0xF0) + (m & 0xF0) + al;
+        let n = r & 0x80 != 0;
+        let v = !(-128..=127).contains(&(signed(a & 0xF0) + signed(m & 0xF0) + al));
+        if r >= 0xA0 {
+            r += 0x60;
+        }
+        (r as Data, [n, v, binary & 0xFF == 0, r >= 0x100])
+    }
+}
+
+/// 65C02 flavour of `reference_decimal`: N and Z reflect the result,
+/// and SBC adjusts it differently (sequence 4).
+fn reference_decimal_65c02(subtract: bool, a: Data, m: Data, c: bool) -> (Data, [bool; 4]) {
+    let (r, [_, v, _, carry]) = reference_decimal(subtract, a, m, c);
+    let r = if subtract {
+        let (a, m, c) = (a as i32, m as i32, c as i32);
+        let al = (a & 0x0F) - (m & 0x0F) + c - 1;
+        let mut r = a - m + c - 1;
+        if r < 0 {
+            r -= 0x60;
+        }
+        if al < 0 {
+            r -= 0x06;
+        }
+        r as Data
+    } else {
+        r
+    };
+    (r, [r & 0x80 != 0, v, r == 0, carry])
+}
+
+#[test]
+fn test_decimal_mode_exhaustive() {
+    check_decimal_mode(Variant::NMOS, reference_decimal);
+}
+
+#[test]
+fn test_decimal_mode_exhaustive_65c02() {
+    check_decimal_mode(Variant::CMOS, reference_decimal_65c02);
+}
+
+fn check_decimal_mode(
+    variant: Variant,
+    reference: fn(bool, Data, Data, bool) -> (Data, [bool; 4]),
+) {
+    let mut cpu = Cpu::with_variant(variant);
+    let mut mem = CheapoMemory::new();
+    for &(opcode, subtract) in [(0x69, false), (0xE9, true)].iter() {
+        load(&mut mem, PROGRAM_START, &[opcode]);
+        for a in 0..=0xFF {
+            for m in 0..=0xFF {
+                for &c in [false, true].iter() {
+                    let s = cpu.state_mut();
+                    s.pc = PROGRAM_START;
+                    s.a = a;
+                    s.set_sr(0);
+                    s.set_flag(Flag::DEC, true);
+                    s.set_flag(Flag::CRY, c);
+                    mem.write(PROGRAM_START + 1, m);
+
+                    run_instruction(&mut cpu, &mut mem);
+
+                    let s = cpu.state();
+                    let flags = [
+                        s.get_flag(Flag::NEG),
+                        s.get_flag(Flag::OVF),
+                        s.get_flag(Flag::ZRO),
+                        s.get_flag(Flag::CRY),
+                    ];
+                    let expected = reference(subtract, a, m, c);
+                    assert_eq!(
+                        (s.a, flags),
+                        expected,
+                        "opcode {:02X}, a {:02X}, m {:02X}, c {}",
+                        opcode,
+                        a,
+                        m,
+                        c
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_and() {
+    for_each_mode(
+        Operation::AND,
+        0x0F,
+        |s| s.a = 0x3C,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0x0C);
+        },
+    );
+    for_each_mode(
+        Operation::AND,
+        0x0F,
+        |s| s.a = 0xF0,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+        },
+    );
+}
+
+#[test]
+fn test_ora() {
+    for_each_mode(
+        Operation::ORA,
+        0x0F,
+        |s| s.a = 0x80,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0x8F);
+            assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+        },
+    );
+}
+
+#[test]
+fn test_eor() {
+    for_each_mode(
+        Operation::EOR,
+        0xFF,
+        |s| s.a = 0xFF,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0x00);
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+        },
+    );
+}
+
+#[test]
+fn test_cmp() {
+    for_each_mode(
+        Operation::CMP,
+        0x10,
+        |s| s.a = 0x20,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), false);
+            assert_eq!(cpu.state().a, 0x20);
+        },
+    );
+    for_each_mode(
+        Operation::CMP,
+        0x20,
+        |s| s.a = 0x20,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+        },
+    );
+    for_each_mode(
+        Operation::CMP,
+        0x21,
+        |s| s.a = 0x20,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().get_flag(Flag::CRY), false);
+            assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+        },
+    );
+}
+
+#[test]
+fn test_cpx() {
+    for_each_mode(
+        Operation::CPX,
+        0x04,
+        |_| {},
+        |cpu, _, _| {
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+        },
+    );
+    for_each_mode(
+        Operation::CPX,
+        0x05,
+        |_| {},
+        |cpu, _, _| {
+            assert_eq!(cpu.state().get_flag(Flag::CRY), false);
+        },
+    );
+}
+
+#[test]
+fn test_cpy() {
+    for_each_mode(
+        Operation::CPY,
+        0x04,
+        |_| {},
+        |cpu, _, _| {
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+        },
+    );
+    for_each_mode(
+        Operation::CPY,
+        0x03,
+        |_| {},
+        |cpu, _, _| {
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), false);
+        },
+    );
+}
+
+#[test]
+fn test_bit() {
+    for_each_mode(
+        Operation::BIT,
+        0xC0,
+        |s| s.a = 0x01,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+            assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+            assert_eq!(cpu.state().get_flag(Flag::OVF), true);
+            assert_eq!(cpu.state().a, 0x01);
+        },
+    );
+    for_each_mode(
+        Operation::BIT,
+        0x01,
+        |s| s.a = 0x01,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), false);
+            assert_eq!(cpu.state().get_flag(Flag::NEG), false);
+            assert_eq!(cpu.state().get_flag(Flag::OVF), false);
+        },
+    );
+}
+
+#[test]
+fn test_asl() {
+    for_each_mode(
+        Operation::ASL,
+        0x81,
+        |_| {},
+        |cpu, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0x02));
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+        },
+    );
+
+    let (mut cpu, mut mem) = setup(&[0x0A]);
+    cpu.state_mut().a = 0x40;
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0x80);
+    assert_eq!(cpu.state().get_flag(Flag::CRY), false);
+    assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+}
+
+#[test]
+fn test_lsr() {
+    for_each_mode(
+        Operation::LSR,
+        0x01,
+        |_| {},
+        |cpu, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0x00));
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+        },
+    );
+
+    let (mut cpu, mut mem) = setup(&[0x4A]);
+    cpu.state_mut().a = 0x84;
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0x42);
+    assert_eq!(cpu.state().get_flag(Flag::CRY), false);
+}
+
+#[test]
+fn test_rol() {
+    for_each_mode(
+        Operation::ROL,
+        0x80,
+        |s| s.set_flag(Flag::CRY, true),
+        |cpu, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0x01));
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+        },
+    );
+
+    let (mut cpu, mut mem) = setup(&[0x2A]);
+    cpu.state_mut().a = 0x40;
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0x80);
+    assert_eq!(cpu.state().get_flag(Flag::CRY), false);
+}
+
+#[test]
+fn test_ror() {
+    for_each_mode(
+        Operation::ROR,
+        0x01,
+        |s| s.set_flag(Flag::CRY, true),
+        |cpu, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0x80));
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+            assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+        },
+    );
+
+    let (mut cpu, mut mem) = setup(&[0x6A]);
+    cpu.state_mut().a = 0x02;
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0x01);
+    assert_eq!(cpu.state().get_flag(Flag::CRY), false);
+}
+
+#[test]
+fn test_inc() {
+    for_each_mode(
+        Operation::INC,
+        0xFF,
+        |_| {},
+        |cpu, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0x00));
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+        },
+    );
+}
+
+#[test]
+fn test_dec() {
+    for_each_mode(
+        Operation::DEC,
+        0x00,
+        |_| {},
+        |cpu, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0xFF));
+            assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+        },
+    );
+}
+
+#[test]
+fn test_increment_decrement_registers() {
+    let (mut cpu, mut mem) = setup(&[0xE8, 0xC8, 0xCA, 0xCA, 0x88]);
+    cpu.state_mut().x = 0xFF;
+    cpu.state_mut().y = 0x10;
+
+    run_instruction(&mut cpu, &mut mem); // INX
+    assert_eq!(cpu.state().x, 0x00);
+    assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+
+    run_instruction(&mut cpu, &mut mem); // INY
+    assert_eq!(cpu.state().y, 0x11);
+
+    run_instruction(&mut cpu, &mut mem); // DEX
+    run_instruction(&mut cpu, &mut mem); // DEX
+    assert_eq!(cpu.state().x, 0xFE);
+    assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+
+    run_instruction(&mut cpu, &mut mem); // DEY
+    assert_eq!(cpu.state().y, 0x10);
+}
+
+#[test]
+fn test_register_transfers() {
+    // TAX, TAY, TSX, TXA, TYA, TXS
+    let (mut cpu, mut mem) = setup(&[0xAA, 0xA8, 0xBA, 0x8A, 0x98, 0x9A]);
+    cpu.state_mut().a = 0x42;
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().x, 0x42);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().y, 0x42);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().x, 0xFF);
+    assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0xFF);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0x42);
+    assert_eq!(cpu.state().get_flag(Flag::NEG), false);
+
+    cpu.state_mut().x = 0x00;
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().sp, 0x00);
+    assert_eq!(cpu.state().get_flag(Flag::ZRO), false);
+}
+
+#[test]
+fn test_flag_instructions() {
+    // SEC, SED, SEI, CLC, CLD, CLI, CLV
+    let (mut cpu, mut mem) = setup(&[0x38, 0xF8, 0x78, 0x18, 0xD8, 0x58, 0xB8]);
+    cpu.state_mut().set_flag(Flag::OVF, true);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().get_flag(Flag::DEC), true);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().get_flag(Flag::INT), true);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().get_flag(Flag::CRY), false);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().get_flag(Flag::DEC), false);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().get_flag(Flag::INT), false);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().get_flag(Flag::OVF), false);
+}
+
+#[test]
+fn test_branches() {
+    let cases = [
+        (0x90, Flag::CRY, false),
+        (0xB0, Flag::CRY, true),
+        (0xD0, Flag::ZRO, false),
+        (0xF0, Flag::ZRO, true),
+        (0x10, Flag::NEG, false),
+        (0x30, Flag::NEG, true),
+        (0x50, Flag::OVF, false),
+        (0x70, Flag::OVF, true),
+    ];
+    for (opcode, flag, taken_when) in cases.iter() {
+        let (mut cpu, mut mem) = setup(&[*opcode, 0x10]);
+        cpu.state_mut().set_flag(*flag, *taken_when);
+        run_instruction(&mut cpu, &mut mem);
+        assert_eq!(cpu.state().pc, PROGRAM_START + 2 + 0x10);
+
+        let (mut cpu, mut mem) = setup(&[*opcode, 0xFC]);
+        cpu.state_mut().set_flag(*flag, *taken_when);
+        run_instruction(&mut cpu, &mut mem);
+        assert_eq!(cpu.state().pc, PROGRAM_START + 2 - 4);
+
+        let (mut cpu, mut mem) = setup(&[*opcode, 0x10]);
+        cpu.state_mut().set_flag(*flag, !*taken_when);
+        run_instruction(&mut cpu, &mut mem);
+        assert_eq!(cpu.state().pc, PROGRAM_START + 2);
+    }
+}
+
+#[test]
+fn test_branch_cycles() {
+    // BNE not taken, taken, taken across page boundary
+    let (mut cpu, mut mem) = setup(&[0xD0, 0x10]);
+    cpu.state_mut().set_flag(Flag::ZRO, true);
+    assert_eq!(run_instruction(&mut cpu, &mut mem), 2);
+
+    let (mut cpu, mut mem) = setup(&[0xD0, 0x10]);
+    assert_eq!(run_instruction(&mut cpu, &mut mem), 3);
+    assert_eq!(cpu.state().pc, 0x0212);
+
+    let (mut cpu, mut mem) = setup(&[0xD0, 0xF0]);
+    assert_eq!(run_instruction(&mut cpu, &mut mem), 4);
+    assert_eq!(cpu.state().pc, 0x01F2);
+
+    let (mut cpu, mut mem) = setup(&[]);
+    cpu.state_mut().pc = 0x02F0;
+    load(&mut mem, 0x02F0, &[0xD0, 0x10]);
+    assert_eq!(run_instruction(&mut cpu, &mut mem), 4);
+    assert_eq!(cpu.state().pc, 0x0302);
+}
+
+#[test]
+fn test_indexed_page_crossing_cycles() {
+    // LDA $12F0,X
+    let (mut cpu, mut mem) = setup(&[0xBD, 0xF0, 0x12]);
+    cpu.state_mut().x = 0x0F;
+    mem.write(0x12FF, 0x42);
+    assert_eq!(run_instruction(&mut cpu, &mut mem), 4);
+    assert_eq!(cpu.state().a, 0x42);
+
+    let (mut cpu, mut mem) = setup(&[0xBD, 0xF0, 0x12]);
+    cpu.state_mut().x = 0x10;
+    mem.write(0x1300, 0x42);
+    assert_eq!(run_instruction(&mut cpu, &mut mem), 5);
+    assert_eq!(cpu.state().a, 0x42);
+
+    // LDX $12F0,Y
+    let (mut cpu, mut mem) = setup(&[0xBE, 0xF0, 0x12]);
+    cpu.state_mut().y = 0x20;
+    mem.write(0x1310, 0x42);
+    assert_eq!(run_instruction(&mut cpu, &mut mem), 5);
+    assert_eq!(cpu.state().x, 0x42);
+
+    // LDA ($20),Y
+    let (mut cpu, mut mem) = setup(&[0xB1, 0x20]);
+    load(&mut mem, 0x0020, &[0xF0, 0x12]);
+    cpu.state_mut().y = 0x0F;
+    mem.write(0x12FF, 0x42);
+    assert_eq!(run_instruction(&mut cpu, &mut mem), 5);
+    assert_eq!(cpu.state().a, 0x42);
+
+    let (mut cpu, mut mem) = setup(&[0xB1, 0x20]);
+    load(&mut mem, 0x0020, &[0xF0, 0x12]);
+    cpu.state_mut().y = 0x10;
+    mem.write(0x1300, 0x42);
+    assert_eq!(run_instruction(&mut cpu, &mut mem), 6);
+    assert_eq!(cpu.state().a, 0x42);
+}
+
+#[test]
+fn test_indexed_address_wraps_at_end_of_memory() {
+    // LDA $FFFF,X
+    let (mut cpu, mut mem) = setup(&[0xBD, 0xFF, 0xFF]);
+    cpu.state_mut().x = 0x02;
+    mem.write(0x0001, 0x42);
+
+    assert_eq!(run_instruction(&mut cpu, &mut mem), 5);
+    assert_eq!(cpu.state().a, 0x42);
+}
+
+#[test]
+fn test_zero_page_indexed_wraps() {
+    // LDA $F0,X
+    let (mut cpu, mut mem) = setup(&[0xB5, 0xF0]);
+    cpu.state_mut().x = 0x20;
+    mem.write(0x0010, 0x42);
+    mem.write(0x0110, 0x99);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0x42);
+
+    // LDX $F0,Y
+    let (mut cpu, mut mem) = setup(&[0xB6, 0xF0]);
+    cpu.state_mut().y = 0x20;
+    mem.write(0x0010, 0x42);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().x, 0x42);
+}
+
+#[test]
+fn test_zero_page_pointer_wraps() {
+    // LDA ($FE,X) reads its pointer from $FF and $00
+    let (mut cpu, mut mem) = setup(&[0xA1, 0xFE]);
+    cpu.state_mut().x = 0x01;
+    mem.write(0x00FF, 0x34);
+    mem.write(0x0000, 0x12);
+    mem.write(0x0100, 0x56);
+    mem.write(0x1234, 0x42);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0x42);
+
+    // LDA ($FF),Y reads its pointer from $FF and $00
+    let (mut cpu, mut mem) = setup(&[0xB1, 0xFF]);
+    cpu.state_mut().y = 0x01;
+    mem.write(0x00FF, 0x33);
+    mem.write(0x0000, 0x12);
+    mem.write(0x0100, 0x56);
+    mem.write(0x1234, 0x42);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0x42);
+}
+
+#[test]
+fn test_jmp_indirect_page_boundary_bug() {
+    let (mut cpu, mut mem) = setup(&[0x6C, 0xFF, 0x30]);
+    mem.write(0x30FF, 0x34);
+    mem.write(0x3000, 0x12);
+    mem.write(0x3100, 0x56);
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().pc, 0x1234);
+}
+
+#[test]
+fn test_documented_opcode_cycles() {
+    #[rustfmt::skip]
+    let cycles: [usize; 256] = [
+        7, 6, 0, 0, 0, 3, 5, 0, 3, 2, 2, 0, 0, 4, 6, 0,
+        2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
+        6, 6, 0, 0, 3, 3, 5, 0, 4, 2, 2, 0, 4, 4, 6, 0,
+        2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
+        6, 6, 0, 0, 0, 3, 5, 0, 3, 2, 2, 0, 3, 4, 6, 0,
+        2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
+        6, 6, 0, 0, 0, 3, 5, 0, 4, 2, 2, 0, 5, 4, 6, 0,
+        2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
+        0, 6, 0, 0, 3, 3, 3, 0, 2, 0, 2, 0, 4, 4, 4, 0,
+        2, 6, 0, 0, 4, 4, 4, 0, 2, 5, 2, 0, 0, 5, 0, 0,
+        2, 6, 2, 0, 3, 3, 3, 0, 2, 2, 2, 0, 4, 4, 4, 0,
+        2, 5, 0, 0, 4, 4, 4, 0, 2, 4, 2, 0, 4, 4, 4, 0,
+        2, 6, 0, 0, 3, 3, 5, 0, 2, 2, 2, 0, 4, 4, 6, 0,
+        2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
+        2, 6, 0, 0, 3, 3, 5, 0, 2, 2, 2, 0, 4, 4, 6, 0,
+        2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
+    ];
+    for opcode in 0..=0xFF {
+        if decode(opcode).is_none() {
+            continue;
+        }
+        let (mut cpu, mut mem) = setup(&[opcode]);
+        if opcode & 0x1F == 0x10 {
+            // branch not taken
+            let taken_when_set = opcode & 0x20 != 0;
+            cpu.state_mut()
+                .set_sr(if taken_when_set { 0x00 } else { 0xFF });
+        }
+
+        let taken = run_instruction(&mut cpu, &mut mem);
+
+        assert_eq!(taken, cycles[opcode as usize], "opcode {:02X}", opcode);
+    }
+}
+
+#[test]
+fn test_jmp_absolute() {
+    let (mut cpu, mut mem) = setup(&[0x4C, 0x34, 0x12]);
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().pc, 0x1234);
+}
+
+#[test]
+fn test_jmp_indirect() {
+    let (mut cpu, mut mem) = setup(&[0x6C, 0x00, 0x30]);
+    load(&mut mem, 0x3000, &[0x34, 0x12]);
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().pc, 0x1234);
+}
+
+#[test]
+fn test_jsr_rts() {
+    let (mut cpu, mut mem) = setup(&[0x20, 0x00, 0x30]);
+    load(&mut mem, 0x3000, &[0x60]);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, 0x3000);
+    assert_eq!(cpu.state().sp, 0xFD);
+    // return address points at last byte of JSR
+    assert_eq!(mem.read(&0x01FF), Some(0x02));
+    assert_eq!(mem.read(&0x01FE), Some(0x02));
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 3);
+    assert_eq!(cpu.state().sp, 0xFF);
+}
+
+#[test]
+fn test_pha_pla() {
+    // PHA, LDA #0, PLA
+    let (mut cpu, mut mem) = setup(&[0x48, 0xA9, 0x00, 0x68]);
+    cpu.state_mut().a = 0x80;
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(mem.read(&0x01FF), Some(0x80));
+    assert_eq!(cpu.state().sp, 0xFE);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0x80);
+    assert_eq!(cpu.state().sp, 0xFF);
+    assert_eq!(cpu.state().get_flag(Flag::ZRO), false);
+    assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+}
+
+#[test]
+fn test_php_plp() {
+    // PHP, CLC, PLP
+    let (mut cpu, mut mem) = setup(&[0x08, 0x18, 0x28]);
+    cpu.state_mut().set_flag(Flag::CRY, true);
+
+    run_instruction(&mut cpu, &mut mem);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().get_flag(Flag::CRY), false);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+    assert_eq!(cpu.state().sp, 0xFF);
+}
+
+#[test]
+fn test_php_sets_break_and_reserved() {
+    let (mut cpu, mut mem) = setup(&[0x08]);
+    cpu.state_mut().set_sr(0);
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(mem.read(&0x01FF), Some(0b0011_0000));
+    assert_eq!(cpu.state().get_flag(Flag::BRK), false);
+}
+
+#[test]
+fn test_plp_ignores_break() {
+    let (mut cpu, mut mem) = setup(&[0x28]);
+    cpu.state_mut().sp = 0xFE;
+    mem.write(0x01FF, 0b1101_0001);
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().sr(), 0b1110_0001);
+}
+
+#[test]
+fn test_brk_rti() {
+    let (mut cpu, mut mem) = setup(&[0x00, 0xEA, 0xEA]);
+    load(&mut mem, 0xFFFE, &[0x00, 0x30]);
+    load(&mut mem, 0x3000, &[0x40]);
+    cpu.state_mut().set_flag(Flag::CRY, true);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, 0x3000);
+    assert_eq!(cpu.state().sp, 0xFC);
+    assert_eq!(cpu.state().get_flag(Flag::INT), true);
+    // BRK skips a padding byte
+    assert_eq!(mem.read(&0x01FF), Some(0x02));
+    assert_eq!(mem.read(&0x01FE), Some(0x02));
+    assert_eq!(mem.read(&0x01FD), Some(0b0011_0001));
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 2);
+    assert_eq!(cpu.state().sp, 0xFF);
+    assert_eq!(cpu.state().get_flag(Flag::INT), false);
+    assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+    assert_eq!(cpu.state().get_flag(Flag::BRK), false);
+}
+
+#[test]
+fn test_stack_addresses() {
+    let mut state = CpuState::new();
+    state.sp = 0xFD;
+    assert_eq!(state.stack_addr(), 0x01FD);
+    assert_eq!(state.stack_top(), 0x01FE);
+
+    state.sp = 0xFF;
+    assert_eq!(state.stack_addr(), 0x01FF);
+    assert_eq!(state.stack_top(), 0x0100);
+}
+
+#[test]
+fn test_stack_push_wraps() {
+    // PHA, PHA
+    let (mut cpu, mut mem) = setup(&[0x48, 0x48]);
+    cpu.state_mut().sp = 0x00;
+    cpu.state_mut().a = 0x42;
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(mem.read(&0x0100), Some(0x42));
+    assert_eq!(cpu.state().sp, 0xFF);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(mem.read(&0x01FF), Some(0x42));
+    assert_eq!(mem.read(&0x0200), Some(0x48));
+    assert_eq!(cpu.state().sp, 0xFE);
+}
+
+#[test]
+fn test_stack_pull_wraps() {
+    // PLA, PLP
+    let (mut cpu, mut mem) = setup(&[0x68, 0x28]);
+    cpu.state_mut().sp = 0xFE;
+    mem.write(0x01FF, 0x42);
+    mem.write(0x0100, 0xFF);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0x42);
+    assert_eq!(cpu.state().sp, 0xFF);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().sr(), 0xEF);
+    assert_eq!(cpu.state().sp, 0x00);
+}
+
+#[test]
+fn test_jsr_rts_stack_wraps() {
+    let (mut cpu, mut mem) = setup(&[0x20, 0x00, 0x30]);
+    load(&mut mem, 0x3000, &[0x60]);
+    cpu.state_mut().sp = 0x00;
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(mem.read(&0x0100), Some(0x02));
+    assert_eq!(mem.read(&0x01FF), Some(0x02));
+    assert_eq!(cpu.state().sp, 0xFE);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 3);
+    assert_eq!(cpu.state().sp, 0x00);
+}
+
+#[test]
+fn test_brk_rti_stack_wraps() {
+    let (mut cpu, mut mem) = setup(&[0x00, 0xEA, 0xEA]);
+    load(&mut mem, 0xFFFE, &[0x00, 0x30]);
+    load(&mut mem, 0x3000, &[0x40]);
+    cpu.state_mut().sp = 0x01;
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(mem.read(&0x0101), Some(0x02));
+    assert_eq!(mem.read(&0x0100), Some(0x02));
+    assert_eq!(mem.read(&0x01FF), Some(0b0011_0000));
+    assert_eq!(cpu.state().sp, 0xFE);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 2);
+    assert_eq!(cpu.state().sp, 0x01);
+}
+
+#[test]
+fn test_nop() {
+    let (mut cpu, mut mem) = setup(&[0xEA]);
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().pc, PROGRAM_START + 1);
+    assert_eq!(cpu.state().a, 0);
+}
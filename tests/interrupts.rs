@@ -0,0 +1,219 @@
+#![allow(clippy::bool_assert_comparison)]
+
+mod common;
+
+use common::*;
+use luvemix_rust::cpu::*;
+use luvemix_rust::memory::*;
+
+#[test]
+fn test_reset_sequence() {
+    let (mut cpu, mut mem) = setup_interrupts();
+    cpu.state_mut().sp = 0x00;
+
+    cpu.reset();
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(trace.len(), 7);
+    assert!(trace.iter().all(|(_, rwb, _)| *rwb == BusMode::READ));
+    assert_eq!(trace[2].0, 0x0100);
+    assert_eq!(trace[3].0, 0x01FF);
+    assert_eq!(trace[4].0, 0x01FE);
+    assert_eq!(cpu.state().pc, 0x5000);
+    assert_eq!(cpu.state().sp, 0xFD);
+    assert_eq!(cpu.state().get_flag(Flag::INT), true);
+}
+
+#[test]
+fn test_reset_line_holds_cpu() {
+    let (mut cpu, mut mem) = setup_interrupts();
+    run_cycle(&mut cpu, &mut mem);
+
+    cpu.res = true;
+    for _ in 0..10 {
+        run_cycle(&mut cpu, &mut mem);
+    }
+    assert_eq!(cpu.state().pc, PROGRAM_START + 1);
+
+    cpu.res = false;
+    assert_eq!(run_instruction(&mut cpu, &mut mem), 7);
+    assert_eq!(cpu.state().pc, 0x5000);
+}
+
+#[test]
+fn test_irq() {
+    let (mut cpu, mut mem) = setup_interrupts();
+    cpu.state_mut().set_flag(Flag::CRY, true);
+    cpu.irq = true;
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 1);
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace,
+        vec![
+            (0x0201, BusMode::READ, 0xEA),
+            (0x0201, BusMode::READ, 0xEA),
+            (0x01FF, BusMode::WRITE, 0x02),
+            (0x01FE, BusMode::WRITE, 0x01),
+            (0x01FD, BusMode::WRITE, 0b0010_0001),
+            (0xFFFE, BusMode::READ, 0x00),
+            (0xFFFF, BusMode::READ, 0x30),
+        ]
+    );
+    assert_eq!(cpu.state().pc, 0x3000);
+    assert_eq!(cpu.state().get_flag(Flag::INT), true);
+}
+
+#[test]
+fn test_irq_masked_by_interrupt_disable() {
+    let (mut cpu, mut mem) = setup_interrupts();
+    cpu.state_mut().set_flag(Flag::INT, true);
+    cpu.irq = true;
+
+    for _ in 0..4 {
+        run_instruction(&mut cpu, &mut mem);
+    }
+
+    assert_eq!(cpu.state().pc, PROGRAM_START + 4);
+}
+
+#[test]
+fn test_irq_is_level_triggered() {
+    let (mut cpu, mut mem) = setup_interrupts();
+    load(&mut mem, 0x3000, &[0x40]); // RTI
+    cpu.irq = true;
+
+    run_instruction(&mut cpu, &mut mem); // NOP
+    run_instruction(&mut cpu, &mut mem); // IRQ
+    run_instruction(&mut cpu, &mut mem); // RTI
+    assert_eq!(cpu.state().pc, PROGRAM_START + 1);
+
+    // RTI clears the interrupt disable flag early enough to get
+    // interrupted again right away
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, 0x3000);
+}
+
+#[test]
+fn test_nmi_is_edge_triggered() {
+    let (mut cpu, mut mem) = setup_interrupts();
+    load(&mut mem, 0x4000, &[0x40]); // RTI
+    cpu.state_mut().set_flag(Flag::INT, true);
+    cpu.nmi = true;
+
+    run_instruction(&mut cpu, &mut mem); // NOP
+    run_instruction(&mut cpu, &mut mem); // NMI
+    assert_eq!(cpu.state().pc, 0x4000);
+    assert_eq!(mem.read(&0x01FD), Some(0b0010_0100));
+
+    run_instruction(&mut cpu, &mut mem); // RTI
+    run_instruction(&mut cpu, &mut mem); // NOP
+    assert_eq!(cpu.state().pc, PROGRAM_START + 2);
+
+    cpu.nmi = false;
+    run_instruction(&mut cpu, &mut mem);
+    cpu.nmi = true;
+    run_instruction(&mut cpu, &mut mem);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, 0x4000);
+}
+
+#[test]
+fn test_cli_delays_irq_by_one_instruction() {
+    // CLI, NOP, NOP
+    let (mut cpu, mut mem) = setup_interrupts();
+    load(&mut mem, PROGRAM_START, &[0x58]);
+    cpu.state_mut().set_flag(Flag::INT, true);
+    cpu.irq = true;
+
+    run_instruction(&mut cpu, &mut mem); // CLI
+    run_instruction(&mut cpu, &mut mem); // NOP
+    assert_eq!(cpu.state().pc, PROGRAM_START + 2);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, 0x3000);
+}
+
+#[test]
+fn test_sei_lets_pending_irq_through() {
+    // SEI, NOP
+    let (mut cpu, mut mem) = setup_interrupts();
+    load(&mut mem, PROGRAM_START, &[0x78]);
+    cpu.irq = true;
+
+    run_instruction(&mut cpu, &mut mem); // SEI
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().pc, 0x3000);
+    // the pushed status has the interrupt disable flag set
+    assert_eq!(mem.read(&0x01FD), Some(0b0010_0100));
+}
+
+#[test]
+fn test_taken_branch_delays_irq() {
+    // BNE +0, NOP
+    let (mut cpu, mut mem) = setup_interrupts();
+    load(&mut mem, PROGRAM_START, &[0xD0, 0x00]);
+
+    run_cycle(&mut cpu, &mut mem);
+    cpu.irq = true;
+    run_cycle(&mut cpu, &mut mem);
+    run_cycle(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 2);
+
+    run_instruction(&mut cpu, &mut mem); // NOP
+    assert_eq!(cpu.state().pc, PROGRAM_START + 3);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, 0x3000);
+}
+
+#[test]
+fn test_irq_polled_in_second_to_last_cycle() {
+    // LDA $10, NOP
+    let (mut cpu, mut mem) = setup_interrupts();
+    load(&mut mem, PROGRAM_START, &[0xA5, 0x10]);
+
+    run_cycle(&mut cpu, &mut mem);
+    cpu.irq = true;
+    run_cycle(&mut cpu, &mut mem);
+    run_cycle(&mut cpu, &mut mem);
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().pc, 0x3000);
+
+    // too late when asserted during the last cycle
+    let (mut cpu, mut mem) = setup_interrupts();
+    load(&mut mem, PROGRAM_START, &[0xA5, 0x10]);
+
+    run_cycle(&mut cpu, &mut mem);
+    run_cycle(&mut cpu, &mut mem);
+    cpu.irq = true;
+    run_cycle(&mut cpu, &mut mem);
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().pc, PROGRAM_START + 3);
+}
+
+#[test]
+fn test_nmi_hijacks_brk() {
+    let (mut cpu, mut mem) = setup_interrupts();
+    load(&mut mem, PROGRAM_START, &[0x00, 0x00]);
+
+    run_cycle(&mut cpu, &mut mem);
+    run_cycle(&mut cpu, &mut mem);
+    cpu.nmi = true;
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().pc, 0x4000);
+    // the pushed status still has the break flag set
+    assert_eq!(mem.read(&0x01FD), Some(0b0011_0000));
+
+    // the NMI has been handled
+    load(&mut mem, 0x4000, &[0xEA, 0xEA]);
+    run_instruction(&mut cpu, &mut mem);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, 0x4002);
+}
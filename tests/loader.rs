@@ -0,0 +1,229 @@
+use luvemix_rust::cpu::*;
+use luvemix_rust::loader::*;
+use luvemix_rust::memory::*;
+
+#[test]
+fn test_load_bin() {
+    let mut mem = Ram::new();
+
+    let loaded = load_bin(&[0xA9, 0x2A], 0x0600, &mut mem).unwrap();
+
+    assert_eq!(loaded.start, Some(0x0600));
+    assert_eq!(loaded.end, Some(0x0601));
+    assert_eq!(loaded.len, 2);
+    assert_eq!(loaded.entry, None);
+    assert_eq!(mem.read(&0x0601), Some(0x2A));
+    assert_eq!(
+        load_bin(&[0; 3], 0xFFFE, &mut mem),
+        Err(LoadError::OutOfRange(0x10000))
+    );
+}
+
+#[test]
+fn test_load_prg() {
+    let mut mem = Ram::new();
+
+    let loaded = load_prg(&[0x01, 0x08, 0x0B, 0x08], &mut mem).unwrap();
+
+    assert_eq!(loaded.start, Some(0x0801));
+    assert_eq!(loaded.len, 2);
+    assert_eq!(mem.read(&0x0801), Some(0x0B));
+    assert_eq!(mem.read(&0x0802), Some(0x08));
+    assert_eq!(load_prg(&[0x01], &mut mem), Err(LoadError::MissingHeader));
+}
+
+#[test]
+fn test_load_hex() {
+    let text = "\
+:06060000A92A8D00020092
+:02FFFC000006FD
+
+:0400000500000600F1
+:00000001FF
+:03001000010203E7
+";
+    let mut mem = Ram::new();
+
+    let loaded = load_hex(text, &mut mem).unwrap();
+
+    assert_eq!(loaded.start, Some(0x0600));
+    assert_eq!(loaded.end, Some(0xFFFD));
+    assert_eq!(loaded.len, 8);
+    assert_eq!(loaded.entry, Some(0x0600));
+    assert_eq!(mem.read(&0x0602), Some(0x8D));
+    assert_eq!(mem.read(&0xFFFD), Some(0x06));
+    // nothing after the end-of-file record
+    assert_eq!(mem.read(&0x0010), Some(0x00));
+}
+
+#[test]
+fn test_load_hex_start_addresses() {
+    let entry = |text: &str| load_hex(text, &mut Ram::new()).map(|loaded| loaded.entry);
+
+    // CS $0100, IP $0000
+    assert_eq!(
+        entry(":0400000301000000F8\n:00000001FF\n"),
+        Ok(Some(0x1000))
+    );
+    assert_eq!(
+        entry(":040000050000F00007\n:00000001FF\n"),
+        Ok(Some(0xF000))
+    );
+    assert_eq!(
+        entry(":0400000500011234B0\n:00000001FF\n"),
+        Err(LoadError::OutOfRange(0x11234))
+    );
+    assert_eq!(
+        entry(":04000003FFFF0010EB\n:00000001FF\n"),
+        Err(LoadError::OutOfRange(0x100000))
+    );
+}
+
+#[test]
+fn test_load_hex_extended_addresses() {
+    let mut mem = Ram::new();
+
+    // segment $0010 puts offset $0010 at $0110
+    let text = ":020000020010EC\n:03001000010203E7\n:020000040000FA\n:00000001FF\n";
+    let loaded = load_hex(text, &mut mem).unwrap();
+    assert_eq!(loaded.start, Some(0x0110));
+    assert_eq!(mem.read(&0x0112), Some(0x03));
+
+    let text = ":020000040001F9\n:03001000010203E7\n:00000001FF\n";
+    assert_eq!(
+        load_hex(text, &mut mem),
+        Err(LoadError::OutOfRange(0x10010))
+    );
+
+    let text = ":02000004FFFFFC\n:01FFFF00EA17\n:00000001FF\n";
+    assert_eq!(
+        load_hex(text, &mut mem),
+        Err(LoadError::OutOfRange(0xFFFFFFFF))
+    );
+}
+
+#[test]
+fn test_load_hex_errors() {
+    let mut mem = Ram::new();
+    let err = |text: &str| load_hex(text, &mut Ram::new()).unwrap_err();
+
+    assert_eq!(
+        err(":03001000010203E8\n:00000001FF\n"),
+        LoadError::Checksum {
+            line: 1,
+            expected: 0xE7,
+            found: 0xE8
+        }
+    );
+    assert_eq!(
+        err(":03001000010203E8\n").to_string(),
+        "line 1: checksum is $E8, expected $E7"
+    );
+    assert!(matches!(
+        err(":03001000010203E7\n03001000010203E7"),
+        LoadError::Syntax { line: 2, .. }
+    ));
+    assert!(matches!(
+        err(":0000001FF"),
+        LoadError::Syntax { line: 1, .. }
+    ));
+    assert!(matches!(
+        err(":0G000001FF"),
+        LoadError::Syntax { line: 1, .. }
+    ));
+    assert!(matches!(
+        err(":04001000010203E7"),
+        LoadError::Syntax { line: 1, .. }
+    ));
+    assert!(matches!(
+        err(":00000006FA"),
+        LoadError::Syntax { line: 1, .. }
+    ));
+    assert_eq!(err(":03001000010203E7\n"), LoadError::MissingEnd);
+    assert_eq!(load_hex(":00000001FF", &mut mem).unwrap().len, 0);
+}
+
+#[test]
+fn test_load_srec() {
+    let text = "\
+S00600004844521B
+S1090600A92A8D0002008E
+S20600C000EAEA65
+S5030002FA
+S9030600F6
+";
+    let mut mem = Ram::new();
+
+    let loaded = load_srec(text, &mut mem).unwrap();
+
+    assert_eq!(loaded.start, Some(0x0600));
+    assert_eq!(loaded.len, 8);
+    assert_eq!(loaded.entry, Some(0x0600));
+    assert_eq!(mem.read(&0x0601), Some(0x2A));
+    assert_eq!(mem.read(&0xC001), Some(0xEA));
+
+    let loaded = load_srec("S80400C0003B\n", &mut mem).unwrap();
+    assert_eq!(loaded.entry, Some(0xC000));
+    assert_eq!(loaded.start, None);
+}
+
+#[test]
+fn test_load_srec_errors() {
+    let err = |text: &str| load_srec(text, &mut Ram::new()).unwrap_err();
+
+    assert_eq!(
+        err("S1090600A92A8D0002008F"),
+        LoadError::Checksum {
+            line: 1,
+            expected: 0x8E,
+            found: 0x8F
+        }
+    );
+    assert_eq!(err("S205010000EA0F"), LoadError::OutOfRange(0x10000));
+    assert_eq!(err("S306FFFFFFFFEA13"), LoadError::OutOfRange(0xFFFFFFFF));
+    assert!(matches!(
+        err("S9030600F6\nX1090600"),
+        LoadError::Syntax { line: 2, .. }
+    ));
+    assert!(matches!(
+        err("S10A0600A92A8D0002008E"),
+        LoadError::Syntax { .. }
+    ));
+    assert!(matches!(err("S4030002FA"), LoadError::Syntax { .. }));
+}
+
+#[test]
+fn test_load_into_rom_fails() {
+    let mut rom = Rom::new(&[0; 0x100]);
+    rom.policy = WritePolicy::ERROR;
+
+    assert_eq!(
+        load_bin(&[1], 0x10, &mut rom),
+        Err(LoadError::Memory(MemoryError::ReadOnly(0x10)))
+    );
+}
+
+#[test]
+fn test_detect_format() {
+    assert_eq!(Format::detect("test.hex", b""), Format::HEX);
+    assert_eq!(Format::detect("TEST.S19", b""), Format::SREC);
+    assert_eq!(Format::detect("demo.prg", b":00000001FF"), Format::PRG);
+    assert_eq!(Format::detect("image", b":00000001FF\r\n"), Format::HEX);
+    assert_eq!(Format::detect("image", b"S9030600F6\n"), Format::SREC);
+    assert_eq!(Format::detect("image.bin", b":\x00"), Format::BIN);
+    assert_eq!(Format::detect("image", b"\xA9\x2A"), Format::BIN);
+
+    assert_eq!("s28".parse::<Format>().unwrap(), Format::SREC);
+    assert!(matches!("elf".parse::<Format>(), Err(EmuError::Config(_))));
+}
+
+#[test]
+fn test_load_dispatch() {
+    let mut mem = Ram::new();
+
+    let loaded = load(Format::SREC, b"S1090600A92A8D0002008E\n", 0, &mut mem).unwrap();
+    assert_eq!(loaded.start, Some(0x0600));
+
+    let err = EmuError::from(load(Format::PRG, b"", 0, &mut mem).unwrap_err());
+    assert_eq!(err.to_string(), "PRG file without load address");
+}
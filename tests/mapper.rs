@@ -0,0 +1,122 @@
+#![allow(clippy::bool_assert_comparison)]
+
+mod common;
+
+use common::*;
+use luvemix_rust::bus::*;
+use luvemix_rust::cpu::*;
+use luvemix_rust::mapper::*;
+use luvemix_rust::memory::*;
+use luvemix_rust::types::*;
+
+/// ROM banks of `size` bytes, each filled with its own number.
+fn numbered_banks(count: usize, size: usize) -> Vec<Box<dyn Memory>> {
+    (0..count)
+        .map(|i| Box::new(Rom::new(&vec![i as Data; size])) as Box<dyn Memory>)
+        .collect()
+}
+
+#[test]
+fn test_uxrom() {
+    let mut mem = Banked::new(UxRom::new(8), numbered_banks(8, 0x4000));
+
+    assert_eq!(mem.read(&0x0000), Some(0));
+    assert_eq!(mem.read(&0x7FFF), Some(7));
+
+    mem.write(0x1234, 3);
+
+    assert_eq!(mem.mapper.selected(), 3);
+    assert_eq!(mem.read(&0x0000), Some(3));
+    assert_eq!(mem.read(&0x3FFF), Some(3));
+    assert_eq!(mem.read(&0x4000), Some(7));
+}
+
+#[test]
+fn test_uxrom_switched_by_cpu() {
+    let mut bus = Bus::new();
+    bus.attach_at(Ram::new(), Mapping::new(0x0000, 0x7FFF));
+    let banked = Banked::new(UxRom::new(4), numbered_banks(4, 0x4000));
+    bus.attach_at(banked, Mapping::new(0x8000, 0xFFFF));
+    // LDA #2 ; STA $8000 ; LDA $8000
+    load(
+        &mut bus,
+        PROGRAM_START,
+        &[0xA9, 0x02, 0x8D, 0x00, 0x80, 0xAD, 0x00, 0x80],
+    );
+    let mut cpu = Cpu::new();
+    cpu.state_mut().pc = PROGRAM_START;
+
+    for _ in 0..10 {
+        bus.cycle(&mut cpu).unwrap();
+    }
+
+    assert_eq!(cpu.state().a, 2);
+    assert_eq!(bus.read(&0xC000), Some(3));
+}
+
+#[test]
+fn test_window_mapper() {
+    let mut banks: Vec<Box<dyn Memory>> = vec![Box::new(Ram::new())];
+    for _ in 0..4 {
+        banks.push(Box::new(Ram::new()));
+    }
+    let mut mem = Banked::new(Window::new(0x8000, 0x4000, 0xFE00, 4), banks);
+
+    for bank in 0..4 {
+        mem.write(0xFE00, bank);
+        mem.write(0x8000, 0x10 + bank);
+    }
+    mem.write(0x7FFF, 0x42);
+    mem.write(0xC000, 0x43);
+
+    for bank in 0..4 {
+        mem.write(0xFE00, bank);
+        assert_eq!(mem.read(&0xFE00), Some(bank));
+        assert_eq!(mem.read(&0x8000), Some(0x10 + bank));
+        assert_eq!(mem.read(&0x7FFF), Some(0x42));
+        assert_eq!(mem.read(&0xC000), Some(0x43));
+    }
+}
+
+#[test]
+fn test_c64_pla() {
+    let banks: Vec<Box<dyn Memory>> = vec![
+        Box::new(Ram::new()),
+        Box::new(Rom::new(&[0xBA; 0x2000])),
+        Box::new(Rom::new(&[0xEE; 0x2000])),
+    ];
+    let mut mem = Banked::new(C64Pla::new(), banks);
+
+    assert_eq!(mem.read(&0x0001), Some(0x37));
+    assert_eq!(mem.read(&0xA000), Some(0xBA));
+    assert_eq!(mem.read(&0xFFFC), Some(0xEE));
+
+    // writes go to the RAM under the ROM
+    mem.write(0xA000, 0x42);
+    mem.write(0xFFFC, 0x43);
+    assert_eq!(mem.read(&0xA000), Some(0xBA));
+
+    // HIRAM off: all RAM
+    mem.write(0x0001, 0x35);
+    assert_eq!(mem.read(&0xA000), Some(0x42));
+    assert_eq!(mem.read(&0xFFFC), Some(0x43));
+
+    // LORAM off: KERNAL only
+    mem.write(0x0001, 0x36);
+    assert_eq!(mem.read(&0xA000), Some(0x42));
+    assert_eq!(mem.read(&0xFFFC), Some(0xEE));
+}
+
+#[test]
+fn test_banked_rom_write_error() {
+    let mut rom = Rom::new(&[0; 0x4000]);
+    rom.policy = WritePolicy::ERROR;
+    let banks: Vec<Box<dyn Memory>> = vec![Box::new(Ram::new()), Box::new(rom)];
+    let mut mem = Banked::new(Window::new(0x8000, 0x4000, 0xFE00, 1), banks);
+
+    assert_eq!(
+        mem.try_write(0x8010, 0x42),
+        Err(MemoryError::ReadOnly(0x8010))
+    );
+    assert_eq!(mem.try_write(0x0010, 0x42), Ok(()));
+}
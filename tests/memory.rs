@@ -0,0 +1,113 @@
+#![allow(clippy::bool_assert_comparison)]
+
+use luvemix_rust::memory::*;
+
+#[test]
+fn test_ram_readwrite() {
+    let mut m = Ram::new();
+
+    assert_eq!(m.read(&0), Some(0));
+
+    m.write(0, 42);
+    m.write(0xFFFF, 43);
+
+    assert_eq!(m.read(&0), Some(42));
+    assert_eq!(m.read(&0xFFFF), Some(43));
+}
+
+#[test]
+fn test_ram_power_on_patterns() {
+    let ones = Ram::with_pattern(PowerOn::ONES);
+    assert!((0..=0xFFFF).all(|addr| ones.read(&addr) == Some(0xFF)));
+
+    let dram = Ram::with_pattern(PowerOn::DRAM);
+    assert_eq!(dram.read(&0x0000), Some(0x00));
+    assert_eq!(dram.read(&0x003F), Some(0x00));
+    assert_eq!(dram.read(&0x0040), Some(0xFF));
+    assert_eq!(dram.read(&0x007F), Some(0xFF));
+    assert_eq!(dram.read(&0x0080), Some(0x00));
+}
+
+#[test]
+fn test_ram_random_pattern_is_seeded() {
+    let dump = |ram: &Ram| (0..=0xFFFF).map(|addr| ram.read(&addr)).collect::<Vec<_>>();
+    let a = dump(&Ram::with_pattern(PowerOn::RANDOM(1)));
+    let b = dump(&Ram::with_pattern(PowerOn::RANDOM(1)));
+    let c = dump(&Ram::with_pattern(PowerOn::RANDOM(2)));
+
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    // not stuck on a single value
+    assert!(a.iter().any(|v| *v != a[0]));
+}
+
+#[test]
+fn test_cheapo_memory_readwrite() {
+    let mut m = CheapoMemory::new();
+
+    assert_eq!(m.read(&0), None);
+
+    m.write(0, 42);
+
+    assert_eq!(m.read(&0).unwrap(), 42);
+
+    m.write(0, 43);
+
+    assert_eq!(m.read(&0).unwrap(), 43);
+}
+
+#[test]
+fn test_rom_read() {
+    let rom = Rom::new(&[0x01, 0x02, 0x03]);
+
+    assert_eq!(rom.len(), 3);
+    assert_eq!(rom.read(&0), Some(0x01));
+    assert_eq!(rom.read(&2), Some(0x03));
+    assert_eq!(rom.read(&3), None);
+}
+
+#[test]
+fn test_rom_write_policies() {
+    for policy in [WritePolicy::IGNORE, WritePolicy::LOG] {
+        let mut rom = Rom::new(&[0x01]);
+        rom.policy = policy;
+
+        rom.write(0, 0x42);
+        assert_eq!(rom.try_write(0, 0x42), Ok(()));
+
+        assert_eq!(rom.read(&0), Some(0x01));
+    }
+
+    let mut rom = Rom::new(&[0x01]);
+    rom.policy = WritePolicy::ERROR;
+
+    assert_eq!(rom.try_write(0, 0x42), Err(MemoryError::ReadOnly(0)));
+    rom.write(0, 0x42);
+    assert_eq!(rom.read(&0), Some(0x01));
+}
+
+#[test]
+fn test_rom_from_file() {
+    let path = std::env::temp_dir().join(format!("luvemix-rom-{}.bin", std::process::id()));
+    std::fs::write(&path, [0xEA, 0x4C, 0x00, 0xF0]).unwrap();
+
+    let rom = Rom::from_file(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let rom = rom.unwrap();
+    assert_eq!(rom.len(), 4);
+    assert_eq!(rom.read(&1), Some(0x4C));
+    assert!(Rom::from_file(&path).is_err());
+}
+
+#[test]
+fn test_memory_error_display() {
+    assert_eq!(
+        MemoryError::Unmapped(0x9000).to_string(),
+        "access to unmapped $9000"
+    );
+    assert_eq!(
+        MemoryError::ReadOnly(0x12).at(0xE012),
+        MemoryError::ReadOnly(0xE012)
+    );
+}
@@ -0,0 +1,265 @@
+use luvemix_rust::asm;
+use luvemix_rust::cpu::*;
+use luvemix_rust::memory::*;
+use luvemix_rust::monitor::*;
+
+/// A monitor with `source` assembled into RAM, and PC at its start.
+fn monitor(source: &str) -> Monitor<Ram> {
+    let assembly = asm::assemble(Variant::NMOS, source).unwrap();
+    let mut mem = Ram::new();
+    assembly.write_to(&mut mem).unwrap();
+    let mut cpu = Cpu::new();
+    cpu.state_mut().pc = assembly.start().unwrap();
+    cpu.state_mut().sp = 0xFF;
+    Monitor::new(cpu, mem)
+}
+
+fn command(monitor: &mut Monitor<Ram>, line: &str) -> String {
+    let mut out = Vec::new();
+    assert!(monitor.command(line, &mut out).unwrap());
+    String::from_utf8(out).unwrap()
+}
+
+const PROGRAM: &str = "
+    .org $0600
+            LDX #3
+    loop:   JSR double
+            DEX
+            BNE loop
+            STA $0200
+            BRK
+    double: ASL
+            ADC #1
+            RTS
+";
+
+#[test]
+fn test_step() {
+    let mut monitor = monitor(PROGRAM);
+    assert_eq!(
+        command(&mut monitor, "step"),
+        "PC=0602 A=00 X=03 Y=00 SP=FF P=nv-bdizc cycles=2\n0602  20 0C 06  JSR $060C\n"
+    );
+    let out = command(&mut monitor, "s 3");
+    assert!(out.starts_with("PC=060F A=01 "), "{}", out);
+}
+
+#[test]
+fn test_next_steps_over_subroutines() {
+    let mut monitor = monitor(PROGRAM);
+    command(&mut monitor, "step");
+    let out = command(&mut monitor, "next");
+    assert!(out.starts_with("PC=0605 A=01 "), "{}", out);
+    let out = command(&mut monitor, "n");
+    assert!(out.starts_with("PC=0606 A=01 X=02 "), "{}", out);
+}
+
+#[test]
+fn test_next_stops_at_breakpoints_inside_subroutines() {
+    let mut monitor = monitor(PROGRAM);
+    command(&mut monitor, "step");
+    command(&mut monitor, "break 60D");
+    let out = command(&mut monitor, "next");
+    assert!(out.starts_with("breakpoint at $060D\nPC=060D "), "{}", out);
+}
+
+#[test]
+fn test_continue_to_breakpoint_and_brk() {
+    let mut monitor = monitor(PROGRAM);
+    assert_eq!(command(&mut monitor, "b $0605"), "");
+    assert_eq!(command(&mut monitor, "b 0x060C"), "");
+    assert_eq!(command(&mut monitor, "break"), "$0605\n$060C\n");
+
+    let out = command(&mut monitor, "c");
+    assert!(out.starts_with("breakpoint at $060C\nPC=060C "), "{}", out);
+    let out = command(&mut monitor, "continue");
+    assert!(
+        out.starts_with("breakpoint at $0605\nPC=0605 A=01 "),
+        "{}",
+        out
+    );
+
+    assert_eq!(command(&mut monitor, "delete 60C"), "");
+    assert_eq!(
+        command(&mut monitor, "delete 60C"),
+        "error: no breakpoint at $060C\n"
+    );
+    command(&mut monitor, "delete 605");
+    let out = command(&mut monitor, "continue");
+    assert!(
+        out.starts_with("BRK at $060B\nPC=060B A=07 X=00 "),
+        "{}",
+        out
+    );
+}
+
+#[test]
+fn test_conditional_breakpoints() {
+    let mut monitor = monitor(PROGRAM);
+    assert_eq!(command(&mut monitor, "break 605 if X == 2 && A > 1"), "");
+    assert_eq!(command(&mut monitor, "b"), "$0605 if X == 2 && A > 1\n");
+    let out = command(&mut monitor, "c");
+    assert!(
+        out.starts_with("breakpoint at $0605\nPC=0605 A=03 X=02 "),
+        "{}",
+        out
+    );
+
+    let out = command(&mut monitor, "break 605 if Q");
+    assert!(
+        out.starts_with("error: invalid configuration: invalid condition"),
+        "{}",
+        out
+    );
+    assert_eq!(
+        command(&mut monitor, "break 605 when X"),
+        "error: unexpected argument: when\n"
+    );
+}
+
+#[test]
+fn test_watchpoints() {
+    let mut monitor = monitor(PROGRAM);
+    assert_eq!(command(&mut monitor, "watch 200 2FF"), "");
+    assert_eq!(command(&mut monitor, "rwatch 60E"), "");
+    assert_eq!(command(&mut monitor, "awatch 10"), "");
+    assert_eq!(
+        command(&mut monitor, "watch"),
+        "write $0200-$02FF\nread $060E\naccess $0010\n"
+    );
+
+    let out = command(&mut monitor, "continue");
+    assert!(
+        out.starts_with("read of $01 from $060E\nPC=060F "),
+        "{}",
+        out
+    );
+    assert_eq!(command(&mut monitor, "unwatch 60E"), "");
+    let out = command(&mut monitor, "continue");
+    assert!(
+        out.starts_with("write of $07 to $0200\nPC=060B "),
+        "{}",
+        out
+    );
+
+    assert_eq!(
+        command(&mut monitor, "unwatch 60E"),
+        "error: no such watchpoint\n"
+    );
+    assert_eq!(command(&mut monitor, "watch 20 10"), "error: empty range\n");
+}
+
+#[test]
+fn test_continue_until_halted() {
+    let mut monitor = monitor(".org $0600 / LDA #1 / .byte $02");
+    let out = command(&mut monitor, "continue");
+    assert!(out.starts_with("CPU jammed by $02 at $0602\n"), "{}", out);
+}
+
+#[test]
+fn test_continue_until_jump_to_itself() {
+    let mut monitor = monitor(".org $0600 / LDX #2 / @loop: DEX / BNE @loop / done: JMP done");
+    let out = command(&mut monitor, "c");
+    assert!(
+        out.starts_with("trap at $0605\nPC=0605 A=00 X=00 "),
+        "{}",
+        out
+    );
+    let out = command(&mut monitor, "c");
+    assert!(out.starts_with("trap at $0605\n"), "{}", out);
+}
+
+#[test]
+fn test_registers() {
+    let mut monitor = monitor(PROGRAM);
+    assert_eq!(
+        command(&mut monitor, "regs"),
+        "PC=0600 A=00 X=00 Y=00 SP=FF P=nv-bdizc cycles=0\n0600  A2 03     LDX #$03\n"
+    );
+    command(&mut monitor, "set a 2A");
+    command(&mut monitor, "set X $FF");
+    command(&mut monitor, "set y 1");
+    command(&mut monitor, "set sp f0");
+    command(&mut monitor, "set p 81");
+    let out = command(&mut monitor, "set PC 0605");
+    assert!(
+        out.starts_with("PC=0605 A=2A X=FF Y=01 SP=F0 P=Nv-bdizC "),
+        "{}",
+        out
+    );
+    assert_eq!(
+        command(&mut monitor, "set q 1"),
+        "error: unknown register: q\n"
+    );
+    assert_eq!(
+        command(&mut monitor, "set a 100"),
+        "error: out of range: 100\n"
+    );
+}
+
+#[test]
+fn test_memory() {
+    let mut monitor = monitor(PROGRAM);
+    assert_eq!(command(&mut monitor, "fill 10 1F AA"), "");
+    assert_eq!(command(&mut monitor, "poke 12 1 2 3"), "");
+    assert_eq!(
+        command(&mut monitor, "mem 10 21"),
+        "0010: AA AA 01 02 03 AA AA AA AA AA AA AA AA AA AA AA\n0020: 00 00\n"
+    );
+    assert_eq!(command(&mut monitor, "m 0600").lines().count(), 4);
+    assert_eq!(command(&mut monitor, "mem 20 10"), "error: empty range\n");
+    assert_eq!(
+        command(&mut monitor, "poke 10 zz"),
+        "error: not a hex number: zz\n"
+    );
+}
+
+#[test]
+fn test_disasm() {
+    let mut monitor = monitor(PROGRAM);
+    assert_eq!(
+        command(&mut monitor, "disasm 60C 3"),
+        "060C  0A        ASL A\n060D  69 01     ADC #$01\n060F  60        RTS\n"
+    );
+    assert_eq!(command(&mut monitor, "d").lines().count(), 8);
+    let out = command(&mut monitor, "d 600 1");
+    assert_eq!(out, "0600  A2 03     LDX #$03\n");
+}
+
+#[test]
+fn test_bad_commands() {
+    let mut monitor = monitor(PROGRAM);
+    assert_eq!(
+        command(&mut monitor, "frobnicate"),
+        "error: unknown command: frobnicate, try help\n"
+    );
+    assert_eq!(command(&mut monitor, "step x"), "error: not a count: x\n");
+    assert_eq!(
+        command(&mut monitor, "regs 1"),
+        "error: unexpected argument: 1\n"
+    );
+    assert_eq!(
+        command(&mut monitor, "fill 10"),
+        "error: missing argument\n"
+    );
+    assert_eq!(command(&mut monitor, ""), "");
+    assert!(command(&mut monitor, "help").contains("breakpoint"));
+}
+
+#[test]
+fn test_repl() {
+    let mut monitor = monitor(PROGRAM);
+    let mut out = Vec::new();
+    monitor
+        .repl("step\nquit\nstep\n".as_bytes(), &mut out)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "PC=0600 A=00 X=00 Y=00 SP=FF P=nv-bdizc cycles=0\n\
+         0600  A2 03     LDX #$03\n\
+         > PC=0602 A=00 X=03 Y=00 SP=FF P=nv-bdizc cycles=2\n\
+         0602  20 0C 06  JSR $060C\n\
+         > "
+    );
+    assert_eq!(monitor.cpu.state().pc, 0x0602);
+}
@@ -0,0 +1,74 @@
+mod common;
+use common::*;
+
+use luvemix_rust::asm;
+use luvemix_rust::breakpoints::*;
+use luvemix_rust::runner::*;
+
+#[test]
+fn test_run_stops_before_brk() {
+    let (mut cpu, mut mem) = setup(&asm!("LDA #1 / BRK"));
+    let stop = run(&mut cpu, &mut mem, &Limits::default()).unwrap();
+    assert_eq!(stop, Stop::Brk(0x0202));
+    assert_eq!(cpu.state().a, 1);
+}
+
+#[test]
+fn test_run_to_trap() {
+    let (mut cpu, mut mem) = setup(&asm!(".org $0200 / loop: INX / JMP loop"));
+    let limits = Limits {
+        traps: vec![0x0200],
+        ..Limits::default()
+    };
+    // The trap only counts once PC gets back to it.
+    assert_eq!(
+        run(&mut cpu, &mut mem, &limits).unwrap(),
+        Stop::Trap(0x0200)
+    );
+    assert_eq!(cpu.state().x, 1);
+}
+
+#[test]
+fn test_run_to_jump_to_itself() {
+    let program = asm!(".org $0200 / LDX #2 / @loop: DEX / BNE @loop / done: JMP done");
+    let limits = Limits {
+        self_jumps: true,
+        ..Limits::default()
+    };
+    let (mut cpu, mut mem) = setup(&program);
+    assert_eq!(
+        run(&mut cpu, &mut mem, &limits).unwrap(),
+        Stop::Trap(0x0205)
+    );
+    assert_eq!(cpu.state().x, 0);
+}
+
+#[test]
+fn test_run_cycle_limit() {
+    let (mut cpu, mut mem) = setup(&asm!(".org $0200 / loop: JMP loop"));
+    let limits = Limits {
+        max_cycles: Some(10),
+        ..Limits::default()
+    };
+    assert_eq!(run(&mut cpu, &mut mem, &limits).unwrap(), Stop::CycleLimit);
+    assert!(cpu.cycles() >= 10);
+}
+
+#[test]
+fn test_run_stops_at_jam_and_breakpoints() {
+    let (mut cpu, mut mem) = setup(&[0xEA, 0x02]);
+    assert_eq!(
+        run(&mut cpu, &mut mem, &Limits::default()).unwrap(),
+        Stop::Jam {
+            opcode: 0x02,
+            addr: 0x0201
+        }
+    );
+
+    let (mut cpu, mut mem) = setup(&[0xEA, 0xEA, 0x02]);
+    cpu.breakpoints_mut().add(0x0202);
+    assert_eq!(
+        run(&mut cpu, &mut mem, &Limits::default()).unwrap(),
+        Stop::Hit(Hit::Breakpoint(0x0202))
+    );
+}
@@ -0,0 +1,182 @@
+#![allow(clippy::bool_assert_comparison)]
+
+use luvemix_rust::cpu::*;
+use luvemix_rust::types::*;
+
+#[test]
+fn test_get_set_flag() {
+    let mut cpu = CpuState::new();
+
+    assert_eq!(cpu.get_flag(Flag::ZRO), false);
+    cpu.set_flag(Flag::ZRO, true);
+    assert_eq!(cpu.get_flag(Flag::ZRO), true);
+    cpu.set_flag(Flag::ZRO, false);
+    assert_eq!(cpu.get_flag(Flag::ZRO), false);
+}
+
+#[test]
+fn test_get_set_flag_ignores_other_flags() {
+    let mut cpu = CpuState::new();
+
+    assert_eq!(cpu.get_flag(Flag::ZRO), false);
+    cpu.set_flag(Flag::NEG, true);
+    assert_eq!(cpu.get_flag(Flag::ZRO), false);
+    cpu.set_flag(Flag::ZRO, false);
+    assert_eq!(cpu.get_flag(Flag::NEG), true);
+}
+
+#[test]
+fn test_reserved_flag_always_set() {
+    let mut cpu = CpuState::new();
+
+    assert_eq!(cpu.get_flag(Flag::RSV), true);
+    cpu.set_flag(Flag::RSV, false);
+    assert_eq!(cpu.get_flag(Flag::RSV), true);
+    cpu.set_sr(0);
+    assert_eq!(cpu.get_flag(Flag::RSV), true);
+}
+
+#[test]
+fn test_break_flag_not_stored() {
+    let mut cpu = CpuState::new();
+
+    cpu.set_flag(Flag::BRK, true);
+    assert_eq!(cpu.get_flag(Flag::BRK), false);
+    cpu.set_sr(0xFF);
+    assert_eq!(cpu.get_flag(Flag::BRK), false);
+}
+
+#[test]
+fn test_get_set_sr() {
+    let mut cpu = CpuState::new();
+
+    cpu.set_sr(0b1100_1111);
+
+    assert_eq!(cpu.sr(), 0b1110_1111);
+    assert_eq!(cpu.get_flag(Flag::CRY), true);
+    assert_eq!(cpu.get_flag(Flag::ZRO), true);
+    assert_eq!(cpu.get_flag(Flag::INT), true);
+    assert_eq!(cpu.get_flag(Flag::DEC), true);
+    assert_eq!(cpu.get_flag(Flag::OVF), true);
+    assert_eq!(cpu.get_flag(Flag::NEG), true);
+
+    cpu.set_sr(0);
+
+    assert_eq!(cpu.sr(), 0b0010_0000);
+}
+
+#[test]
+fn test_transfer_set_a() {
+    let mut cpu = CpuState::new();
+    cpu.mdr = 42;
+
+    cpu.transfer(Target::MDR, Target::ACC);
+
+    assert_eq!(cpu.a, 42);
+}
+
+#[test]
+fn test_transfer_set_a_sets_zro() {
+    let mut cpu = CpuState::new();
+    cpu.mdr = 0;
+
+    cpu.transfer(Target::MDR, Target::ACC);
+
+    assert_eq!(cpu.get_flag(Flag::ZRO), true);
+
+    cpu.mdr = 42;
+    cpu.transfer(Target::MDR, Target::ACC);
+
+    assert_eq!(cpu.get_flag(Flag::ZRO), false);
+}
+
+#[test]
+fn test_transfer_set_a_sets_neg() {
+    let mut cpu = CpuState::new();
+
+    cpu.mdr = 1 << (DATA_WIDTH - 1);
+    cpu.transfer(Target::MDR, Target::ACC);
+
+    assert_eq!(cpu.get_flag(Flag::NEG), true);
+
+    cpu.mdr = 0;
+    cpu.transfer(Target::MDR, Target::ACC);
+
+    assert_eq!(cpu.get_flag(Flag::NEG), false);
+}
+
+#[test]
+fn test_transfer_set_x_y() {
+    let mut cpu = CpuState::new();
+    cpu.mdr = 42;
+
+    cpu.transfer(Target::MDR, Target::X);
+    cpu.transfer(Target::MDR, Target::Y);
+
+    assert_eq!(cpu.x, 42);
+    assert_eq!(cpu.y, 42);
+}
+
+#[test]
+fn test_transfer_set_x_y_sets_flags() {
+    for dst in [Target::X, Target::Y] {
+        let mut cpu = CpuState::new();
+
+        cpu.mdr = 0;
+        cpu.transfer(Target::MDR, dst);
+        assert_eq!(cpu.get_flag(Flag::ZRO), true);
+        assert_eq!(cpu.get_flag(Flag::NEG), false);
+
+        cpu.mdr = 1 << (DATA_WIDTH - 1);
+        cpu.transfer(Target::MDR, dst);
+        assert_eq!(cpu.get_flag(Flag::ZRO), false);
+        assert_eq!(cpu.get_flag(Flag::NEG), true);
+    }
+}
+
+#[test]
+fn test_transfer_matrix() {
+    use Target::*;
+    let targets = [ACC, X, Y, SP, MDR];
+    for src in targets {
+        for dst in targets {
+            let mut cpu = CpuState::new();
+            cpu.a = 0x01;
+            cpu.x = 0x02;
+            cpu.y = 0x03;
+            cpu.sp = 0x04;
+            cpu.mdr = 0x05;
+            let expected = match src {
+                ACC => 0x01,
+                X => 0x02,
+                Y => 0x03,
+                SP => 0x04,
+                MDR => 0x05,
+            };
+
+            cpu.transfer(src, dst);
+
+            let val = match dst {
+                ACC => cpu.a,
+                X => cpu.x,
+                Y => cpu.y,
+                SP => cpu.sp,
+                MDR => cpu.mdr,
+            };
+            assert_eq!(val, expected, "{:?} -> {:?}", src, dst);
+        }
+    }
+}
+
+#[test]
+fn test_transfer_set_sp_keeps_flags() {
+    let mut cpu = CpuState::new();
+    cpu.x = 0x80;
+    cpu.set_flag(Flag::ZRO, true);
+
+    cpu.transfer(Target::X, Target::SP);
+
+    assert_eq!(cpu.sp, 0x80);
+    assert_eq!(cpu.get_flag(Flag::ZRO), true);
+    assert_eq!(cpu.get_flag(Flag::NEG), false);
+}
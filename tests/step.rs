@@ -0,0 +1,228 @@
+#![allow(clippy::bool_assert_comparison)]
+
+mod common;
+
+use common::*;
+use luvemix_rust::bus::*;
+use luvemix_rust::cpu::*;
+use luvemix_rust::memory::*;
+use luvemix_rust::types::*;
+
+#[test]
+fn test_step_retires_instructions() {
+    // LDA #$01 ; INC $10
+    let (mut cpu, mut mem) = setup(&[0xA9, 0x01, 0xE6, 0x10]);
+    mem.write(0x10, 0);
+
+    assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::Retired);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 2);
+    assert_eq!(cpu.state().a, 1);
+
+    assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::Retired);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 4);
+    assert_eq!(mem.read(&0x10), Some(1));
+}
+
+#[test]
+fn test_step_instruction() {
+    // LDA $12F0,X ; JSR $0700 ; NOP
+    let (mut cpu, mut mem) = setup(&[0xBD, 0xF0, 0x12, 0x20, 0x00, 0x07, 0xEA]);
+    cpu.state_mut().x = 0x20;
+
+    let executed = cpu.step_instruction(&mut mem).unwrap();
+    assert_eq!(executed.pc, PROGRAM_START);
+    assert_eq!(executed.opcode, 0xBD);
+    assert_eq!(executed.instruction, decode(0xBD).unwrap());
+    assert_eq!(executed.operands(), &[0xF0, 0x12]);
+    assert_eq!(executed.cycles, 5);
+    assert_eq!(executed.outcome, StepOutcome::Retired);
+
+    let executed = cpu.step_instruction(&mut mem).unwrap();
+    assert_eq!(executed.pc, PROGRAM_START + 3);
+    assert_eq!(executed.operands(), &[0x00, 0x07]);
+    assert_eq!(executed.cycles, 6);
+    assert_eq!(cpu.state().pc, 0x0700);
+}
+
+#[test]
+fn test_step_instruction_variants() {
+    // BBS0 $10,+2 ; NOP
+    let (mut cpu, mut mem) = setup_variant(Variant::WDC, &[0x8F, 0x10, 0x02, 0xEA]);
+    mem.write(0x10, 0x01);
+
+    let executed = cpu.step_instruction(&mut mem).unwrap();
+    assert_eq!(executed.instruction.op, Operation::BBS);
+    assert_eq!(executed.operands(), &[0x10, 0x02]);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 5);
+
+    // implied on NMOS, a single-cycle NOP on the 65C02
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[0x03]);
+    let executed = cpu.step_instruction(&mut mem).unwrap();
+    assert_eq!(executed.operands(), &[] as &[Data]);
+    assert_eq!(executed.cycles, 1);
+}
+
+#[test]
+fn test_step_instruction_interrupt() {
+    let (mut cpu, mut mem) = setup_interrupts();
+    cpu.nmi = true;
+    cpu.step(&mut mem).unwrap();
+
+    let executed = cpu.step_instruction(&mut mem).unwrap();
+    assert_eq!(executed.pc, PROGRAM_START + 1);
+    assert_eq!(executed.cycles, 7);
+    assert_eq!(executed.operands(), &[] as &[Data]);
+    assert_eq!(executed.outcome, StepOutcome::Interrupted(Interrupt::NMI));
+}
+
+#[test]
+fn test_run_cycles() {
+    // LDA #$01 ; JAM
+    let (mut cpu, mut mem) = setup(&[0xA9, 0x01, 0x02]);
+
+    cpu.run_cycles(&mut mem, 1).unwrap();
+    assert_eq!(cpu.cycles(), 1);
+    assert_eq!(cpu.state().a, 0);
+    cpu.run_cycles(&mut mem, 1).unwrap();
+    assert_eq!(cpu.state().a, 1);
+
+    assert!(matches!(
+        cpu.run_cycles(&mut mem, 100),
+        Err(EmuError::Jam { .. })
+    ));
+    assert_eq!(cpu.cycles(), 4);
+}
+
+#[test]
+fn test_run_until() {
+    // LDX #$05 ; DEX ; BNE -3 ; NOP
+    let (mut cpu, mut mem) = setup(&[0xA2, 0x05, 0xCA, 0xD0, 0xFD, 0xEA]);
+
+    let outcome = cpu.run_until(&mut mem, |cpu| cpu.state().x == 2).unwrap();
+    assert_eq!(outcome, StepOutcome::Retired);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 3);
+
+    cpu.run_until(&mut mem, |cpu| cpu.state().pc == PROGRAM_START + 5)
+        .unwrap();
+    assert_eq!(cpu.state().x, 0);
+    assert_eq!(cpu.cycles(), 2 + 5 * 2 + 4 * 3 + 2);
+}
+
+#[test]
+fn test_step_interrupted() {
+    let (mut cpu, mut mem) = setup_interrupts();
+    cpu.irq = true;
+
+    assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::Retired);
+    assert_eq!(
+        cpu.step(&mut mem).unwrap(),
+        StepOutcome::Interrupted(Interrupt::IRQ)
+    );
+    assert_eq!(cpu.state().pc, 0x3000);
+
+    cpu.res = true;
+    assert_eq!(
+        cpu.step(&mut mem).unwrap(),
+        StepOutcome::Interrupted(Interrupt::RES)
+    );
+    cpu.res = false;
+    assert_eq!(
+        cpu.step(&mut mem).unwrap(),
+        StepOutcome::Interrupted(Interrupt::RES)
+    );
+    assert_eq!(cpu.state().pc, 0x5000);
+}
+
+#[test]
+fn test_step_jam() {
+    // NOP ; JAM
+    let (mut cpu, mut mem) = setup(&[0xEA, 0x12]);
+
+    assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::Retired);
+    match cpu.step(&mut mem) {
+        Err(EmuError::Jam { opcode, addr }) => {
+            assert_eq!(opcode, 0x12);
+            assert_eq!(addr, PROGRAM_START + 1);
+        }
+        other => panic!("expected JAM, got {:?}", other),
+    }
+    // stays jammed
+    assert!(matches!(cpu.step(&mut mem), Err(EmuError::Jam { .. })));
+}
+
+#[test]
+fn test_step_halted_and_waiting() {
+    let (mut cpu, mut mem) = setup_variant(Variant::WDC, &[0xCB, 0xDB]);
+    load(&mut mem, 0xFFFE, &[0x01, 0x06]);
+
+    assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::Waiting);
+    assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::Waiting);
+
+    cpu.state_mut().set_flag(Flag::INT, true);
+    cpu.irq = true;
+    assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::Retired);
+    assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::Halted);
+    assert_eq!(cpu.step(&mut mem).unwrap(), StepOutcome::Halted);
+}
+
+#[test]
+fn test_run_until_stopped() {
+    // LDX #$05 ; DEX ; BNE -3 ; STP
+    let (mut cpu, mut mem) = setup_variant(Variant::WDC, &[0xA2, 0x05, 0xCA, 0xD0, 0xFD, 0xDB]);
+
+    assert_eq!(cpu.run(&mut mem).unwrap(), StepOutcome::Halted);
+    assert_eq!(cpu.state().x, 0);
+}
+
+#[test]
+fn test_run_until_waiting() {
+    // NOP ; WAI, with no interrupt to wake it up
+    let (mut cpu, mut mem) = setup_variant(Variant::WDC, &[0xEA, 0xCB]);
+
+    assert_eq!(
+        cpu.run_until(&mut mem, |_| false).unwrap(),
+        StepOutcome::Waiting
+    );
+    assert_eq!(cpu.state().pc, PROGRAM_START + 2);
+}
+
+#[test]
+fn test_step_strict_unmapped() {
+    let mut bus = Bus::new();
+    bus.attach_at(Ram::new(), Mapping::new(0x0000, 0x7FFF));
+    bus.strict = true;
+    // LDA $9000
+    load(&mut bus, PROGRAM_START, &[0xAD, 0x00, 0x90]);
+    let mut cpu = Cpu::new();
+    cpu.state_mut().pc = PROGRAM_START;
+
+    match cpu.step(&mut bus) {
+        Err(EmuError::Memory(err)) => assert_eq!(err, MemoryError::Unmapped(0x9000)),
+        other => panic!("expected unmapped access, got {:?}", other),
+    }
+    // the instruction still completed
+    assert_eq!(cpu.state().pc, PROGRAM_START + 3);
+}
+
+#[test]
+fn test_emu_error() {
+    let err = "z80".parse::<Variant>().unwrap_err();
+    assert!(matches!(err, EmuError::Config(_)));
+    assert_eq!(
+        err.to_string(),
+        "invalid configuration: unknown CPU variant: z80"
+    );
+
+    let err = EmuError::from(MemoryError::ReadOnly(0xE000));
+    assert_eq!(err.to_string(), "write to ROM at $E000");
+    assert!(std::error::Error::source(&err).is_some());
+
+    let err = EmuError::Jam {
+        opcode: 0x02,
+        addr: 0x0600,
+    };
+    assert_eq!(err.to_string(), "CPU jammed by $02 at $0600");
+
+    let err = EmuError::from(std::fs::File::open("/nonexistent/luvemix").unwrap_err());
+    assert!(matches!(err, EmuError::Io(_)));
+}
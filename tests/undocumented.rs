@@ -0,0 +1,418 @@
+#![allow(clippy::bool_assert_comparison)]
+
+mod common;
+
+use common::*;
+use luvemix_rust::cpu::*;
+use luvemix_rust::memory::*;
+
+#[test]
+fn test_decode_undocumented_opcodes() {
+    for opcode in 0..=0xFF {
+        assert_ne!(
+            decode(opcode).is_some(),
+            decode_undocumented(opcode).is_some(),
+            "opcode {:02X}",
+            opcode
+        );
+    }
+    let count = (0..=0xFF)
+        .filter(|op| decode_undocumented(*op).is_some())
+        .count();
+
+    assert_eq!(count, 105);
+}
+
+#[test]
+fn test_undocumented_opcode_cycles() {
+    #[rustfmt::skip]
+    let cycles: [usize; 256] = [
+        0, 0, 0, 8, 3, 0, 0, 5, 0, 0, 0, 2, 4, 0, 0, 6,
+        0, 0, 0, 8, 4, 0, 0, 6, 0, 0, 2, 7, 4, 0, 0, 7,
+        0, 0, 0, 8, 0, 0, 0, 5, 0, 0, 0, 2, 0, 0, 0, 6,
+        0, 0, 0, 8, 4, 0, 0, 6, 0, 0, 2, 7, 4, 0, 0, 7,
+        0, 0, 0, 8, 3, 0, 0, 5, 0, 0, 0, 2, 0, 0, 0, 6,
+        0, 0, 0, 8, 4, 0, 0, 6, 0, 0, 2, 7, 4, 0, 0, 7,
+        0, 0, 0, 8, 3, 0, 0, 5, 0, 0, 0, 2, 0, 0, 0, 6,
+        0, 0, 0, 8, 4, 0, 0, 6, 0, 0, 2, 7, 4, 0, 0, 7,
+        2, 0, 2, 6, 0, 0, 0, 3, 0, 2, 0, 2, 0, 0, 0, 4,
+        0, 0, 0, 6, 0, 0, 0, 4, 0, 0, 0, 5, 5, 0, 5, 5,
+        0, 0, 0, 6, 0, 0, 0, 3, 0, 0, 0, 2, 0, 0, 0, 4,
+        0, 0, 0, 5, 0, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0, 4,
+        0, 0, 2, 8, 0, 0, 0, 5, 0, 0, 0, 2, 0, 0, 0, 6,
+        0, 0, 0, 8, 4, 0, 0, 6, 0, 0, 2, 7, 4, 0, 0, 7,
+        0, 0, 2, 8, 0, 0, 0, 5, 0, 0, 0, 2, 0, 0, 0, 6,
+        0, 0, 0, 8, 4, 0, 0, 6, 0, 0, 2, 7, 4, 0, 0, 7,
+    ];
+    for opcode in 0..=0xFF {
+        match decode_undocumented(opcode) {
+            Some(i) if i.op != Operation::JAM => {}
+            _ => continue,
+        }
+        let (mut cpu, mut mem) = setup(&[opcode]);
+
+        let taken = run_instruction(&mut cpu, &mut mem);
+
+        assert_eq!(taken, cycles[opcode as usize], "opcode {:02X}", opcode);
+    }
+}
+
+#[test]
+fn test_undocumented_page_crossing_cycles() {
+    // NOP $12FF,X ; LAX $12FF,Y
+    for opcode in [0x1C, 0xBF] {
+        let (mut cpu, mut mem) = setup(&[opcode, 0xFF, 0x12]);
+        cpu.state_mut().x = 1;
+        cpu.state_mut().y = 1;
+
+        assert_eq!(run_instruction(&mut cpu, &mut mem), 5);
+    }
+}
+
+#[test]
+fn test_slo() {
+    for_each_mode(
+        Operation::SLO,
+        0x81,
+        |s| s.a = 0x01,
+        |cpu, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0x02));
+            assert_eq!(cpu.state().a, 0x03);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+        },
+    );
+}
+
+#[test]
+fn test_rla() {
+    for_each_mode(
+        Operation::RLA,
+        0x81,
+        |s| {
+            s.a = 0x0F;
+            s.set_flag(Flag::CRY, true);
+        },
+        |cpu, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0x03));
+            assert_eq!(cpu.state().a, 0x03);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+        },
+    );
+}
+
+#[test]
+fn test_sre() {
+    for_each_mode(
+        Operation::SRE,
+        0x81,
+        |s| s.a = 0xFF,
+        |cpu, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0x40));
+            assert_eq!(cpu.state().a, 0xBF);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+            assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+        },
+    );
+}
+
+#[test]
+fn test_rra() {
+    for_each_mode(
+        Operation::RRA,
+        0x03,
+        |s| {
+            s.a = 0x10;
+            s.set_flag(Flag::CRY, true);
+        },
+        |cpu, mem, addr| {
+            // ROR leaves the carry set, which ADC adds in
+            assert_eq!(mem.read(&addr), Some(0x81));
+            assert_eq!(cpu.state().a, 0x92);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), false);
+        },
+    );
+}
+
+#[test]
+fn test_dcp() {
+    for_each_mode(
+        Operation::DCP,
+        0x43,
+        |s| s.a = 0x42,
+        |cpu, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0x42));
+            assert_eq!(cpu.state().a, 0x42);
+            assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+        },
+    );
+}
+
+#[test]
+fn test_isc() {
+    for_each_mode(
+        Operation::ISC,
+        0x41,
+        |s| {
+            s.a = 0x43;
+            s.set_flag(Flag::CRY, true);
+        },
+        |cpu, mem, addr| {
+            assert_eq!(mem.read(&addr), Some(0x42));
+            assert_eq!(cpu.state().a, 0x01);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+        },
+    );
+}
+
+#[test]
+fn test_sax() {
+    for_each_mode(
+        Operation::SAX,
+        0x00,
+        |s| s.a = 0x0F,
+        |cpu, mem, addr| {
+            // X is 4 in all modes
+            assert_eq!(mem.read(&addr), Some(0x04));
+            assert_eq!(cpu.state().a, 0x0F);
+        },
+    );
+}
+
+#[test]
+fn test_lax() {
+    for_each_mode(
+        Operation::LAX,
+        0x80,
+        |_| {},
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0x80);
+            assert_eq!(cpu.state().x, 0x80);
+            assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+        },
+    );
+}
+
+#[test]
+fn test_anc() {
+    for_each_mode(
+        Operation::ANC,
+        0x80,
+        |s| s.a = 0xFF,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0x80);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+        },
+    );
+}
+
+#[test]
+fn test_alr() {
+    for_each_mode(
+        Operation::ALR,
+        0x03,
+        |s| s.a = 0xFF,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0x01);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+        },
+    );
+}
+
+#[test]
+fn test_arr() {
+    for_each_mode(
+        Operation::ARR,
+        0xC0,
+        |s| {
+            s.a = 0xFF;
+            s.set_flag(Flag::CRY, true);
+        },
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0xE0);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+            assert_eq!(cpu.state().get_flag(Flag::OVF), false);
+            assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+        },
+    );
+    for_each_mode(
+        Operation::ARR,
+        0x40,
+        |s| s.a = 0xFF,
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0x20);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), false);
+            assert_eq!(cpu.state().get_flag(Flag::OVF), true);
+        },
+    );
+}
+
+#[test]
+fn test_arr_decimal() {
+    for_each_mode(
+        Operation::ARR,
+        0x66,
+        |s| {
+            s.a = 0xFF;
+            s.set_flag(Flag::DEC, true);
+        },
+        |cpu, _, _| {
+            assert_eq!(cpu.state().a, 0x99);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+            assert_eq!(cpu.state().get_flag(Flag::OVF), true);
+            assert_eq!(cpu.state().get_flag(Flag::NEG), false);
+        },
+    );
+}
+
+#[test]
+fn test_sbx() {
+    for_each_mode(
+        Operation::SBX,
+        0x10,
+        |s| {
+            s.a = 0xF0;
+            s.x = 0x3C;
+        },
+        |cpu, _, _| {
+            assert_eq!(cpu.state().x, 0x20);
+            assert_eq!(cpu.state().a, 0xF0);
+            assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+        },
+    );
+}
+
+#[test]
+fn test_las() {
+    // LAS $1230,Y
+    let (mut cpu, mut mem) = setup(&[0xBB, 0x30, 0x12]);
+    cpu.state_mut().y = 4;
+    cpu.state_mut().sp = 0x3F;
+    mem.write(0x1234, 0xF0);
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().a, 0x30);
+    assert_eq!(cpu.state().x, 0x30);
+    assert_eq!(cpu.state().sp, 0x30);
+}
+
+#[test]
+fn test_xaa_magic_constant() {
+    for (magic, expected) in [(0xEE, 0x0E), (0xFF, 0x0F), (0x00, 0x00)] {
+        // XAA #$0F
+        let (mut cpu, mut mem) = setup(&[0x8B, 0x0F]);
+        cpu.unstable.xaa_magic = magic;
+        cpu.state_mut().x = 0xFF;
+
+        run_instruction(&mut cpu, &mut mem);
+
+        assert_eq!(cpu.state().a, expected, "magic {:02X}", magic);
+    }
+}
+
+#[test]
+fn test_lxa_magic_constant() {
+    for (magic, expected) in [(0xEE, 0x0E), (0xFF, 0x0F), (0x00, 0x00)] {
+        // LXA #$0F
+        let (mut cpu, mut mem) = setup(&[0xAB, 0x0F]);
+        cpu.unstable.lxa_magic = magic;
+
+        run_instruction(&mut cpu, &mut mem);
+
+        assert_eq!(cpu.state().a, expected, "magic {:02X}", magic);
+        assert_eq!(cpu.state().x, expected, "magic {:02X}", magic);
+    }
+}
+
+#[test]
+fn test_sh_stores_and_high_byte() {
+    // SHA $1230,Y ; SHX $1230,Y ; SHY $1230,X ; TAS $1230,Y
+    for (opcode, index_x) in [(0x9F, false), (0x9E, false), (0x9C, true), (0x9B, false)] {
+        for and_high_byte in [true, false] {
+            let (mut cpu, mut mem) = setup(&[opcode, 0x30, 0x12]);
+            cpu.unstable.and_high_byte = and_high_byte;
+            let s = cpu.state_mut();
+            s.a = 0xFF;
+            s.x = if index_x { 4 } else { 0xFF };
+            s.y = if index_x { 0xFF } else { 4 };
+
+            run_instruction(&mut cpu, &mut mem);
+
+            let expected = if and_high_byte { 0x13 } else { 0xFF };
+            assert_eq!(mem.read(&0x1234), Some(expected), "opcode {:02X}", opcode);
+        }
+    }
+}
+
+#[test]
+fn test_sh_page_cross_corrupts_address() {
+    // SHX $12FF,Y
+    let (mut cpu, mut mem) = setup(&[0x9E, 0xFF, 0x12]);
+    cpu.state_mut().x = 0x05;
+    cpu.state_mut().y = 1;
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(mem.read(&0x0100), Some(0x01));
+    assert_eq!(mem.read(&0x1300), None);
+}
+
+#[test]
+fn test_tas() {
+    // TAS $1230,Y
+    let (mut cpu, mut mem) = setup(&[0x9B, 0x30, 0x12]);
+    cpu.state_mut().a = 0xF3;
+    cpu.state_mut().x = 0x3F;
+    cpu.state_mut().y = 4;
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().sp, 0x33);
+    assert_eq!(mem.read(&0x1234), Some(0x13));
+}
+
+#[test]
+fn test_undocumented_nops() {
+    for (opcode, mode) in opcodes_for(Operation::NOP) {
+        let (mut cpu, mut mem) = setup(&[opcode, 0x34, 0x12]);
+        let sr = cpu.state().sr();
+
+        run_instruction(&mut cpu, &mut mem);
+
+        let expected = PROGRAM_START
+            + match mode {
+                AddressingMode::IMP => 1,
+                AddressingMode::ABS | AddressingMode::ABX => 3,
+                _ => 2,
+            };
+        assert_eq!(cpu.state().pc, expected, "opcode {:02X}", opcode);
+        assert_eq!(cpu.state().a, 0);
+        assert_eq!(cpu.state().sr(), sr);
+    }
+}
+
+#[test]
+fn test_jam_halts_cpu() {
+    let (mut cpu, mut mem) = setup_interrupts();
+    mem.write(PROGRAM_START, 0x02);
+    cpu.irq = true;
+    cpu.nmi = true;
+
+    for _ in 0..20 {
+        run_cycle(&mut cpu, &mut mem);
+    }
+
+    assert_eq!(cpu.is_halted(), true);
+    assert_eq!(cpu.addr_bus, 0xFFFF);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 1);
+
+    cpu.res = true;
+    run_cycle(&mut cpu, &mut mem);
+    cpu.res = false;
+    cpu.irq = false;
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.is_halted(), false);
+    assert_eq!(cpu.state().pc, 0x5000);
+}
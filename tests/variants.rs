@@ -0,0 +1,365 @@
+#![allow(clippy::bool_assert_comparison)]
+
+mod common;
+
+use common::*;
+use luvemix_rust::cpu::*;
+use luvemix_rust::memory::*;
+
+#[test]
+fn test_variant_decode() {
+    for opcode in 0..=0xFF {
+        let nmos = decode(opcode).or_else(|| decode_undocumented(opcode));
+        assert_eq!(Some(Variant::NMOS.decode(opcode)), nmos);
+        assert_eq!(Some(Variant::RP2A03.decode(opcode)), nmos);
+        if let Some(i) = decode(opcode) {
+            // the 65C02 keeps all documented NMOS opcodes
+            assert_eq!(Variant::WDC.decode(opcode), i, "opcode {:02X}", opcode);
+        }
+    }
+    let op = |variant: Variant, opcode| variant.decode(opcode).op;
+    assert_eq!(op(Variant::CMOS, 0x07), Operation::NOP);
+    assert_eq!(op(Variant::ROCKWELL, 0x07), Operation::RMB);
+    assert_eq!(op(Variant::ROCKWELL, 0xCB), Operation::NOP);
+    assert_eq!(op(Variant::WDC, 0xCB), Operation::WAI);
+    assert_eq!(op(Variant::WDC, 0xDB), Operation::STP);
+    assert_eq!(op(Variant::WDC, 0xFF), Operation::BBS);
+}
+
+#[test]
+fn test_65c02_opcode_cycles() {
+    #[rustfmt::skip]
+    let cycles: [usize; 256] = [
+        7, 6, 2, 1, 5, 3, 5, 5, 3, 2, 2, 1, 6, 4, 6, 5,
+        2, 5, 5, 1, 5, 4, 6, 5, 2, 4, 2, 1, 6, 4, 6, 5,
+        6, 6, 2, 1, 3, 3, 5, 5, 4, 2, 2, 1, 4, 4, 6, 5,
+        2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 2, 1, 4, 4, 6, 5,
+        6, 6, 2, 1, 3, 3, 5, 5, 3, 2, 2, 1, 3, 4, 6, 5,
+        2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 3, 1, 8, 4, 6, 5,
+        6, 6, 2, 1, 3, 3, 5, 5, 4, 2, 2, 1, 6, 4, 6, 5,
+        2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 4, 1, 6, 4, 6, 5,
+        3, 6, 2, 1, 3, 3, 3, 5, 2, 2, 2, 1, 4, 4, 4, 5,
+        2, 6, 5, 1, 4, 4, 4, 5, 2, 5, 2, 1, 4, 5, 5, 5,
+        2, 6, 2, 1, 3, 3, 3, 5, 2, 2, 2, 1, 4, 4, 4, 5,
+        2, 5, 5, 1, 4, 4, 4, 5, 2, 4, 2, 1, 4, 4, 4, 5,
+        2, 6, 2, 1, 3, 3, 5, 5, 2, 2, 2, 0, 4, 4, 6, 5,
+        2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 3, 0, 4, 4, 7, 5,
+        2, 6, 2, 1, 3, 3, 5, 5, 2, 2, 2, 1, 4, 4, 6, 5,
+        2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 4, 1, 4, 4, 7, 5,
+    ];
+    for opcode in 0..=0xFF {
+        if cycles[opcode as usize] == 0 {
+            // WAI and STP never finish by themselves
+            continue;
+        }
+        let (mut cpu, mut mem) = setup_variant(Variant::WDC, &[opcode]);
+        match Variant::WDC.decode(opcode).op {
+            // branches not taken
+            Operation::BBR => mem.write(0x0000, 0xFF),
+            _ if opcode & 0x1F == 0x10 => {
+                let taken_when_set = opcode & 0x20 != 0;
+                cpu.state_mut()
+                    .set_sr(if taken_when_set { 0x00 } else { 0xFF });
+            }
+            _ => {}
+        }
+
+        let taken = run_instruction(&mut cpu, &mut mem);
+
+        assert_eq!(taken, cycles[opcode as usize], "opcode {:02X}", opcode);
+    }
+}
+
+#[test]
+fn test_65c02_without_bit_instructions() {
+    // RMB0 $10 ; BBR0 $10,+0 ; WAI
+    for opcode in [0x07, 0x0F, 0xCB] {
+        let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[opcode]);
+
+        assert_eq!(run_instruction(&mut cpu, &mut mem), 1);
+        assert_eq!(cpu.state().pc, PROGRAM_START + 1);
+    }
+}
+
+#[test]
+fn test_65c02_jmp_indirect_fixed() {
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[0x6C, 0xFF, 0x30]);
+    mem.write(0x30FF, 0x34);
+    mem.write(0x3000, 0x56);
+    mem.write(0x3100, 0x12);
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().pc, 0x1234);
+}
+
+#[test]
+fn test_jmp_indexed_indirect() {
+    // JMP ($30FE,X)
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[0x7C, 0xFE, 0x30]);
+    cpu.state_mut().x = 2;
+    load(&mut mem, 0x3100, &[0x34, 0x12]);
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().pc, 0x1234);
+}
+
+#[test]
+fn test_zero_page_indirect() {
+    // LDA ($FF) ; STA ($20)
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[0xB2, 0xFF, 0x92, 0x20]);
+    mem.write(0x00FF, 0x34);
+    mem.write(0x0000, 0x12);
+    mem.write(0x1234, 0x42);
+    load(&mut mem, 0x0020, &[0x00, 0x30]);
+
+    run_instruction(&mut cpu, &mut mem);
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().a, 0x42);
+    assert_eq!(mem.read(&0x3000), Some(0x42));
+}
+
+#[test]
+fn test_bra() {
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[0x80, 0x10]);
+    cpu.state_mut().set_sr(0xFF);
+
+    assert_eq!(run_instruction(&mut cpu, &mut mem), 3);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 0x12);
+}
+
+#[test]
+fn test_phx_phy_plx_ply() {
+    // PHX ; PHY ; PLX ; PLY
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[0xDA, 0x5A, 0xFA, 0x7A]);
+    cpu.state_mut().x = 0x12;
+    cpu.state_mut().y = 0x80;
+
+    run_instruction(&mut cpu, &mut mem);
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(mem.read(&0x01FF), Some(0x12));
+    assert_eq!(mem.read(&0x01FE), Some(0x80));
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().x, 0x80);
+    assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().y, 0x12);
+    assert_eq!(cpu.state().get_flag(Flag::NEG), false);
+    assert_eq!(cpu.state().sp, 0xFF);
+}
+
+#[test]
+fn test_stz() {
+    // STZ $10 ; STZ $10,X ; STZ $1234 ; STZ $1230,X
+    let program = [0x64, 0x10, 0x74, 0x10, 0x9C, 0x34, 0x12, 0x9E, 0x30, 0x12];
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &program);
+    cpu.state_mut().a = 0xFF;
+    cpu.state_mut().x = 4;
+    for addr in [0x0010, 0x0014, 0x1234, 0x1234] {
+        mem.write(addr, 0xFF);
+
+        run_instruction(&mut cpu, &mut mem);
+
+        assert_eq!(mem.read(&addr), Some(0x00));
+    }
+}
+
+#[test]
+fn test_trb_tsb() {
+    // TSB $10 ; TRB $10
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[0x04, 0x10, 0x14, 0x10]);
+    cpu.state_mut().a = 0x0F;
+    mem.write(0x0010, 0x30);
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(mem.read(&0x0010), Some(0x3F));
+    assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(mem.read(&0x0010), Some(0x30));
+    assert_eq!(cpu.state().get_flag(Flag::ZRO), false);
+}
+
+#[test]
+fn test_rmb_smb() {
+    // SMB5 $10 ; RMB0 $10
+    let (mut cpu, mut mem) = setup_variant(Variant::ROCKWELL, &[0xD7, 0x10, 0x07, 0x10]);
+    mem.write(0x0010, 0x01);
+    let sr = cpu.state().sr();
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(mem.read(&0x0010), Some(0x21));
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(mem.read(&0x0010), Some(0x20));
+    assert_eq!(cpu.state().sr(), sr);
+}
+
+#[test]
+fn test_bbr_bbs() {
+    // BBR3 $10,+$10 ; BBS3 $10,+$10
+    for (opcode, val, taken) in [(0x3F, 0x00, true), (0x3F, 0x08, false), (0xBF, 0x08, true)] {
+        let (mut cpu, mut mem) = setup_variant(Variant::ROCKWELL, &[opcode, 0x10, 0x10]);
+        mem.write(0x0010, val);
+
+        let cycles = run_instruction(&mut cpu, &mut mem);
+
+        let (pc, expected) = if taken { (0x0213, 6) } else { (0x0203, 5) };
+        assert_eq!(cpu.state().pc, pc, "opcode {:02X}, val {:02X}", opcode, val);
+        assert_eq!(cycles, expected);
+    }
+}
+
+#[test]
+fn test_inc_dec_accumulator() {
+    // INC A ; DEC A ; DEC A
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[0x1A, 0x3A, 0x3A]);
+    cpu.state_mut().a = 0xFF;
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0x00);
+    assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+
+    run_instruction(&mut cpu, &mut mem);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0xFE);
+    assert_eq!(cpu.state().get_flag(Flag::NEG), true);
+}
+
+#[test]
+fn test_bit_immediate_only_sets_zero() {
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[0x89, 0xC0]);
+    cpu.state_mut().a = 0x01;
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+    assert_eq!(cpu.state().get_flag(Flag::NEG), false);
+    assert_eq!(cpu.state().get_flag(Flag::OVF), false);
+}
+
+#[test]
+fn test_65c02_decimal_extra_cycle() {
+    // ADC #$01 with decimal flag set
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[0x69, 0x01]);
+    cpu.state_mut().a = 0x99;
+    cpu.state_mut().set_flag(Flag::DEC, true);
+
+    assert_eq!(run_instruction(&mut cpu, &mut mem), 3);
+    assert_eq!(cpu.state().a, 0x00);
+    assert_eq!(cpu.state().get_flag(Flag::ZRO), true);
+    assert_eq!(cpu.state().get_flag(Flag::CRY), true);
+}
+
+#[test]
+fn test_2a03_ignores_decimal_flag() {
+    // ADC #$01 ; SBC #$01
+    let (mut cpu, mut mem) = setup_variant(Variant::RP2A03, &[0x69, 0x01, 0xE9, 0x01]);
+    cpu.state_mut().a = 0x09;
+    cpu.state_mut().set_flag(Flag::DEC, true);
+
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0x0A);
+
+    cpu.state_mut().set_flag(Flag::CRY, true);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().a, 0x09);
+    assert_eq!(cpu.state().get_flag(Flag::DEC), true);
+}
+
+#[test]
+fn test_65c02_bus_modify_reads_twice() {
+    // INC $1234
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[0xEE, 0x34, 0x12]);
+    mem.write(0x1234, 0x41);
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(
+        trace[3..],
+        [
+            (0x1234, BusMode::READ, 0x41),
+            (0x1234, BusMode::READ, 0x41),
+            (0x1234, BusMode::WRITE, 0x42),
+        ]
+    );
+}
+
+#[test]
+fn test_65c02_bus_page_cross_reads_operand() {
+    // LDA $12FF,X
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[0xBD, 0xFF, 0x12]);
+    cpu.state_mut().x = 1;
+
+    let trace = trace_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(trace[3], (0x0202, BusMode::READ, 0x12));
+    assert_eq!(trace[4].0, 0x1300);
+}
+
+#[test]
+fn test_65c02_brk_clears_decimal() {
+    let (mut cpu, mut mem) = setup_variant(Variant::CMOS, &[0x00]);
+    cpu.state_mut().set_flag(Flag::DEC, true);
+
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().get_flag(Flag::DEC), false);
+    assert_eq!(
+        mem.read(&0x01FD).map(|p| p & Flag::DEC.to_mask()),
+        Some(Flag::DEC.to_mask())
+    );
+}
+
+#[test]
+fn test_wai() {
+    // WAI ; NOP
+    let (mut cpu, mut mem) = setup_variant(Variant::WDC, &[0xCB, 0xEA]);
+    load(&mut mem, 0xFFFE, &[0x00, 0x30]);
+    for _ in 0..10 {
+        run_cycle(&mut cpu, &mut mem);
+    }
+    assert_eq!(cpu.is_waiting(), true);
+
+    cpu.irq = true;
+    run_instruction(&mut cpu, &mut mem);
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.is_waiting(), false);
+    assert_eq!(cpu.state().pc, 0x3000);
+}
+
+#[test]
+fn test_wai_with_interrupts_disabled() {
+    // WAI ; NOP
+    let (mut cpu, mut mem) = setup_variant(Variant::WDC, &[0xCB, 0xEA]);
+    cpu.state_mut().set_flag(Flag::INT, true);
+    for _ in 0..10 {
+        run_cycle(&mut cpu, &mut mem);
+    }
+
+    cpu.irq = true;
+    run_instruction(&mut cpu, &mut mem);
+
+    assert_eq!(cpu.state().pc, PROGRAM_START + 1);
+    run_instruction(&mut cpu, &mut mem);
+    assert_eq!(cpu.state().pc, PROGRAM_START + 2);
+}
+
+#[test]
+fn test_stp() {
+    let (mut cpu, mut mem) = setup_variant(Variant::WDC, &[0xDB]);
+    cpu.irq = true;
+    for _ in 0..10 {
+        run_cycle(&mut cpu, &mut mem);
+    }
+
+    assert_eq!(cpu.is_halted(), true);
+}